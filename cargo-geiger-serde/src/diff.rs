@@ -0,0 +1,353 @@
+use crate::{
+    Count, CounterBlock, PackageId, ReportEntry, SafetyReport, Source,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Differences between two reports, e.g. of two versions of a project
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ReportDiff {
+    /// Packages only found in the new report, sorted by package id
+    pub added_packages: Vec<ReportEntry>,
+    /// Packages only found in the old report, sorted by package id
+    pub removed_packages: Vec<ReportEntry>,
+    /// Packages found in both reports, possibly with different versions, that
+    /// changed in some way, sorted by the new package id
+    pub changed_packages: Vec<PackageDiff>,
+}
+
+impl ReportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_packages.is_empty()
+            && self.removed_packages.is_empty()
+            && self.changed_packages.is_empty()
+    }
+}
+
+/// Changes of a package found in both reports
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PackageDiff {
+    pub old_id: PackageId,
+    pub new_id: PackageId,
+    /// Change of the statistics for code used by the project
+    pub used: CounterBlockDiff,
+    /// Change of the statistics for code not used by the project
+    pub unused: CounterBlockDiff,
+    pub old_forbids_unsafe: bool,
+    pub new_forbids_unsafe: bool,
+}
+
+impl PackageDiff {
+    /// The version changed, or the commit a git source was resolved to.
+    pub fn is_version_change(&self) -> bool {
+        self.old_id != self.new_id
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.is_version_change()
+            && self.used.is_empty()
+            && self.unused.is_empty()
+            && self.old_forbids_unsafe == self.new_forbids_unsafe
+    }
+}
+
+/// Change of a `Count`, negative if the count went down
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CountDiff {
+    pub safe: i64,
+    pub unsafe_: i64,
+}
+
+impl CountDiff {
+    pub fn new(old: &Count, new: &Count) -> Self {
+        CountDiff {
+            safe: new.safe as i64 - old.safe as i64,
+            unsafe_: new.unsafe_ as i64 - old.unsafe_ as i64,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.safe == 0 && self.unsafe_ == 0
+    }
+}
+
+/// Change of every field of a `CounterBlock`
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CounterBlockDiff {
+    pub functions: CountDiff,
+    pub exprs: CountDiff,
+    pub item_impls: CountDiff,
+    pub item_traits: CountDiff,
+    pub methods: CountDiff,
+    pub macros: CountDiff,
+}
+
+impl CounterBlockDiff {
+    pub fn new(old: &CounterBlock, new: &CounterBlock) -> Self {
+        CounterBlockDiff {
+            functions: CountDiff::new(&old.functions, &new.functions),
+            exprs: CountDiff::new(&old.exprs, &new.exprs),
+            item_impls: CountDiff::new(&old.item_impls, &new.item_impls),
+            item_traits: CountDiff::new(&old.item_traits, &new.item_traits),
+            methods: CountDiff::new(&old.methods, &new.methods),
+            macros: CountDiff::new(&old.macros, &new.macros),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+            && self.exprs.is_empty()
+            && self.item_impls.is_empty()
+            && self.item_traits.is_empty()
+            && self.methods.is_empty()
+            && self.macros.is_empty()
+    }
+}
+
+/// Compares two reports. Packages with the same id, apart from the commit a
+/// git source was resolved to, are compared with each other, as are packages
+/// with a name that occurs exactly once in both reports. This makes a version
+/// bump or a git dependency moving to another commit show up as a change of a
+/// package instead of a removed and an added package.
+pub fn diff_reports(old: &SafetyReport, new: &SafetyReport) -> ReportDiff {
+    let mut new_by_key = new
+        .packages
+        .iter()
+        .map(|(id, entry)| (pairing_key(id), entry))
+        .collect::<HashMap<_, _>>();
+    let mut pairs = Vec::new();
+    let mut old_only = Vec::new();
+    for (id, old_entry) in &old.packages {
+        match new_by_key.remove(&pairing_key(id)) {
+            Some(new_entry) => pairs.push((old_entry, new_entry)),
+            None => old_only.push(old_entry),
+        }
+    }
+    let mut new_only = new_by_key.into_values().collect::<Vec<_>>();
+
+    let old_name_counts = name_counts(&old.packages);
+    let new_name_counts = name_counts(&new.packages);
+    let is_unique_name = |name: &str| {
+        old_name_counts.get(name) == Some(&1)
+            && new_name_counts.get(name) == Some(&1)
+    };
+    let mut removed_packages = Vec::new();
+    for old_entry in old_only {
+        let name = old_entry.package.id.name.as_str();
+        let new_index = new_only
+            .iter()
+            .position(|new_entry| new_entry.package.id.name == name)
+            .filter(|_| is_unique_name(name));
+        match new_index {
+            Some(i) => pairs.push((old_entry, new_only.remove(i))),
+            None => removed_packages.push(old_entry.clone()),
+        }
+    }
+    let mut added_packages = new_only.into_iter().cloned().collect::<Vec<_>>();
+
+    let mut changed_packages = pairs
+        .into_iter()
+        .map(|(old_entry, new_entry)| PackageDiff {
+            old_id: old_entry.package.id.clone(),
+            new_id: new_entry.package.id.clone(),
+            used: CounterBlockDiff::new(
+                &old_entry.unsafety.used,
+                &new_entry.unsafety.used,
+            ),
+            unused: CounterBlockDiff::new(
+                &old_entry.unsafety.unused,
+                &new_entry.unsafety.unused,
+            ),
+            old_forbids_unsafe: old_entry.unsafety.forbids_unsafe,
+            new_forbids_unsafe: new_entry.unsafety.forbids_unsafe,
+        })
+        .filter(|package_diff| !package_diff.is_empty())
+        .collect::<Vec<_>>();
+
+    added_packages.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+    removed_packages.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+    changed_packages.sort_by(|a, b| a.new_id.cmp(&b.new_id));
+    ReportDiff {
+        added_packages,
+        removed_packages,
+        changed_packages,
+    }
+}
+
+/// The package id without the commit a git source was resolved to.
+fn pairing_key(id: &PackageId) -> PackageId {
+    let mut key = id.clone();
+    if let Source::Git { precise, .. } = &mut key.source {
+        *precise = None;
+    }
+    key
+}
+
+fn name_counts(
+    packages: &HashMap<PackageId, ReportEntry>,
+) -> HashMap<&str, usize> {
+    let mut name_counts = HashMap::new();
+    for id in packages.keys() {
+        *name_counts.entry(id.name.as_str()).or_insert(0) += 1;
+    }
+    name_counts
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+
+    use crate::{GitReference, PackageInfo, UnsafeInfo};
+    use semver::Version;
+    use url::Url;
+
+    fn package_id(name: &str, version: &str) -> PackageId {
+        PackageId {
+            name: String::from(name),
+            version: Version::parse(version).unwrap(),
+            source: Source::Registry {
+                name: String::from("crates.io"),
+                url: Url::parse("https://github.com/rust-lang/crates.io-index")
+                    .unwrap(),
+            },
+        }
+    }
+
+    fn report(entries: &[(&str, &str, u64)]) -> SafetyReport {
+        let mut report = SafetyReport::default();
+        for (name, version, unsafe_exprs) in entries {
+            let id = package_id(name, version);
+            let mut unsafety = UnsafeInfo::default();
+            unsafety.used.exprs.unsafe_ = *unsafe_exprs;
+            report.packages.insert(
+                id.clone(),
+                ReportEntry {
+                    package: PackageInfo::new(id),
+                    unsafety,
+                    files: None,
+                },
+            );
+        }
+        report
+    }
+
+    fn ids(entries: &[ReportEntry]) -> Vec<PackageId> {
+        entries
+            .iter()
+            .map(|entry| entry.package.id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn diff_reports_pairs_versions_of_unique_names() {
+        let old = report(&[("foo", "1.0.0", 1), ("same", "1.0.0", 0)]);
+        let new = report(&[("foo", "1.1.0", 3), ("same", "1.0.0", 0)]);
+
+        let diff = diff_reports(&old, &new);
+
+        assert!(diff.added_packages.is_empty());
+        assert!(diff.removed_packages.is_empty());
+        assert_eq!(diff.changed_packages.len(), 1);
+        let package_diff = &diff.changed_packages[0];
+        assert_eq!(package_diff.old_id, package_id("foo", "1.0.0"));
+        assert_eq!(package_diff.new_id, package_id("foo", "1.1.0"));
+        assert!(package_diff.is_version_change());
+        assert_eq!(
+            package_diff.used.exprs,
+            CountDiff {
+                safe: 0,
+                unsafe_: 2
+            }
+        );
+    }
+
+    #[test]
+    fn diff_reports_pairs_git_sources_at_other_commits() {
+        let git_package_id = |precise: &str| PackageId {
+            name: String::from("foo"),
+            version: Version::parse("1.0.0").unwrap(),
+            source: Source::Git {
+                url: Url::parse("https://github.com/foo/foo").unwrap(),
+                reference: GitReference::DefaultBranch,
+                precise: Some(String::from(precise)),
+            },
+        };
+        let git_report = |precise: &str| {
+            let id = git_package_id(precise);
+            let mut report = SafetyReport::default();
+            report.packages.insert(
+                id.clone(),
+                ReportEntry {
+                    package: PackageInfo::new(id),
+                    unsafety: UnsafeInfo::default(),
+                    files: None,
+                },
+            );
+            report
+        };
+        // Same name twice, so only the pairing by id applies.
+        let mut old = git_report("0123abcd");
+        old.packages.extend(report(&[("foo", "2.0.0", 0)]).packages);
+        let mut new = git_report("4567ef01");
+        new.packages.extend(report(&[("foo", "2.0.0", 0)]).packages);
+
+        let diff = diff_reports(&old, &new);
+
+        assert!(diff.added_packages.is_empty());
+        assert!(diff.removed_packages.is_empty());
+        assert_eq!(diff.changed_packages.len(), 1);
+        let package_diff = &diff.changed_packages[0];
+        assert_eq!(package_diff.old_id, git_package_id("0123abcd"));
+        assert_eq!(package_diff.new_id, git_package_id("4567ef01"));
+        assert!(package_diff.is_version_change());
+    }
+
+    #[test]
+    fn diff_reports_does_not_pair_versions_of_repeated_names() {
+        let old = report(&[("foo", "1.0.0", 1), ("foo", "2.0.0", 1)]);
+        let new = report(&[("foo", "2.1.0", 1)]);
+
+        let diff = diff_reports(&old, &new);
+
+        assert_eq!(ids(&diff.added_packages), vec![package_id("foo", "2.1.0")]);
+        assert_eq!(
+            ids(&diff.removed_packages),
+            vec![package_id("foo", "1.0.0"), package_id("foo", "2.0.0")]
+        );
+        assert!(diff.changed_packages.is_empty());
+    }
+
+    #[test]
+    fn diff_reports_sorts_added_and_removed_packages() {
+        let old = report(&[
+            ("removed-b", "1.0.0", 0),
+            ("removed-a", "2.0.0", 0),
+            ("removed-a", "1.0.0", 0),
+        ]);
+        let new = report(&[
+            ("added-c", "1.0.0", 0),
+            ("added-a", "1.0.0", 0),
+            ("added-b", "1.0.0", 0),
+        ]);
+
+        let diff = diff_reports(&old, &new);
+
+        assert_eq!(
+            ids(&diff.added_packages),
+            vec![
+                package_id("added-a", "1.0.0"),
+                package_id("added-b", "1.0.0"),
+                package_id("added-c", "1.0.0")
+            ]
+        );
+        assert_eq!(
+            ids(&diff.removed_packages),
+            vec![
+                package_id("removed-a", "1.0.0"),
+                package_id("removed-a", "2.0.0"),
+                package_id("removed-b", "1.0.0")
+            ]
+        );
+        assert!(diff_reports(&new, &new).is_empty());
+    }
+}
@@ -6,13 +6,21 @@
 #![forbid(unsafe_code)]
 #![forbid(warnings)]
 
+mod diff;
 mod package_id;
 mod report;
+mod schema;
 mod source;
 
+pub use diff::{
+    diff_reports, CountDiff, CounterBlockDiff, PackageDiff, ReportDiff,
+};
 pub use package_id::PackageId;
 pub use report::{
-    Count, CounterBlock, DependencyKind, PackageInfo, QuickReportEntry,
-    QuickSafetyReport, ReportEntry, SafetyReport, UnsafeInfo,
+    Count, CounterBlock, DependencyKind, FileMetrics, LineColumn, PackageInfo,
+    QuickReportEntry, QuickSafetyReport, ReportEntry, ReportMetadata,
+    SafetyReport, UnsafeFinding, UnsafeInfo, UnsafeKind, UnsafeLocation,
+    REPORT_SCHEMA_VERSION,
 };
-pub use source::Source;
+pub use schema::{quick_safety_report_schema, safety_report_schema};
+pub use source::{GitReference, Source};
@@ -1,4 +1,5 @@
 use crate::PackageId;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
@@ -6,8 +7,14 @@ use std::{
     path::PathBuf,
 };
 
+/// Version of the report schema written by this version of the crate, bumped
+/// whenever a field is added, removed or changes its meaning. Reports without
+/// a `schema_version` were written by cargo-geiger 0.10 or earlier and have
+/// version 0.
+pub const REPORT_SCHEMA_VERSION: u32 = 2;
+
 /// Package dependency information
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct PackageInfo {
     pub id: PackageId,
     #[serde(serialize_with = "set_serde::serialize")]
@@ -38,7 +45,7 @@ impl PackageInfo {
 }
 
 /// Entry of the report generated from scanning for packages that forbid the use of `unsafe`
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct QuickReportEntry {
     pub package: PackageInfo,
     /// Whether this package forbids the use of `unsafe`
@@ -46,28 +53,75 @@ pub struct QuickReportEntry {
 }
 
 /// Report generated from scanning for packages that forbid the use of `unsafe`
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct QuickSafetyReport {
+    /// Version of the schema of the report, see `REPORT_SCHEMA_VERSION`
+    #[serde(default)]
+    pub schema_version: u32,
+    /// How the report was produced, empty for reports written before the
+    /// metadata was added
+    #[serde(default)]
+    pub metadata: ReportMetadata,
     /// Packages that were scanned successfully
     #[serde(with = "entry_serde")]
+    #[schemars(with = "Vec<QuickReportEntry>")]
     pub packages: HashMap<PackageId, QuickReportEntry>,
     /// Packages that were not scanned successfully
     #[serde(serialize_with = "set_serde::serialize")]
     pub packages_without_metrics: HashSet<PackageId>,
 }
 
+impl Default for QuickSafetyReport {
+    fn default() -> Self {
+        QuickSafetyReport {
+            schema_version: REPORT_SCHEMA_VERSION,
+            metadata: Default::default(),
+            packages: Default::default(),
+            packages_without_metrics: Default::default(),
+        }
+    }
+}
+
 /// Entry of the report generated from scanning for the use of `unsafe`
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct ReportEntry {
     pub package: PackageInfo,
     /// Unsafety scan results
     pub unsafety: UnsafeInfo,
+    /// Scan results of every source file of the package, sorted by path. Only
+    /// included when requested with `--per-file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileMetrics>>,
+}
+
+/// Unsafety usage in a single source file of a package
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct FileMetrics {
+    /// Path of the file, relative to the package root unless the file is
+    /// outside of it
+    pub path: PathBuf,
+    /// Whether the file is the entry point of a target, e.g. `src/lib.rs`
+    pub is_entry_point: bool,
+    /// Whether the file is used by the build
+    pub used: bool,
+    /// Unsafe usage statistics of the file
+    pub counters: CounterBlock,
+    /// Whether the file declares `#![forbid(unsafe_code)]`
+    pub forbids_unsafe: bool,
 }
 
 /// Report generated from scanning for the use of `unsafe`
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct SafetyReport {
+    /// Version of the schema of the report, see `REPORT_SCHEMA_VERSION`
+    #[serde(default)]
+    pub schema_version: u32,
+    /// How the report was produced, empty for reports written before the
+    /// metadata was added
+    #[serde(default)]
+    pub metadata: ReportMetadata,
     #[serde(with = "entry_serde")]
+    #[schemars(with = "Vec<ReportEntry>")]
     pub packages: HashMap<PackageId, ReportEntry>,
     #[serde(serialize_with = "set_serde::serialize")]
     pub packages_without_metrics: HashSet<PackageId>,
@@ -75,8 +129,65 @@ pub struct SafetyReport {
     pub used_but_not_scanned_files: HashSet<PathBuf>,
 }
 
+impl Default for SafetyReport {
+    fn default() -> Self {
+        SafetyReport {
+            schema_version: REPORT_SCHEMA_VERSION,
+            metadata: Default::default(),
+            packages: Default::default(),
+            packages_without_metrics: Default::default(),
+            used_but_not_scanned_files: Default::default(),
+        }
+    }
+}
+
+/// The context of a scan, to tell which settings and toolchain produced a
+/// report
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct ReportMetadata {
+    /// Version of the report format, the same as the `schema_version` of the
+    /// report
+    pub format_version: u32,
+    /// Version of cargo-geiger that wrote the report
+    pub geiger_version: String,
+    /// Version of rustc, e.g. `1.47.0`
+    pub rustc_version: String,
+    /// Target triple the scan was done for, the host if no target was given
+    pub target: String,
+    /// Whether the dependencies of all targets were included
+    pub all_targets: bool,
+    /// The cfgs of the target, `None` if cfgs were not evaluated, e.g. for
+    /// `--forbid-only`
+    pub target_cfgs: Option<Vec<String>>,
+    /// Features activated with `--features`
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    /// Whether unsafe usage in tests was counted
+    pub include_tests: bool,
+}
+
+impl Default for ReportMetadata {
+    fn default() -> Self {
+        ReportMetadata {
+            format_version: REPORT_SCHEMA_VERSION,
+            geiger_version: Default::default(),
+            rustc_version: Default::default(),
+            target: Default::default(),
+            all_targets: Default::default(),
+            target_cfgs: Default::default(),
+            features: Default::default(),
+            all_features: Default::default(),
+            no_default_features: Default::default(),
+            include_tests: Default::default(),
+        }
+    }
+}
+
 /// Unsafety usage in a package
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(
+    Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize,
+)]
 pub struct UnsafeInfo {
     /// Unsafe usage statistics for code used by the project
     pub used: CounterBlock,
@@ -88,10 +199,73 @@ pub struct UnsafeInfo {
     pub declared_unsafe_functions: Vec<String>,
     /// Names of safe functions that contains unsafe blocks
     pub contains_unsafe_functions: Vec<String>,
+    /// Source locations of every unsafe item found in the package, sorted by
+    /// file and position
+    #[serde(default)]
+    pub unsafe_locations: Vec<UnsafeLocation>,
+}
+
+/// Kind of unsafe item, one for each counter in `CounterBlock`
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize,
+)]
+pub enum UnsafeKind {
+    Function,
+    Expression,
+    ItemImpl,
+    ItemTrait,
+    Method,
+    Macro,
+}
+
+/// Position in a source file, both line and column are 1-based
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    Hash,
+    JsonSchema,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Single unsafe item found while scanning a source file
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct UnsafeFinding {
+    pub kind: UnsafeKind,
+    /// Path of the innermost item containing the finding, relative to the
+    /// scanned file, e.g. `tests::Foo::bar`. Unsafe functions, methods, impls
+    /// and traits are their own innermost item.
+    pub item_path: String,
+    pub start: LineColumn,
+    pub end: LineColumn,
+}
+
+/// Unsafe item together with the file it was found in
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct UnsafeLocation {
+    /// Path of the file, relative to the package root unless the file is
+    /// outside of it
+    pub file: PathBuf,
+    /// Whether the file is used by the build
+    pub used: bool,
+    #[serde(flatten)]
+    pub finding: UnsafeFinding,
 }
 
 /// Kind of dependency for a package
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, JsonSchema, PartialEq, Serialize,
+)]
 pub enum DependencyKind {
     /// Dependency in the `[dependencies]` section of `Cargo.toml`
     Normal,
@@ -102,7 +276,9 @@ pub enum DependencyKind {
 }
 
 /// Statistics about the use of `unsafe`
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(
+    Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize,
+)]
 pub struct Count {
     /// Number of safe items
     pub safe: u64,
@@ -139,22 +315,31 @@ impl AddAssign for Count {
 }
 
 /// Unsafe usage metrics collection.
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(
+    Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize,
+)]
 pub struct CounterBlock {
     pub functions: Count,
     pub exprs: Count,
     pub item_impls: Count,
     pub item_traits: Count,
     pub methods: Count,
+    /// Macro invocations and `macro_rules!` definitions, counted as unsafe if
+    /// `unsafe` is found among their tokens
+    #[serde(default)]
+    pub macros: Count,
 }
 
 impl CounterBlock {
+    /// Macros are included, the `unsafe` found in their tokens may end up in
+    /// the expanded code.
     pub fn has_unsafe(&self) -> bool {
         self.functions.unsafe_ > 0
             || self.exprs.unsafe_ > 0
             || self.item_impls.unsafe_ > 0
             || self.item_traits.unsafe_ > 0
             || self.methods.unsafe_ > 0
+            || self.macros.unsafe_ > 0
     }
 }
 
@@ -168,6 +353,7 @@ impl Add for CounterBlock {
             item_impls: self.item_impls + other.item_impls,
             item_traits: self.item_traits + other.item_traits,
             methods: self.methods + other.methods,
+            macros: self.macros + other.macros,
         }
     }
 }
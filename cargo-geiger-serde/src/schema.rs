@@ -0,0 +1,136 @@
+//! JSON Schema of the reports, and reading of reports written with an older
+//! version of the schema.
+
+use crate::{
+    QuickSafetyReport, ReportMetadata, SafetyReport, REPORT_SCHEMA_VERSION,
+};
+use schemars::{schema::RootSchema, schema_for};
+use serde::de::{DeserializeOwned, Error};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// Migrations of a report to the next schema version, the one at index `n`
+/// migrates reports of version `n`
+const MIGRATIONS: [fn(&mut Map<String, Value>);
+    REPORT_SCHEMA_VERSION as usize] = [migrate_from_0, migrate_from_1];
+
+/// JSON Schema of `SafetyReport`, the report of a full scan
+pub fn safety_report_schema() -> RootSchema {
+    schema_for!(SafetyReport)
+}
+
+/// JSON Schema of `QuickSafetyReport`, the report of a `--forbid-only` scan
+pub fn quick_safety_report_schema() -> RootSchema {
+    schema_for!(QuickSafetyReport)
+}
+
+impl SafetyReport {
+    /// Reads a JSON report of the current or any earlier schema version,
+    /// older reports are migrated to the current version
+    pub fn from_json_slice(json: &[u8]) -> serde_json::Result<Self> {
+        from_json_slice(json)
+    }
+}
+
+impl QuickSafetyReport {
+    /// Reads a JSON report of the current or any earlier schema version,
+    /// older reports are migrated to the current version
+    pub fn from_json_slice(json: &[u8]) -> serde_json::Result<Self> {
+        from_json_slice(json)
+    }
+}
+
+fn from_json_slice<T: DeserializeOwned>(json: &[u8]) -> serde_json::Result<T> {
+    let mut report = match serde_json::from_slice(json)? {
+        Value::Object(report) => report,
+        _ => return Err(Error::custom("expected the report to be an object")),
+    };
+    let schema_version = match report.get("schema_version") {
+        Some(schema_version) => u32::deserialize(schema_version)?,
+        None => 0,
+    };
+    if schema_version > REPORT_SCHEMA_VERSION {
+        return Err(Error::custom(format!(
+            "the report has schema version {}, only versions up to {} are \
+             supported",
+            schema_version, REPORT_SCHEMA_VERSION
+        )));
+    }
+    for migration in &MIGRATIONS[schema_version as usize..] {
+        migration(&mut report);
+    }
+    report.insert(
+        String::from("schema_version"),
+        Value::from(REPORT_SCHEMA_VERSION),
+    );
+    if let Some(Value::Object(metadata)) = report.get_mut("metadata") {
+        metadata.insert(
+            String::from("format_version"),
+            Value::from(REPORT_SCHEMA_VERSION),
+        );
+    }
+    serde_json::from_value(Value::Object(report))
+}
+
+/// Reports of cargo-geiger 0.10 and earlier have no metadata, macro counts
+/// and unsafe locations
+fn migrate_from_0(report: &mut Map<String, Value>) {
+    report
+        .entry("metadata")
+        .or_insert_with(|| json!(ReportMetadata::default()));
+    let unsafety_objects = report
+        .get_mut("packages")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.get_mut("unsafety"))
+        .filter_map(Value::as_object_mut);
+    for unsafety in unsafety_objects {
+        unsafety
+            .entry("unsafe_locations")
+            .or_insert_with(|| json!([]));
+        for counter_block in &["used", "unused"] {
+            if let Some(counter_block) = unsafety
+                .get_mut(*counter_block)
+                .and_then(Value::as_object_mut)
+            {
+                counter_block
+                    .entry("macros")
+                    .or_insert_with(|| json!({ "safe": 0, "unsafe_": 0 }));
+            }
+        }
+    }
+}
+
+/// Git sources of schema version 1 only have the `rev` asked for, which is
+/// the only kind of reference that was supported
+fn migrate_from_1(report: &mut Map<String, Value>) {
+    for value in report.values_mut() {
+        migrate_git_sources(value);
+    }
+}
+
+fn migrate_git_sources(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Object(git)) = object.get_mut("Git") {
+                if let Some(rev) = git.remove("rev") {
+                    git.insert(
+                        String::from("reference"),
+                        json!({ "Rev": rev }),
+                    );
+                    git.insert(String::from("precise"), Value::Null);
+                }
+            }
+            for value in object.values_mut() {
+                migrate_git_sources(value);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                migrate_git_sources(value);
+            }
+        }
+        _ => {}
+    }
+}
@@ -1,12 +1,100 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 /// Source of a package (where it is fetched from)
 #[derive(
-    Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+    Clone,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    JsonSchema,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
 )]
 pub enum Source {
-    Git { url: Url, rev: String },
-    Registry { name: String, url: Url },
+    Git {
+        url: Url,
+        /// The branch, tag or revision asked for by the dependency
+        reference: GitReference,
+        /// The commit the dependency was resolved to, unknown for reports
+        /// written before schema version 2
+        precise: Option<String>,
+    },
+    /// Registry using the git protocol, named as in the `[registries]` of the
+    /// cargo config, or by its URL if it is not configured
+    Registry {
+        name: String,
+        url: Url,
+    },
     Path(Url),
+    /// Registry using the sparse protocol, named like `Registry`
+    SparseRegistry {
+        name: String,
+        url: Url,
+    },
+    /// Registry in a local directory, e.g. created by `cargo local-registry`
+    LocalRegistry(Url),
+    /// Directory of vendored packages, e.g. created by `cargo vendor`
+    Directory(Url),
+}
+
+/// What a git dependency asks for
+#[derive(
+    Clone,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    JsonSchema,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub enum GitReference {
+    Branch(String),
+    Tag(String),
+    Rev(String),
+    /// The `HEAD` of the repository, if no branch, tag or revision is given
+    DefaultBranch,
+}
+
+#[cfg(test)]
+mod source_tests {
+    use super::*;
+
+    fn git_source(reference: GitReference, precise: Option<&str>) -> Source {
+        Source::Git {
+            url: Url::parse("https://github.com/foo/bar").unwrap(),
+            reference,
+            precise: precise.map(String::from),
+        }
+    }
+
+    #[test]
+    fn git_source_equality_includes_precise() {
+        assert_ne!(
+            git_source(GitReference::DefaultBranch, Some("0123abcd")),
+            git_source(GitReference::DefaultBranch, Some("4567ef01"))
+        );
+        assert_ne!(
+            git_source(GitReference::DefaultBranch, None),
+            git_source(GitReference::DefaultBranch, Some("0123abcd"))
+        );
+    }
+
+    #[test]
+    fn git_source_equality_includes_reference() {
+        assert_ne!(
+            git_source(GitReference::DefaultBranch, Some("0123abcd")),
+            git_source(
+                GitReference::Branch(String::from("main")),
+                Some("0123abcd")
+            )
+        );
+    }
 }
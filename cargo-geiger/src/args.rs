@@ -1,5 +1,6 @@
 use crate::format::print_config::OutputFormat;
 use crate::format::Charset;
+use crate::scan::{Thresholds, UnsafeBudget};
 
 use cargo::core::shell::ColorChoice;
 use cargo::{CliResult, Config};
@@ -11,9 +12,24 @@ pub const HELP: &str =
 
 USAGE:
     cargo geiger [OPTIONS]
+    cargo geiger diff <OLD> <NEW> [--json]
+
+SUBCOMMANDS:
+    diff                          Compare two reports written with `--json`,
+                                  listing added and removed packages and the
+                                  change of the unsafe counts of every other
+                                  package.
 
 OPTIONS:
-    -p, --package <SPEC>          Package to be used as the root of the tree.
+    -p, --package <SPEC>...       Package to be used as the root of the tree,
+                                  can be given more than once. Takes package
+                                  id specs like `name`, `name@version` or
+                                  `url#name:version`.
+        --workspace               Scan all packages in the workspace, one tree
+                                  per member. Required for workspaces with a
+                                  virtual manifest.
+        --exclude <SPEC>...       Exclude packages from the scan, only works
+                                  with `--workspace`.
         --features <FEATURES>     Space-separated list of features to activate.
         --all-features            Activate all available features.
         --no-default-features     Do not activate the `default` feature.
@@ -21,8 +37,28 @@ OPTIONS:
         --all-targets             Return dependencies for all targets. By
                                   default only the host target is matched.
         --manifest-path <PATH>    Path to Cargo.toml.
+        --target-dir <DIRECTORY>  Directory for the build done to find the
+                                  used .rs files [default: target/geiger].
         --unsafe-fn-log <PATH>    Save unsafe function names to this log file.
                                   Only works with `--json` option.
+        --cache-dir <DIRECTORY>   Directory for the cached scan results of
+                                  dependencies
+                                  [default: $CARGO_HOME/geiger-cache].
+        --no-cache                Scan all files, don't use the scan cache.
+        --baseline <PATH>         Compare the scan to a report written with
+                                  `--json` and fail if a package uses more
+                                  unsafe code, no longer forbids unsafe code
+                                  or is new and uses unsafe code.
+        --update-baseline         Write the report of the scan to the
+                                  `--baseline` file instead of comparing.
+        --max-unsafe-exprs <N>    Fail if a package uses more unsafe
+                                  expressions than this. Also available for
+                                  `functions`, `impls` and `macros`, and as
+                                  `--max-total-unsafe-exprs` etc. for the sum
+                                  over all packages. Overrides the thresholds
+                                  of `geiger.toml`.
+    -j, --jobs <N>                Number of files to scan in parallel
+                                  [default: number of CPUs].
     -i, --invert                  Invert the tree direction.
         --no-indent               Display the dependencies as a list (rather
                                   than a tree).
@@ -34,7 +70,28 @@ OPTIONS:
                                   [default: utf8].
     --format <FORMAT>             Format string used for printing dependencies
                                   [default: {p}].
-    --json                        Output in JSON format.
+    --json                        Output in JSON format, the same as
+                                  `--output-format json`.
+        --output-format <FORMAT>  Output format of the report: json, sarif,
+                                  cyclonedx, html, markdown, dot. SARIF lists
+                                  every unsafe finding for code scanning
+                                  tools. CycloneDX is an SBOM with the
+                                  unsafety of each package as properties. HTML
+                                  is a standalone page with a collapsible
+                                  tree. Markdown is a summary table for pull
+                                  request comments, with the changes compared
+                                  to `--baseline` if given. DOT is the
+                                  dependency graph for Graphviz, pointing from
+                                  dependencies to dependents with `--invert`.
+                                  Only JSON and CycloneDX are available with
+                                  `--forbid-only`.
+        --per-file                Add the metrics of every source file to the
+                                  packages of the JSON report.
+        --top <N>                 Only list the N packages using the most
+                                  unsafe code in the Markdown summary.
+        --print-schema            Print the JSON Schema of the JSON report, or
+                                  of the `--forbid-only` report together with
+                                  `--forbid-only`.
     -v, --verbose                 Use verbose output (-vv very verbose/build.rs
                                   output).
     -q, --quiet                   No output printed to stdout other than the
@@ -53,18 +110,35 @@ OPTIONS:
                                   entry point .rs source files for.
                                   forbid(unsafe_code) flags. This is
                                   significantly faster than the default
-                                  scanning mode. TODO: Add ability to combine
-                                  this with a whitelist for use in CI.
+                                  scanning mode. Combine this with a
+                                  `geiger.toml` allowlist for use in CI.
+        --no-build                Don't build or clean anything, find the .rs
+                                  files used by the build by following `mod`
+                                  declarations from each target entry point.
+                                  Source files only reachable through build
+                                  scripts or proc macros are not detected.
     -h, --help                    Prints help information.
     -V, --version                 Prints version information.
+
+EXIT CODES:
+    0                             Success.
+    1                             The scan could not be completed.
+    2                             A package broke the allowlist policy.
+    3                             Unsafe usage exceeded a threshold.
+    4                             Unsafe usage grew compared to `--baseline`.
+    5                             The scan finished with warnings.
 ";
 
 #[derive(Default)]
 pub struct Args {
     pub all: bool,
+    pub baseline: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
     pub charset: Charset,
     pub color: Option<String>,
     pub deps_args: DepsArgs,
+    pub diff_paths: Option<(PathBuf, PathBuf)>,
+    pub exclude: Vec<String>,
     pub features_args: FeaturesArgs,
     pub forbid_only: bool,
     pub format: String,
@@ -72,18 +146,28 @@ pub struct Args {
     pub help: bool,
     pub include_tests: bool,
     pub invert: bool,
+    pub jobs: Option<usize>,
     pub locked: bool,
     pub manifest_path: Option<PathBuf>,
+    pub no_build: bool,
+    pub no_cache: bool,
     pub unsafe_fn_log: Option<PathBuf>,
     pub no_indent: bool,
     pub offline: bool,
-    pub package: Option<String>,
+    pub package: Vec<String>,
+    pub per_file: bool,
     pub prefix_depth: bool,
+    pub print_schema: bool,
     pub quiet: bool,
     pub target_args: TargetArgs,
+    pub target_dir: Option<PathBuf>,
+    pub thresholds: Thresholds,
+    pub top: Option<usize>,
     pub unstable_flags: Vec<String>,
+    pub update_baseline: bool,
     pub verbose: u32,
     pub version: bool,
+    pub workspace: bool,
     pub output_format: Option<OutputFormat>,
 }
 
@@ -91,8 +175,35 @@ impl Args {
     pub fn parse_args(
         mut raw_args: Arguments,
     ) -> Result<Args, Box<dyn std::error::Error>> {
+        let mut subcommand = raw_args.subcommand()?;
+        // Cargo runs `cargo geiger` as `cargo-geiger geiger`.
+        if subcommand.as_deref() == Some("geiger") {
+            subcommand = raw_args.subcommand()?;
+        }
+        let diff_paths = match subcommand.as_deref() {
+            None => None,
+            Some("diff") => {
+                match (raw_args.subcommand()?, raw_args.subcommand()?) {
+                    (Some(old_path), Some(new_path)) => {
+                        Some((PathBuf::from(old_path), PathBuf::from(new_path)))
+                    }
+                    _ => {
+                        return Err(
+                            "`diff` takes the paths of two reports".into()
+                        )
+                    }
+                }
+            }
+            Some(subcommand) => {
+                return Err(
+                    format!("unknown subcommand `{}`", subcommand).into()
+                )
+            }
+        };
         let args = Args {
             all: raw_args.contains(["-a", "--all"]),
+            baseline: raw_args.opt_value_from_str("--baseline")?,
+            cache_dir: raw_args.opt_value_from_str("--cache-dir")?,
             charset: raw_args
                 .opt_value_from_str("--charset")?
                 .unwrap_or(Charset::Utf8),
@@ -102,6 +213,8 @@ impl Args {
                 build_deps: raw_args.contains("--build-dependencies"),
                 dev_deps: raw_args.contains("--dev-dependencies"),
             },
+            diff_paths,
+            exclude: raw_args.values_from_str("--exclude")?,
             features_args: FeaturesArgs {
                 all_features: raw_args.contains("--all-features"),
                 features: parse_features(
@@ -117,22 +230,50 @@ impl Args {
             help: raw_args.contains(["-h", "--help"]),
             include_tests: raw_args.contains("--include-tests"),
             invert: raw_args.contains(["-i", "--invert"]),
+            jobs: raw_args.opt_value_from_str(["-j", "--jobs"])?,
             locked: raw_args.contains("--locked"),
             manifest_path: raw_args.opt_value_from_str("--manifest-path")?,
+            no_build: raw_args.contains("--no-build"),
+            no_cache: raw_args.contains("--no-cache"),
             unsafe_fn_log: raw_args.opt_value_from_str("--unsafe-fn-log")?,
             no_indent: raw_args.contains("--no-indent"),
             offline: raw_args.contains("--offline"),
-            package: raw_args.opt_value_from_str("--manifest-path")?,
+            package: raw_args.values_from_str(["-p", "--package"])?,
+            per_file: raw_args.contains("--per-file"),
             prefix_depth: raw_args.contains("--prefix-depth"),
+            print_schema: raw_args.contains("--print-schema"),
             quiet: raw_args.contains(["-q", "--quiet"]),
             target_args: TargetArgs {
                 all_targets: raw_args.contains("--all-targets"),
                 target: raw_args.opt_value_from_str("--target")?,
             },
+            target_dir: raw_args.opt_value_from_str("--target-dir")?,
+            thresholds: Thresholds {
+                per_crate: UnsafeBudget {
+                    exprs: raw_args.opt_value_from_str("--max-unsafe-exprs")?,
+                    functions: raw_args
+                        .opt_value_from_str("--max-unsafe-functions")?,
+                    impls: raw_args.opt_value_from_str("--max-unsafe-impls")?,
+                    macros: raw_args
+                        .opt_value_from_str("--max-unsafe-macros")?,
+                },
+                total: UnsafeBudget {
+                    exprs: raw_args
+                        .opt_value_from_str("--max-total-unsafe-exprs")?,
+                    functions: raw_args
+                        .opt_value_from_str("--max-total-unsafe-functions")?,
+                    impls: raw_args
+                        .opt_value_from_str("--max-total-unsafe-impls")?,
+                    macros: raw_args
+                        .opt_value_from_str("--max-total-unsafe-macros")?,
+                },
+            },
+            top: raw_args.opt_value_from_str("--top")?,
             unstable_flags: raw_args
                 .opt_value_from_str("-Z")?
                 .map(|s: String| s.split(' ').map(|s| s.to_owned()).collect())
                 .unwrap_or_else(Vec::new),
+            update_baseline: raw_args.contains("--update-baseline"),
             verbose: match (
                 raw_args.contains("-vv"),
                 raw_args.contains(["-v", "--verbose"]),
@@ -142,17 +283,56 @@ impl Args {
                 (true, _) => 2,
             },
             version: raw_args.contains(["-V", "--version"]),
-            output_format: if raw_args.contains("--json") {
-                Some(OutputFormat::Json)
-            } else {
-                None
+            workspace: raw_args.contains("--workspace"),
+            output_format: match (
+                raw_args.contains("--json"),
+                raw_args.opt_value_from_str("--output-format")?,
+            ) {
+                (false, output_format) => output_format,
+                (true, None) | (true, Some(OutputFormat::Json)) => {
+                    Some(OutputFormat::Json)
+                }
+                (true, Some(_)) => {
+                    return Err(
+                        "--json can not be used with other output formats"
+                            .into(),
+                    )
+                }
             },
         };
+        if args.update_baseline && args.baseline.is_none() {
+            return Err("--update-baseline requires --baseline <PATH>".into());
+        }
+        if args.forbid_only && args.baseline.is_some() {
+            return Err("--baseline can not be used with --forbid-only".into());
+        }
+        if args.per_file && args.output_format != Some(OutputFormat::Json) {
+            return Err("--per-file requires --output-format json".into());
+        }
+        if args.top.is_some()
+            && args.output_format != Some(OutputFormat::Markdown)
+        {
+            return Err("--top requires --output-format markdown".into());
+        }
+        if args.forbid_only
+            && !matches!(
+                args.output_format,
+                None | Some(OutputFormat::CycloneDx) | Some(OutputFormat::Json)
+            )
+        {
+            return Err("--forbid-only supports only the cyclonedx and json \
+                        output formats"
+                .into());
+        }
+        if args.forbid_only && !args.thresholds.is_empty() {
+            return Err("--max-* can not be used with --forbid-only".into());
+        }
         Ok(args)
     }
 
     pub fn update_config(&self, config: &mut Config) -> CliResult {
-        let target_dir = None; // Doesn't add any value for cargo-geiger.
+        // The cargo-geiger build directory is set up by `resolve_rs_file_deps`.
+        let target_dir = None;
         config.configure(
             self.verbose,
             self.quiet,
@@ -269,6 +449,293 @@ pub mod args_tests {
         assert_eq!(args.verbose, expected_verbose)
     }
 
+    #[rstest(
+        input_argument_vector,
+        expected_diff_paths,
+        case(vec![], None),
+        case(vec!["geiger", "--json"], None),
+        case(
+            vec!["geiger", "diff", "old.json", "new.json", "--json"],
+            Some((PathBuf::from("old.json"), PathBuf::from("new.json")))
+        ),
+        case(
+            vec!["diff", "old.json", "new.json"],
+            Some((PathBuf::from("old.json"), PathBuf::from("new.json")))
+        )
+    )]
+    fn parse_args_test_diff(
+        input_argument_vector: Vec<&str>,
+        expected_diff_paths: Option<(PathBuf, PathBuf)>,
+    ) {
+        let args = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(args.diff_paths, expected_diff_paths);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        case(vec!["geiger", "diff", "old.json"]),
+        case(vec!["geiger", "unknown"])
+    )]
+    fn parse_args_test_invalid_subcommand(input_argument_vector: Vec<&str>) {
+        let args_result = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ));
+
+        assert!(args_result.is_err());
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_workspace,
+        expected_exclude,
+        case(vec![], false, vec![]),
+        case(vec!["--workspace"], true, vec![]),
+        case(
+            vec!["--workspace", "--exclude", "a", "--exclude", "b"],
+            true,
+            vec![String::from("a"), String::from("b")]
+        )
+    )]
+    fn parse_args_test_workspace(
+        input_argument_vector: Vec<&str>,
+        expected_workspace: bool,
+        expected_exclude: Vec<String>,
+    ) {
+        let args = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(args.workspace, expected_workspace);
+        assert_eq!(args.exclude, expected_exclude);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_baseline,
+        expected_update_baseline,
+        case(vec![], None, false),
+        case(
+            vec!["--baseline", "geiger-baseline.json"],
+            Some(PathBuf::from("geiger-baseline.json")),
+            false
+        ),
+        case(
+            vec!["--baseline", "geiger-baseline.json", "--update-baseline"],
+            Some(PathBuf::from("geiger-baseline.json")),
+            true
+        )
+    )]
+    fn parse_args_test_baseline(
+        input_argument_vector: Vec<&str>,
+        expected_baseline: Option<PathBuf>,
+        expected_update_baseline: bool,
+    ) {
+        let args = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(args.baseline, expected_baseline);
+        assert_eq!(args.update_baseline, expected_update_baseline);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_output_format,
+        case(vec![], None),
+        case(vec!["--json"], Some(OutputFormat::Json)),
+        case(vec!["--output-format", "json"], Some(OutputFormat::Json)),
+        case(
+            vec!["--json", "--output-format", "json"],
+            Some(OutputFormat::Json)
+        ),
+        case(vec!["--output-format", "sarif"], Some(OutputFormat::Sarif)),
+        case(vec!["--output-format", "dot"], Some(OutputFormat::Dot)),
+        case(vec!["--output-format", "html"], Some(OutputFormat::Html)),
+        case(
+            vec!["--output-format", "markdown"],
+            Some(OutputFormat::Markdown)
+        )
+    )]
+    fn parse_args_test_output_format(
+        input_argument_vector: Vec<&str>,
+        expected_output_format: Option<OutputFormat>,
+    ) {
+        let args = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(args.output_format, expected_output_format);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        case(vec!["--json", "--output-format", "sarif"]),
+        case(vec!["--output-format", "xml"])
+    )]
+    fn parse_args_test_invalid_output_format(input_argument_vector: Vec<&str>) {
+        let args_result = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ));
+
+        assert!(args_result.is_err());
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_error,
+        case(
+            vec!["--update-baseline"],
+            "--update-baseline requires --baseline <PATH>"
+        ),
+        case(
+            vec!["--forbid-only", "--baseline", "geiger-baseline.json"],
+            "--baseline can not be used with --forbid-only"
+        ),
+        case(
+            vec!["--per-file", "--output-format", "sarif"],
+            "--per-file requires --output-format json"
+        ),
+        case(vec!["--top", "10"], "--top requires --output-format markdown"),
+        case(
+            vec!["--forbid-only", "--output-format", "sarif"],
+            "--forbid-only supports only the cyclonedx and json output \
+             formats"
+        ),
+        case(
+            vec!["--forbid-only", "--max-unsafe-exprs", "0"],
+            "--max-* can not be used with --forbid-only"
+        )
+    )]
+    fn parse_args_test_conflicting_args(
+        input_argument_vector: Vec<&str>,
+        expected_error: &str,
+    ) {
+        let args_result = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ));
+
+        match args_result {
+            Ok(_) => panic!("expected the arguments to be rejected"),
+            Err(e) => assert_eq!(e.to_string(), expected_error),
+        }
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_per_file,
+        case(vec![], false),
+        case(vec!["--json", "--per-file"], true)
+    )]
+    fn parse_args_test_per_file(
+        input_argument_vector: Vec<&str>,
+        expected_per_file: bool,
+    ) {
+        let args = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(args.per_file, expected_per_file);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_top,
+        case(vec![], None),
+        case(vec!["--output-format", "markdown", "--top", "10"], Some(10))
+    )]
+    fn parse_args_test_top(
+        input_argument_vector: Vec<&str>,
+        expected_top: Option<usize>,
+    ) {
+        let args = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(args.top, expected_top);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_thresholds,
+        case(vec![], Thresholds::default()),
+        case(
+            vec![
+                "--max-unsafe-exprs",
+                "10",
+                "--max-unsafe-impls",
+                "0",
+                "--max-total-unsafe-functions",
+                "5",
+                "--max-total-unsafe-macros",
+                "1"
+            ],
+            Thresholds {
+                per_crate: UnsafeBudget {
+                    exprs: Some(10),
+                    functions: None,
+                    impls: Some(0),
+                    macros: None,
+                },
+                total: UnsafeBudget {
+                    exprs: None,
+                    functions: Some(5),
+                    impls: None,
+                    macros: Some(1),
+                },
+            }
+        )
+    )]
+    fn parse_args_test_thresholds(
+        input_argument_vector: Vec<&str>,
+        expected_thresholds: Thresholds,
+    ) {
+        let args = Args::parse_args(Arguments::from_vec(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(args.thresholds, expected_thresholds);
+    }
+
     #[rstest(
         input_raw_features,
         expected_features,
@@ -8,6 +8,7 @@
 // TODO: Investigate how cargo-clippy is implemented. Is it using syn?  Is is
 // using rustc? Is it implementing a compiler plugin?
 
+use crate::mapping::{QueryResolve, QueryResolveError};
 use crate::Args;
 
 // TODO: Consider making this a lib.rs (again) and expose a full API, excluding
@@ -15,7 +16,8 @@ use crate::Args;
 use cargo::core::Workspace;
 use cargo::util::{self, important_paths, CargoResult};
 use cargo::Config;
-use cargo_metadata::{CargoOpt, Metadata, MetadataCommand};
+use cargo_geiger_serde::{quick_safety_report_schema, safety_report_schema};
+use cargo_metadata::{CargoOpt, Metadata, MetadataCommand, PackageId};
 use cargo_platform::Cfg;
 use krates::Builder as KratesBuilder;
 use krates::Krates;
@@ -83,6 +85,40 @@ pub fn get_krates(cargo_metadata: &Metadata) -> CargoResult<Krates> {
         .build_with_metadata(cargo_metadata.clone(), |_| ())?)
 }
 
+/// The packages scanned with `--workspace`, all workspace members except the
+/// ones matching a package id spec in `exclude`.
+pub fn get_workspace_member_package_ids<T: QueryResolve>(
+    config: &Config,
+    exclude: &[String],
+    krates: &T,
+    metadata: &Metadata,
+) -> CargoResult<Vec<PackageId>> {
+    let mut excluded_package_ids = Vec::new();
+    for spec in exclude {
+        match krates.query_resolve(spec) {
+            Ok(package_id)
+                if metadata.workspace_members.contains(&package_id) =>
+            {
+                excluded_package_ids.push(package_id)
+            }
+            Ok(_) | Err(QueryResolveError::NotFound(_)) => {
+                config.shell().warn(format!(
+                    "excluded package `{}` not found in workspace",
+                    spec
+                ))?
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(metadata
+        .packages
+        .iter()
+        .filter(|package| metadata.workspace_members.contains(&package.id))
+        .filter(|package| !excluded_package_ids.contains(&package.id))
+        .map(|package| package.id.clone())
+        .collect())
+}
+
 pub fn get_workspace(
     config: &Config,
     manifest_path: Option<PathBuf>,
@@ -94,6 +130,17 @@ pub fn get_workspace(
     Workspace::new(&root, config)
 }
 
+/// The JSON Schema of the report written with `--json`, or of the
+/// `--forbid-only` report.
+pub fn report_schema(forbid_only: bool) -> String {
+    let schema = if forbid_only {
+        quick_safety_report_schema()
+    } else {
+        safety_report_schema()
+    };
+    serde_json::to_string_pretty(&schema).unwrap()
+}
+
 // TODO: Make a wrapper type for canonical paths and hide all mutable access.
 
 #[cfg(test)]
@@ -101,6 +148,98 @@ mod cli_tests {
     use super::*;
     use rstest::*;
 
+    #[rstest(
+        input_exclude,
+        expected_package_names,
+        case(vec![], vec!["cargo-geiger", "cargo-geiger-serde", "geiger"]),
+        case(vec!["geiger"], vec!["cargo-geiger", "cargo-geiger-serde"]),
+        case(vec!["geiger@0.4.5"], vec!["cargo-geiger", "cargo-geiger-serde"]),
+        case(
+            vec!["geiger", "not-a-member"],
+            vec!["cargo-geiger", "cargo-geiger-serde"]
+        ),
+        case(
+            vec!["cargo_metadata"],
+            vec!["cargo-geiger", "cargo-geiger-serde", "geiger"]
+        )
+    )]
+    fn get_workspace_member_package_ids_test(
+        input_exclude: Vec<&str>,
+        expected_package_names: Vec<&str>,
+    ) {
+        let config = Config::default().unwrap();
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()
+            .unwrap();
+        let krates = get_krates(&metadata).unwrap();
+        let exclude = input_exclude
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        let package_ids = get_workspace_member_package_ids(
+            &config, &exclude, &krates, &metadata,
+        )
+        .unwrap();
+
+        let mut package_names = package_ids
+            .iter()
+            .map(|id| {
+                metadata
+                    .packages
+                    .iter()
+                    .find(|package| package.id == *id)
+                    .unwrap()
+                    .name
+                    .as_str()
+            })
+            .collect::<Vec<_>>();
+        package_names.sort_unstable();
+        assert_eq!(package_names, expected_package_names);
+    }
+
+    #[rstest]
+    fn get_workspace_member_package_ids_test_invalid_spec() {
+        let config = Config::default().unwrap();
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()
+            .unwrap();
+        let krates = get_krates(&metadata).unwrap();
+
+        assert!(get_workspace_member_package_ids(
+            &config,
+            &[String::from("geiger@x.y.z")],
+            &krates,
+            &metadata,
+        )
+        .is_err());
+    }
+
+    #[rstest(
+        input_forbid_only,
+        expected_schema,
+        case(
+            false,
+            include_str!("../../cargo-geiger-serde/schema/safety-report.json")
+        ),
+        case(
+            true,
+            include_str!(
+                "../../cargo-geiger-serde/schema/quick-safety-report.json"
+            )
+        )
+    )]
+    fn report_schema_test(input_forbid_only: bool, expected_schema: &str) {
+        // The shipped schema files have to be regenerated with
+        // `cargo geiger --print-schema` when the reports change.
+        assert_eq!(
+            report_schema(input_forbid_only),
+            expected_schema.trim_end()
+        );
+    }
+
     #[rstest]
     fn get_cargo_metadata_test() {
         let args = Args::default();
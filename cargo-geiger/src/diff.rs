@@ -0,0 +1,319 @@
+//! The `cargo geiger diff` subcommand, comparing two JSON reports.
+
+use crate::exit_code;
+use crate::format::package_label;
+use crate::format::print_config::OutputFormat;
+use crate::format::table::UNSAFE_COUNTERS_HEADER;
+
+use cargo::{CliError, CliResult};
+use cargo_geiger_serde::{
+    diff_reports, CountDiff, CounterBlock, CounterBlockDiff, ReportDiff,
+    SafetyReport,
+};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+/// Prints the differences between the reports stored at `old_path` and
+/// `new_path`, both written by `cargo geiger --json`.
+pub fn diff_report_files(
+    old_path: &Path,
+    new_path: &Path,
+    output_format: Option<OutputFormat>,
+) -> CliResult {
+    let old_report = read_report(old_path)?;
+    let new_report = read_report(new_path)?;
+    let report_diff = diff_reports(&old_report, &new_report);
+    match output_format {
+        Some(OutputFormat::Json) => {
+            println!("{}", serde_json::to_string(&report_diff).unwrap())
+        }
+        Some(output_format) => {
+            return Err(CliError::new(
+                anyhow::anyhow!(
+                    "`diff` can only write JSON, not {:?}",
+                    output_format
+                ),
+                exit_code::ERROR,
+            ))
+        }
+        None => {
+            for line in construct_diff_table_lines(&report_diff) {
+                println!("{}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn read_report(path: &Path) -> Result<SafetyReport, CliError> {
+    let report = fs::read(path)
+        .map_err(anyhow::Error::new)
+        .and_then(|bytes| {
+            SafetyReport::from_json_slice(&bytes).map_err(anyhow::Error::new)
+        })
+        .map_err(|e| {
+            e.context(format!("failed to read report `{}`", path.display()))
+        })?;
+    Ok(report)
+}
+
+/// A table in the style of the regular output, with the change of the unsafe
+/// counts as `x/y` in every column, `x` being the change of the unsafe code
+/// used by the build and `y` of all unsafe code found in the package.
+fn construct_diff_table_lines(report_diff: &ReportDiff) -> Vec<String> {
+    let mut lines = Vec::new();
+    if report_diff.is_empty() {
+        lines.push(String::from("No changes found."));
+        return lines;
+    }
+    lines.push(
+        UNSAFE_COUNTERS_HEADER
+            .iter()
+            .map(|s| s.to_owned())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .bold()
+            .to_string(),
+    );
+    lines.push(String::new());
+
+    let empty = CounterBlock::default();
+    for entry in &report_diff.added_packages {
+        let used = CounterBlockDiff::new(&empty, &entry.unsafety.used);
+        let unused = CounterBlockDiff::new(&empty, &entry.unsafety.unused);
+        lines.push(format!(
+            "{} + {}",
+            diff_table_row(&used, &unused),
+            package_label(&entry.package.id)
+        ));
+    }
+    for entry in &report_diff.removed_packages {
+        let used = CounterBlockDiff::new(&entry.unsafety.used, &empty);
+        let unused = CounterBlockDiff::new(&entry.unsafety.unused, &empty);
+        lines.push(format!(
+            "{} - {}",
+            diff_table_row(&used, &unused),
+            package_label(&entry.package.id)
+        ));
+    }
+    for package_diff in &report_diff.changed_packages {
+        let label = if package_diff.is_version_change() {
+            format!(
+                "{} -> {}",
+                package_label(&package_diff.old_id),
+                package_diff.new_id.version
+            )
+        } else {
+            package_label(&package_diff.new_id)
+        };
+        let forbids_unsafe_change = match (
+            package_diff.old_forbids_unsafe,
+            package_diff.new_forbids_unsafe,
+        ) {
+            (false, true) => " (now forbids unsafe)",
+            (true, false) => " (no longer forbids unsafe)",
+            _ => "",
+        };
+        lines.push(format!(
+            "{} ~ {}{}",
+            diff_table_row(&package_diff.used, &package_diff.unused),
+            label,
+            forbids_unsafe_change
+        ));
+    }
+    lines.push(String::new());
+    lines
+}
+
+fn diff_table_row(
+    used: &CounterBlockDiff,
+    unused: &CounterBlockDiff,
+) -> String {
+    let fmt = |used: &CountDiff, unused: &CountDiff| {
+        format!(
+            "{}/{}",
+            signed(used.unsafe_),
+            signed(used.unsafe_ + unused.unsafe_)
+        )
+    };
+    format!(
+        "{: <10} {: <12} {: <6} {: <7} {: <8} {: <6}",
+        fmt(&used.functions, &unused.functions),
+        fmt(&used.exprs, &unused.exprs),
+        fmt(&used.item_impls, &unused.item_impls),
+        fmt(&used.item_traits, &unused.item_traits),
+        fmt(&used.methods, &unused.methods),
+        fmt(&used.macros, &unused.macros),
+    )
+}
+
+pub fn signed(n: i64) -> String {
+    if n == 0 {
+        String::from("0")
+    } else {
+        format!("{:+}", n)
+    }
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+
+    use crate::test_fixtures::{create_report, create_report_entry};
+
+    use cargo_geiger_serde::{
+        GitReference, PackageId, ReportMetadata, Source, REPORT_SCHEMA_VERSION,
+    };
+    use rstest::*;
+    use semver::Version;
+    use tempfile::tempdir;
+    use url::Url;
+
+    #[rstest]
+    fn construct_diff_table_lines_test() {
+        let old_report = create_report(vec![
+            create_report_entry("bar", "0.1.0", 1, 0, false),
+            create_report_entry("foo", "1.0.0", 2, 0, false),
+            create_report_entry("unchanged", "1.0.0", 0, 0, true),
+        ]);
+        let new_report = create_report(vec![
+            create_report_entry("baz", "0.2.0", 3, 0, false),
+            create_report_entry("foo", "1.1.0", 0, 0, true),
+            create_report_entry("unchanged", "1.0.0", 0, 0, true),
+        ]);
+
+        let report_diff = diff_reports(&old_report, &new_report);
+        assert_eq!(report_diff.changed_packages.len(), 1);
+
+        let lines = construct_diff_table_lines(&report_diff);
+        assert_eq!(
+            lines[2..],
+            [
+                "0/0        +3/+3        0/0    0/0     0/0      0/0    \
+                 + baz 0.2.0",
+                "0/0        -1/-1        0/0    0/0     0/0      0/0    \
+                 - bar 0.1.0",
+                "0/0        -2/-2        0/0    0/0     0/0      0/0    \
+                 ~ foo 1.0.0 -> 1.1.0 (now forbids unsafe)",
+                "",
+            ]
+        );
+    }
+
+    #[rstest]
+    fn read_report_test_migrates_old_schema_versions() {
+        // Written by cargo-geiger 0.10, without schema version, metadata,
+        // macro counts and unsafe locations.
+        let counts = |unsafe_exprs: u64| {
+            format!(
+                r#"{{"functions": {{"safe": 0, "unsafe_": 0}},
+                "exprs": {{"safe": 0, "unsafe_": {}}},
+                "item_impls": {{"safe": 0, "unsafe_": 0}},
+                "item_traits": {{"safe": 0, "unsafe_": 0}},
+                "methods": {{"safe": 0, "unsafe_": 0}}}}"#,
+                unsafe_exprs
+            )
+        };
+        let report_json = format!(
+            r#"{{"packages": [{{"package": {{"id": {{"name": "foo",
+                "version": "1.0.0", "source": {{"Registry": {{
+                "name": "crates.io",
+                "url": "https://github.com/rust-lang/crates.io-index"}}}}}},
+                "dependencies": [], "dev_dependencies": [],
+                "build_dependencies": []}},
+                "unsafety": {{"used": {}, "unused": {},
+                "forbids_unsafe": false, "declared_unsafe_functions": [],
+                "contains_unsafe_functions": []}}}}],
+                "packages_without_metrics": [],
+                "used_but_not_scanned_files": []}}"#,
+            counts(2),
+            counts(0)
+        );
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        fs::write(&path, report_json).unwrap();
+
+        let report = read_report(&path).unwrap();
+
+        assert_eq!(report.schema_version, REPORT_SCHEMA_VERSION);
+        assert_eq!(report.metadata, ReportMetadata::default());
+        assert_eq!(
+            report,
+            create_report(vec![create_report_entry(
+                "foo", "1.0.0", 2, 0, false
+            )])
+        );
+    }
+
+    #[rstest]
+    fn read_report_test_migrates_git_sources() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        fs::write(
+            &path,
+            r#"{"schema_version": 1, "packages": [],
+            "packages_without_metrics": [{"name": "foo", "version": "1.0.0",
+                "source": {"Git": {"url": "https://github.com/foo/foo",
+                "rev": "abc123"}}}],
+            "used_but_not_scanned_files": []}"#,
+        )
+        .unwrap();
+
+        let report = read_report(&path).unwrap();
+
+        let package_id = PackageId {
+            name: String::from("foo"),
+            version: Version::parse("1.0.0").unwrap(),
+            source: Source::Git {
+                url: Url::parse("https://github.com/foo/foo").unwrap(),
+                reference: GitReference::Rev(String::from("abc123")),
+                precise: None,
+            },
+        };
+        assert_eq!(
+            report.packages_without_metrics,
+            vec![package_id].into_iter().collect()
+        );
+    }
+
+    #[rstest]
+    fn read_report_test_newer_schema_version() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        fs::write(
+            &path,
+            format!(
+                r#"{{"schema_version": {}, "packages": []}}"#,
+                REPORT_SCHEMA_VERSION + 1
+            ),
+        )
+        .unwrap();
+
+        assert!(read_report(&path).is_err());
+    }
+
+    #[rstest]
+    fn construct_diff_table_lines_test_no_changes() {
+        let report = create_report(vec![create_report_entry(
+            "foo", "1.0.0", 2, 0, false,
+        )]);
+        let report_diff = diff_reports(&report, &report);
+        assert!(report_diff.is_empty());
+        assert_eq!(
+            construct_diff_table_lines(&report_diff),
+            vec![String::from("No changes found.")]
+        );
+    }
+
+    #[rstest(
+        input_n,
+        expected_signed,
+        case(0, "0"),
+        case(3, "+3"),
+        case(-3, "-3")
+    )]
+    fn signed_test(input_n: i64, expected_signed: &str) {
+        assert_eq!(signed(input_n), expected_signed);
+    }
+}
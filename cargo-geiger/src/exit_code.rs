@@ -0,0 +1,16 @@
+//! The exit codes of `cargo geiger`, listed under `EXIT CODES` in the help
+//! text. When several checks fail, the code of the first one in this list
+//! after `ERROR` is used.
+
+/// The scan could not be completed, e.g. because of invalid arguments or a
+/// failed build.
+pub const ERROR: i32 = 1;
+/// A package broke the allowlist policy.
+pub const POLICY_VIOLATION: i32 = 2;
+/// A package, or all packages together, used more unsafe code than allowed by
+/// the thresholds.
+pub const THRESHOLD_EXCEEDED: i32 = 3;
+/// Unsafe usage grew compared to the `--baseline` report.
+pub const BASELINE_REGRESSION: i32 = 4;
+/// The scan finished with warnings, e.g. for files that could not be scanned.
+pub const WARNINGS: i32 = 5;
@@ -1,11 +1,17 @@
+pub mod cyclonedx;
+pub mod dot;
 pub mod emoji_symbols;
+pub mod html;
+pub mod markdown;
 pub mod pattern;
 pub mod print_config;
+pub mod sarif;
 pub mod table;
 
 mod display;
 mod parse;
 
+use cargo_geiger_serde::{PackageId, UnsafeInfo};
 use cargo_metadata::DependencyKind;
 use std::fmt;
 use std::str::{self, FromStr};
@@ -50,6 +56,29 @@ pub enum CrateDetectionStatus {
     UnsafeDetected,
 }
 
+impl CrateDetectionStatus {
+    /// The status of a package in a report, as shown by the table.
+    pub fn from_unsafe_info(unsafe_info: &UnsafeInfo) -> CrateDetectionStatus {
+        if unsafe_info.used.has_unsafe() {
+            CrateDetectionStatus::UnsafeDetected
+        } else if unsafe_info.forbids_unsafe {
+            CrateDetectionStatus::NoneDetectedForbidsUnsafe
+        } else {
+            CrateDetectionStatus::NoneDetectedAllowsUnsafe
+        }
+    }
+
+    /// The symbol of the status for output that is always UTF-8, the table
+    /// uses `EmojiSymbols` instead.
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            CrateDetectionStatus::NoneDetectedForbidsUnsafe => "🔒",
+            CrateDetectionStatus::NoneDetectedAllowsUnsafe => "❓",
+            CrateDetectionStatus::UnsafeDetected => "☢️",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum RawChunk<'a> {
     Argument(&'a str),
@@ -88,6 +117,11 @@ pub fn get_kind_group_name(dep_kind: DependencyKind) -> Option<&'static str> {
     }
 }
 
+/// The name and version of a package, how the reports refer to it.
+pub fn package_label(package_id: &PackageId) -> String {
+    format!("{} {}", package_id.name, package_id.version)
+}
+
 #[cfg(test)]
 mod format_tests {
     use super::*;
@@ -101,6 +135,36 @@ mod format_tests {
         assert_eq!(Charset::from_str("invalid_str"), Err("invalid charset"));
     }
 
+    #[rstest(
+        input_forbids_unsafe,
+        input_used_unsafe_exprs,
+        input_used_unsafe_macros,
+        expected_status,
+        case(true, 0, 0, CrateDetectionStatus::NoneDetectedForbidsUnsafe),
+        case(false, 0, 0, CrateDetectionStatus::NoneDetectedAllowsUnsafe),
+        case(false, 1, 0, CrateDetectionStatus::UnsafeDetected),
+        case(true, 1, 0, CrateDetectionStatus::UnsafeDetected),
+        case(false, 0, 1, CrateDetectionStatus::UnsafeDetected)
+    )]
+    fn crate_detection_status_from_unsafe_info_test(
+        input_forbids_unsafe: bool,
+        input_used_unsafe_exprs: u64,
+        input_used_unsafe_macros: u64,
+        expected_status: CrateDetectionStatus,
+    ) {
+        let mut unsafe_info = UnsafeInfo {
+            forbids_unsafe: input_forbids_unsafe,
+            ..Default::default()
+        };
+        unsafe_info.used.exprs.unsafe_ = input_used_unsafe_exprs;
+        unsafe_info.used.macros.unsafe_ = input_used_unsafe_macros;
+
+        assert_eq!(
+            CrateDetectionStatus::from_unsafe_info(&unsafe_info),
+            expected_status
+        );
+    }
+
     #[rstest]
     fn get_kind_group_name_test() {
         assert_eq!(
@@ -0,0 +1,480 @@
+//! CycloneDX 1.4 SBOM output, the unsafety of each package is added to its
+//! component as properties.
+
+use cargo_geiger_serde::{
+    Count, CounterBlock, GitReference, PackageId, PackageInfo,
+    QuickSafetyReport, SafetyReport, Source,
+};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+const CRATES_IO_SPARSE_INDEX: &str = "https://index.crates.io/";
+
+/// Prefix of the names of all properties added by cargo-geiger.
+const PROPERTY_PREFIX: &str = "cargo-geiger";
+
+/// Converts the report to a CycloneDX BOM, with the unsafe counts and
+/// `forbids_unsafe` of every package as properties.
+pub fn to_cyclonedx(report: &SafetyReport) -> Value {
+    let packages = report
+        .packages
+        .values()
+        .map(|entry| {
+            let mut properties =
+                counter_block_properties("used", &entry.unsafety.used);
+            properties.extend(counter_block_properties(
+                "unused",
+                &entry.unsafety.unused,
+            ));
+            properties
+                .push(forbids_unsafe_property(entry.unsafety.forbids_unsafe));
+            (&entry.package, properties)
+        })
+        .collect();
+    bom(packages, &report.packages_without_metrics)
+}
+
+/// Like `to_cyclonedx`, only `forbids_unsafe` is known in `--forbid-only`
+/// mode.
+pub fn quick_report_to_cyclonedx(report: &QuickSafetyReport) -> Value {
+    let packages = report
+        .packages
+        .values()
+        .map(|entry| {
+            (
+                &entry.package,
+                vec![forbids_unsafe_property(entry.forbids_unsafe)],
+            )
+        })
+        .collect();
+    bom(packages, &report.packages_without_metrics)
+}
+
+/// The package URL of a cargo package, see
+/// <https://github.com/package-url/purl-spec>. Packages from another registry
+/// or from git get a qualifier pointing to their source. Local paths are left
+/// out, they mean nothing outside of the machine the report was made on.
+fn purl(package_id: &PackageId) -> String {
+    let purl = format!(
+        "pkg:cargo/{}@{}",
+        percent_encode(&package_id.name),
+        percent_encode(&package_id.version.to_string())
+    );
+    match &package_id.source {
+        Source::Registry { url, .. } | Source::SparseRegistry { url, .. }
+            if url.as_str() == CRATES_IO_INDEX
+                || url.as_str() == CRATES_IO_SPARSE_INDEX =>
+        {
+            purl
+        }
+        Source::Registry { url, .. } | Source::SparseRegistry { url, .. } => {
+            format!("{}?repository_url={}", purl, percent_encode(url.as_str()))
+        }
+        Source::Git {
+            url,
+            reference,
+            precise,
+        } => {
+            // The resolved commit if known, otherwise what was asked for.
+            let revision = match (precise, reference) {
+                (Some(commit), _) => Some(commit),
+                (None, GitReference::Branch(revision))
+                | (None, GitReference::Tag(revision))
+                | (None, GitReference::Rev(revision)) => Some(revision),
+                (None, GitReference::DefaultBranch) => None,
+            };
+            let vcs_url = match revision {
+                Some(revision) => format!("git+{}@{}", url, revision),
+                None => format!("git+{}", url),
+            };
+            format!("{}?vcs_url={}", purl, percent_encode(&vcs_url))
+        }
+        Source::Path(_) | Source::LocalRegistry(_) | Source::Directory(_) => {
+            purl
+        }
+    }
+}
+
+/// The reference of a component within the BOM, the full package id in the
+/// format of cargo. Unlike the purl it includes every source, so a path or
+/// vendored copy of a package does not share the reference of the original.
+fn bom_ref(package_id: &PackageId) -> String {
+    let source = match &package_id.source {
+        Source::Git {
+            url,
+            reference,
+            precise,
+        } => {
+            let mut source = format!("git+{}", url);
+            match reference {
+                GitReference::Branch(branch) => {
+                    source.push_str(&format!("?branch={}", branch))
+                }
+                GitReference::Tag(tag) => {
+                    source.push_str(&format!("?tag={}", tag))
+                }
+                GitReference::Rev(rev) => {
+                    source.push_str(&format!("?rev={}", rev))
+                }
+                GitReference::DefaultBranch => {}
+            }
+            if let Some(precise) = precise {
+                source.push_str(&format!("#{}", precise));
+            }
+            source
+        }
+        Source::Registry { url, .. } => format!("registry+{}", url),
+        Source::SparseRegistry { url, .. } => format!("sparse+{}", url),
+        Source::Path(url) => format!("path+{}", url),
+        Source::LocalRegistry(url) => format!("local-registry+{}", url),
+        Source::Directory(url) => format!("directory+{}", url),
+    };
+    format!("{} {} ({})", package_id.name, package_id.version, source)
+}
+
+fn bom(
+    mut packages: Vec<(&PackageInfo, Vec<Value>)>,
+    packages_without_metrics: &HashSet<PackageId>,
+) -> Value {
+    packages.sort_by(|(a, _), (b, _)| a.id.cmp(&b.id));
+    let mut packages_without_metrics =
+        packages_without_metrics.iter().collect::<Vec<_>>();
+    packages_without_metrics.sort();
+
+    let components = packages
+        .iter()
+        .map(|(package, properties)| component(&package.id, properties))
+        .chain(
+            packages_without_metrics
+                .into_iter()
+                .map(|package_id| component(package_id, &[])),
+        )
+        .collect::<Vec<_>>();
+    let dependencies = packages
+        .iter()
+        .map(|(package, _)| {
+            let mut dependencies = package
+                .dependencies
+                .iter()
+                .chain(&package.build_dependencies)
+                .chain(&package.dev_dependencies)
+                .collect::<Vec<_>>();
+            dependencies.sort();
+            dependencies.dedup();
+            json!({
+                "ref": bom_ref(&package.id),
+                "dependsOn": dependencies
+                    .into_iter()
+                    .map(bom_ref)
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.4",
+        "version": 1,
+        "metadata": {
+            "tools": [{
+                "vendor": "rust-secure-code",
+                "name": "cargo-geiger",
+                "version": env!("CARGO_PKG_VERSION"),
+            }],
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+fn component(package_id: &PackageId, properties: &[Value]) -> Value {
+    let mut component = json!({
+        "type": "library",
+        "bom-ref": bom_ref(package_id),
+        "name": package_id.name,
+        "version": package_id.version.to_string(),
+        "purl": purl(package_id),
+    });
+    if !properties.is_empty() {
+        component["properties"] = Value::from(properties.to_vec());
+    }
+    component
+}
+
+/// One property per count, e.g. `cargo-geiger:used:exprs:unsafe`.
+fn counter_block_properties(
+    prefix: &str,
+    counter_block: &CounterBlock,
+) -> Vec<Value> {
+    let counts: [(&str, &Count); 6] = [
+        ("functions", &counter_block.functions),
+        ("exprs", &counter_block.exprs),
+        ("item_impls", &counter_block.item_impls),
+        ("item_traits", &counter_block.item_traits),
+        ("methods", &counter_block.methods),
+        ("macros", &counter_block.macros),
+    ];
+    counts
+        .iter()
+        .flat_map(|(name, count)| {
+            vec![
+                property(
+                    &format!("{}:{}:safe", prefix, name),
+                    count.safe.to_string(),
+                ),
+                property(
+                    &format!("{}:{}:unsafe", prefix, name),
+                    count.unsafe_.to_string(),
+                ),
+            ]
+        })
+        .collect()
+}
+
+fn forbids_unsafe_property(forbids_unsafe: bool) -> Value {
+    property("forbids_unsafe", forbids_unsafe.to_string())
+}
+
+fn property(name: &str, value: String) -> Value {
+    json!({
+        "name": format!("{}:{}", PROPERTY_PREFIX, name),
+        "value": value,
+    })
+}
+
+/// Percent-encodes everything except the unreserved characters of RFC 3986.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod cyclonedx_tests {
+    use super::*;
+
+    use crate::test_fixtures::create_package_id;
+
+    use cargo_geiger_serde::{
+        DependencyKind, QuickReportEntry, ReportEntry, UnsafeInfo,
+    };
+    use rstest::*;
+    use semver::Version;
+    use url::Url;
+
+    #[rstest(
+        input_source,
+        expected_purl,
+        case(
+            Source::Registry {
+                name: String::from("crates.io"),
+                url: Url::parse(CRATES_IO_INDEX).unwrap(),
+            },
+            "pkg:cargo/foo@1.0.0-rc.1"
+        ),
+        case(
+            Source::Registry {
+                name: String::from("my-registry"),
+                url: Url::parse("https://example.com/index").unwrap(),
+            },
+            "pkg:cargo/foo@1.0.0-rc.1?repository_url=\
+             https%3A%2F%2Fexample.com%2Findex"
+        ),
+        case(
+            Source::SparseRegistry {
+                name: String::from("crates.io"),
+                url: Url::parse(CRATES_IO_SPARSE_INDEX).unwrap(),
+            },
+            "pkg:cargo/foo@1.0.0-rc.1"
+        ),
+        case(
+            Source::Git {
+                url: Url::parse("https://github.com/foo/foo").unwrap(),
+                reference: GitReference::Rev(String::from("abc123")),
+                precise: None,
+            },
+            "pkg:cargo/foo@1.0.0-rc.1?vcs_url=\
+             git%2Bhttps%3A%2F%2Fgithub.com%2Ffoo%2Ffoo%40abc123"
+        ),
+        case(
+            Source::Git {
+                url: Url::parse("https://github.com/foo/foo").unwrap(),
+                reference: GitReference::Branch(String::from("main")),
+                precise: Some(String::from("def456")),
+            },
+            "pkg:cargo/foo@1.0.0-rc.1?vcs_url=\
+             git%2Bhttps%3A%2F%2Fgithub.com%2Ffoo%2Ffoo%40def456"
+        ),
+        case(
+            Source::Path(Url::parse("file:///ws/foo").unwrap()),
+            "pkg:cargo/foo@1.0.0-rc.1"
+        ),
+        case(
+            Source::LocalRegistry(Url::parse("file:///ws/registry").unwrap()),
+            "pkg:cargo/foo@1.0.0-rc.1"
+        ),
+        case(
+            Source::Directory(Url::parse("file:///ws/vendor").unwrap()),
+            "pkg:cargo/foo@1.0.0-rc.1"
+        )
+    )]
+    fn purl_test(input_source: Source, expected_purl: &str) {
+        let package_id = PackageId {
+            name: String::from("foo"),
+            version: Version::parse("1.0.0-rc.1").unwrap(),
+            source: input_source,
+        };
+
+        assert_eq!(purl(&package_id), expected_purl);
+    }
+
+    #[rstest(
+        input_source,
+        expected_bom_ref,
+        case(
+            Source::Registry {
+                name: String::from("crates.io"),
+                url: Url::parse(CRATES_IO_INDEX).unwrap(),
+            },
+            "foo 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)"
+        ),
+        case(
+            Source::Git {
+                url: Url::parse("https://github.com/foo/foo").unwrap(),
+                reference: GitReference::Branch(String::from("main")),
+                precise: Some(String::from("def456")),
+            },
+            "foo 1.0.0 (git+https://github.com/foo/foo?branch=main#def456)"
+        ),
+        case(
+            Source::Path(Url::parse("file:///ws/foo").unwrap()),
+            "foo 1.0.0 (path+file:///ws/foo)"
+        ),
+        case(
+            Source::Directory(Url::parse("file:///ws/vendor").unwrap()),
+            "foo 1.0.0 (directory+file:///ws/vendor)"
+        )
+    )]
+    fn bom_ref_test(input_source: Source, expected_bom_ref: &str) {
+        let package_id = PackageId {
+            name: String::from("foo"),
+            version: Version::parse("1.0.0").unwrap(),
+            source: input_source,
+        };
+
+        assert_eq!(bom_ref(&package_id), expected_bom_ref);
+    }
+
+    #[rstest]
+    fn to_cyclonedx_test() {
+        let foo = create_package_id("foo", "1.0.0");
+        let bar = create_package_id("bar", "1.0.0");
+        let baz = create_package_id("baz", "1.0.0");
+        let mut package = PackageInfo::new(foo.clone());
+        package.add_dependency(bar.clone(), DependencyKind::Normal);
+        package.add_dependency(baz.clone(), DependencyKind::Build);
+        let mut report = SafetyReport::default();
+        report.packages.insert(
+            foo.clone(),
+            ReportEntry {
+                package,
+                unsafety: UnsafeInfo {
+                    used: CounterBlock {
+                        exprs: Count {
+                            safe: 4,
+                            unsafe_: 2,
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                files: None,
+            },
+        );
+        report.packages.insert(
+            bar.clone(),
+            ReportEntry {
+                package: PackageInfo::new(bar.clone()),
+                unsafety: UnsafeInfo {
+                    forbids_unsafe: true,
+                    ..Default::default()
+                },
+                files: None,
+            },
+        );
+        report.packages_without_metrics.insert(baz.clone());
+
+        let bom = to_cyclonedx(&report);
+
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        let component_refs = bom["components"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["bom-ref"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            component_refs,
+            vec![
+                "bar 1.0.0 (registry+https://github.com/rust-lang/\
+                 crates.io-index)",
+                "foo 1.0.0 (registry+https://github.com/rust-lang/\
+                 crates.io-index)",
+                "baz 1.0.0 (registry+https://github.com/rust-lang/\
+                 crates.io-index)"
+            ]
+        );
+        assert_eq!(bom["components"][1]["purl"], "pkg:cargo/foo@1.0.0");
+        let foo_properties =
+            bom["components"][1]["properties"].as_array().unwrap();
+        assert_eq!(foo_properties.len(), 25);
+        assert!(foo_properties.contains(&json!({
+            "name": "cargo-geiger:used:exprs:unsafe",
+            "value": "2",
+        })));
+        assert!(foo_properties.contains(&json!({
+            "name": "cargo-geiger:forbids_unsafe",
+            "value": "false",
+        })));
+        assert!(bom["components"][2].get("properties").is_none());
+        assert_eq!(
+            bom["dependencies"],
+            json!([
+                { "ref": bom_ref(&bar), "dependsOn": [] },
+                {
+                    "ref": bom_ref(&foo),
+                    "dependsOn": [bom_ref(&bar), bom_ref(&baz)],
+                },
+            ])
+        );
+    }
+
+    #[rstest]
+    fn quick_report_to_cyclonedx_test() {
+        let foo = create_package_id("foo", "1.0.0");
+        let mut report = QuickSafetyReport::default();
+        report.packages.insert(
+            foo.clone(),
+            QuickReportEntry {
+                package: PackageInfo::new(foo),
+                forbids_unsafe: true,
+            },
+        );
+
+        let bom = quick_report_to_cyclonedx(&report);
+
+        assert_eq!(
+            bom["components"][0]["properties"],
+            json!([{ "name": "cargo-geiger:forbids_unsafe", "value": "true" }])
+        );
+    }
+}
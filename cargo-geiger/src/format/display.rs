@@ -118,6 +118,7 @@ pub mod display_tests {
             cargo_metadata_parameters: &CargoMetadataParameters {
                 krates: &krates,
                 metadata: &metadata,
+                registry_names: &Default::default(),
             },
             pattern: &input_pattern,
             package: &package_id,
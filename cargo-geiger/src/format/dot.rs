@@ -0,0 +1,220 @@
+//! Graphviz DOT export of the dependency graph, with every package colored by
+//! its `CrateDetectionStatus` and the edges styled by dependency kind.
+
+use crate::format::CrateDetectionStatus;
+
+use cargo_geiger_serde::{
+    CounterBlock, DependencyKind, PackageId, SafetyReport,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+/// A dependency edge, from the dependent package to the dependency.
+pub type DependencyEdge = (PackageId, PackageId, DependencyKind);
+
+/// Renders the packages of the report and the edges between them. With
+/// `invert` every edge points from the dependency to the dependent package
+/// instead, showing where unsafe code enters the graph.
+pub fn to_dot(
+    report: &SafetyReport,
+    edges: &[DependencyEdge],
+    invert: bool,
+) -> String {
+    let package_ids = report
+        .packages
+        .keys()
+        .chain(&report.packages_without_metrics)
+        .collect::<BTreeSet<_>>();
+    let node_names = package_ids
+        .iter()
+        .enumerate()
+        .map(|(i, package_id)| (*package_id, format!("n{}", i)))
+        .collect::<HashMap<_, _>>();
+
+    let mut dot = String::from(
+        "digraph dependencies {\n    \
+         node [shape=box, style=filled, fontname=\"sans-serif\"];\n",
+    );
+    for package_id in &package_ids {
+        let label = format!("{} {}", package_id.name, package_id.version);
+        let attributes = match report.packages.get(package_id) {
+            Some(entry) => {
+                let status =
+                    CrateDetectionStatus::from_unsafe_info(&entry.unsafety);
+                format!(
+                    "label=\"{}\\n{}\", fillcolor=\"{}\"",
+                    escape(&label),
+                    used_unsafe_label(&entry.unsafety.used),
+                    fill_color(&status)
+                )
+            }
+            None => format!(
+                "label=\"{}\\nno metrics\", fillcolor=\"#dddddd\", \
+                 style=\"filled,dashed\"",
+                escape(&label)
+            ),
+        };
+        writeln!(dot, "    {} [{}];", node_names[package_id], attributes)
+            .unwrap();
+    }
+
+    let mut edges = edges
+        .iter()
+        .filter(|(from, to, _)| {
+            node_names.contains_key(from) && node_names.contains_key(to)
+        })
+        .collect::<Vec<_>>();
+    edges.sort_by(|a, b| {
+        (&a.0, &a.1, kind_order(a.2)).cmp(&(&b.0, &b.1, kind_order(b.2)))
+    });
+    for (from, to, kind) in edges {
+        let (from, to) = if invert { (to, from) } else { (from, to) };
+        write!(dot, "    {} -> {}", node_names[from], node_names[to]).unwrap();
+        match kind {
+            DependencyKind::Normal => dot.push_str(";\n"),
+            DependencyKind::Build => {
+                dot.push_str(" [style=dashed, label=\"build\"];\n")
+            }
+            DependencyKind::Development => {
+                dot.push_str(" [style=dotted, label=\"dev\"];\n")
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// The non-zero unsafe counters used by the build, e.g. `unsafe: 2 fns, 5
+/// exprs`.
+fn used_unsafe_label(used: &CounterBlock) -> String {
+    let counts = [
+        (used.functions.unsafe_, "fns"),
+        (used.exprs.unsafe_, "exprs"),
+        (used.item_impls.unsafe_, "impls"),
+        (used.item_traits.unsafe_, "traits"),
+        (used.methods.unsafe_, "methods"),
+        (used.macros.unsafe_, "macros"),
+    ]
+    .iter()
+    .filter(|(count, _)| *count > 0)
+    .map(|(count, name)| format!("{} {}", count, name))
+    .collect::<Vec<_>>();
+    if counts.is_empty() {
+        String::from("no unsafe used")
+    } else {
+        format!("unsafe: {}", counts.join(", "))
+    }
+}
+
+fn fill_color(status: &CrateDetectionStatus) -> &'static str {
+    match status {
+        CrateDetectionStatus::NoneDetectedForbidsUnsafe => "#b6e3b6",
+        CrateDetectionStatus::NoneDetectedAllowsUnsafe => "#fff2b3",
+        CrateDetectionStatus::UnsafeDetected => "#f4a6a6",
+    }
+}
+
+fn kind_order(kind: DependencyKind) -> u8 {
+    match kind {
+        DependencyKind::Normal => 0,
+        DependencyKind::Build => 1,
+        DependencyKind::Development => 2,
+    }
+}
+
+/// Escapes a string for a double quoted DOT id.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod dot_tests {
+    use super::*;
+
+    use crate::test_fixtures::create_package_id;
+
+    use cargo_geiger_serde::{Count, PackageInfo, ReportEntry, UnsafeInfo};
+    use rstest::*;
+
+    #[rstest(
+        input_invert,
+        expected_edges,
+        case(
+            false,
+            "    n2 -> n0 [style=dashed, label=\"build\"];\n\
+             \x20   n2 -> n1;\n\
+             \x20   n2 -> n1 [style=dotted, label=\"dev\"];\n"
+        ),
+        case(
+            true,
+            "    n0 -> n2 [style=dashed, label=\"build\"];\n\
+             \x20   n1 -> n2;\n\
+             \x20   n1 -> n2 [style=dotted, label=\"dev\"];\n"
+        )
+    )]
+    fn to_dot_test(input_invert: bool, expected_edges: &str) {
+        let foo = create_package_id("foo", "1.0.0");
+        let bar = create_package_id("bar", "1.0.0");
+        let baz = create_package_id("baz", "1.0.0");
+        let outside = create_package_id("outside", "1.0.0");
+        let mut report = SafetyReport::default();
+        report.packages.insert(
+            foo.clone(),
+            ReportEntry {
+                package: PackageInfo::new(foo.clone()),
+                unsafety: UnsafeInfo {
+                    used: CounterBlock {
+                        functions: Count {
+                            safe: 1,
+                            unsafe_: 2,
+                        },
+                        exprs: Count {
+                            safe: 0,
+                            unsafe_: 5,
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                files: None,
+            },
+        );
+        report.packages.insert(
+            bar.clone(),
+            ReportEntry {
+                package: PackageInfo::new(bar.clone()),
+                unsafety: UnsafeInfo {
+                    forbids_unsafe: true,
+                    ..Default::default()
+                },
+                files: None,
+            },
+        );
+        report.packages_without_metrics.insert(baz.clone());
+        let edges = vec![
+            (foo.clone(), baz.clone(), DependencyKind::Development),
+            (foo.clone(), bar.clone(), DependencyKind::Build),
+            (foo.clone(), baz, DependencyKind::Normal),
+            (foo, outside, DependencyKind::Normal),
+        ];
+
+        let dot = to_dot(&report, &edges, input_invert);
+
+        assert_eq!(
+            dot,
+            format!(
+                "digraph dependencies {{\n\
+                 \x20   node [shape=box, style=filled, \
+                 fontname=\"sans-serif\"];\n\
+                 \x20   n0 [label=\"bar 1.0.0\\nno unsafe used\", \
+                 fillcolor=\"#b6e3b6\"];\n\
+                 \x20   n1 [label=\"baz 1.0.0\\nno metrics\", \
+                 fillcolor=\"#dddddd\", style=\"filled,dashed\"];\n\
+                 \x20   n2 [label=\"foo 1.0.0\\nunsafe: 2 fns, 5 exprs\", \
+                 fillcolor=\"#f4a6a6\"];\n\
+                 {}}}\n",
+                expected_edges
+            )
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! A single self-contained HTML page, without external assets, with a
+//! collapsible dependency tree, a sortable package table and the unsafe
+//! findings of every package.
+
+use crate::format::package_label;
+use crate::format::CrateDetectionStatus;
+
+use cargo_geiger_serde::{
+    Count, PackageId, ReportEntry, SafetyReport, UnsafeKind,
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+code, .tree { font-family: monospace; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+table.sortable th { cursor: pointer; background: #eee; }
+td.count { text-align: right; }
+ul.tree, ul.tree ul { list-style: none; padding-left: 1.5em; }
+ul.tree { padding-left: 0; }
+.unsafe-detected { color: #b00; font-weight: bold; }
+.forbids-unsafe { color: #070; }
+.no-metrics { color: #888; }
+details.package { margin-bottom: 0.5em; }
+"#;
+
+/// Sorts the rows of a `sortable` table by the `data-sort` value of the
+/// clicked column, numerically if possible.
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach(function (th, column) {
+  th.addEventListener("click", function () {
+    var tbody = th.closest("table").tBodies[0];
+    var ascending = th.dataset.order !== "ascending";
+    th.dataset.order = ascending ? "ascending" : "descending";
+    var rows = Array.prototype.slice.call(tbody.rows);
+    rows.sort(function (a, b) {
+      var x = a.cells[column].dataset.sort;
+      var y = b.cells[column].dataset.sort;
+      var order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return ascending ? order : -order;
+    });
+    rows.forEach(function (row) { tbody.appendChild(row); });
+  });
+});
+"#;
+
+/// Renders the report as an HTML page. `tree` holds the packages in the order
+/// of `walk_dependency_tree`, each with its depth in the tree.
+pub fn to_html(report: &SafetyReport, tree: &[(usize, PackageId)]) -> String {
+    let mut entries = report.packages.values().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+    let anchors = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (&entry.package.id, format!("pkg-{}", i)))
+        .collect::<HashMap<_, _>>();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n");
+    html.push_str("<meta charset=\"utf-8\">\n");
+    html.push_str("<title>cargo-geiger report</title>\n");
+    writeln!(html, "<style>{}</style>", STYLE).unwrap();
+    html.push_str("</head>\n<body>\n<h1>cargo-geiger report</h1>\n");
+    write_legend(&mut html);
+    write_tree(&mut html, report, tree, &anchors);
+    write_package_table(&mut html, &entries, &anchors);
+    write_packages_without_metrics(&mut html, report);
+    write_package_details(&mut html, &entries, &anchors);
+    writeln!(html, "<script>{}</script>", SCRIPT).unwrap();
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn write_legend(html: &mut String) {
+    html.push_str("<h2>Legend</h2>\n<ul>\n");
+    for status in &[
+        CrateDetectionStatus::NoneDetectedForbidsUnsafe,
+        CrateDetectionStatus::NoneDetectedAllowsUnsafe,
+        CrateDetectionStatus::UnsafeDetected,
+    ] {
+        let description = match status {
+            CrateDetectionStatus::NoneDetectedForbidsUnsafe => {
+                "No unsafe usage found, declares #![forbid(unsafe_code)]"
+            }
+            CrateDetectionStatus::NoneDetectedAllowsUnsafe => {
+                "No unsafe usage found, missing #![forbid(unsafe_code)]"
+            }
+            CrateDetectionStatus::UnsafeDetected => "Unsafe usage found",
+        };
+        writeln!(html, "<li>{} {}</li>", status.emoji(), escape(description))
+            .unwrap();
+    }
+    html.push_str(
+        "<li><code>x/y</code>: x unsafe items used by the build, y unsafe \
+         items found in total</li>\n</ul>\n",
+    );
+}
+
+/// Nested `<details>` elements, one for every package with dependencies.
+fn write_tree(
+    html: &mut String,
+    report: &SafetyReport,
+    tree: &[(usize, PackageId)],
+    anchors: &HashMap<&PackageId, String>,
+) {
+    html.push_str("<h2>Dependency tree</h2>\n<ul class=\"tree\">\n");
+    let mut open_depths = Vec::new();
+    for (i, (depth, package_id)) in tree.iter().enumerate() {
+        while let Some(open_depth) = open_depths.last() {
+            if open_depth < depth {
+                break;
+            }
+            open_depths.pop();
+            html.push_str("</ul></details></li>\n");
+        }
+        let label = tree_label(report, package_id, anchors);
+        let has_children =
+            matches!(tree.get(i + 1), Some((next, _)) if next > depth);
+        if has_children {
+            writeln!(
+                html,
+                "<li><details open><summary>{}</summary><ul>",
+                label
+            )
+            .unwrap();
+            open_depths.push(*depth);
+        } else {
+            writeln!(html, "<li>{}</li>", label).unwrap();
+        }
+    }
+    for _ in open_depths {
+        html.push_str("</ul></details></li>\n");
+    }
+    html.push_str("</ul>\n");
+}
+
+fn tree_label(
+    report: &SafetyReport,
+    package_id: &PackageId,
+    anchors: &HashMap<&PackageId, String>,
+) -> String {
+    match report.packages.get(package_id) {
+        Some(entry) => {
+            let status =
+                CrateDetectionStatus::from_unsafe_info(&entry.unsafety);
+            format!(
+                "{} <a class=\"{}\" href=\"#{}\">{}</a>",
+                status.emoji(),
+                status_class(&status),
+                anchors[package_id],
+                escape(&package_label(package_id))
+            )
+        }
+        None => format!(
+            "<span class=\"no-metrics\">{}</span>",
+            escape(&package_label(package_id))
+        ),
+    }
+}
+
+fn write_package_table(
+    html: &mut String,
+    entries: &[&ReportEntry],
+    anchors: &HashMap<&PackageId, String>,
+) {
+    html.push_str(
+        "<h2>Packages</h2>\n<table class=\"sortable\">\n<thead><tr>\
+         <th>Package</th><th>Status</th><th>Functions</th>\
+         <th>Expressions</th><th>Impls</th><th>Traits</th><th>Methods</th>\
+         <th>Macros</th></tr></thead>\n<tbody>\n",
+    );
+    for entry in entries {
+        let id = &entry.package.id;
+        let status = CrateDetectionStatus::from_unsafe_info(&entry.unsafety);
+        let label = package_label(id);
+        write!(
+            html,
+            "<tr><td data-sort=\"{0}\"><a href=\"#{1}\">{0}</a></td>\
+             <td data-sort=\"{2}\">{3}</td>",
+            escape(&label),
+            anchors[id],
+            status_order(&status),
+            status.emoji()
+        )
+        .unwrap();
+        let used = &entry.unsafety.used;
+        let unused = &entry.unsafety.unused;
+        for (used, unused) in &[
+            (&used.functions, &unused.functions),
+            (&used.exprs, &unused.exprs),
+            (&used.item_impls, &unused.item_impls),
+            (&used.item_traits, &unused.item_traits),
+            (&used.methods, &unused.methods),
+            (&used.macros, &unused.macros),
+        ] {
+            html.push_str(&count_cell(used, unused));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+fn count_cell(used: &Count, unused: &Count) -> String {
+    format!(
+        "<td class=\"count\" data-sort=\"{0}\">{0}/{1}</td>",
+        used.unsafe_,
+        used.unsafe_ + unused.unsafe_
+    )
+}
+
+fn write_packages_without_metrics(html: &mut String, report: &SafetyReport) {
+    if report.packages_without_metrics.is_empty() {
+        return;
+    }
+    let mut package_ids =
+        report.packages_without_metrics.iter().collect::<Vec<_>>();
+    package_ids.sort();
+    html.push_str(
+        "<h2>Packages without metrics</h2>\n\
+         <p>These packages could not be scanned.</p>\n<ul>\n",
+    );
+    for package_id in package_ids {
+        writeln!(html, "<li>{}</li>", escape(&package_label(package_id)))
+            .unwrap();
+    }
+    html.push_str("</ul>\n");
+}
+
+/// One collapsed `<details>` per package, listing its unsafe findings grouped
+/// by file and the names of its unsafe functions.
+fn write_package_details(
+    html: &mut String,
+    entries: &[&ReportEntry],
+    anchors: &HashMap<&PackageId, String>,
+) {
+    html.push_str("<h2>Details</h2>\n");
+    for entry in entries {
+        let id = &entry.package.id;
+        let unsafety = &entry.unsafety;
+        let status = CrateDetectionStatus::from_unsafe_info(unsafety);
+        writeln!(
+            html,
+            "<details class=\"package\" id=\"{}\"><summary>{} {}</summary>",
+            anchors[id],
+            status.emoji(),
+            escape(&package_label(id))
+        )
+        .unwrap();
+        if unsafety.unsafe_locations.is_empty() {
+            html.push_str("<p>No unsafe code found.</p>\n");
+        }
+        let mut current_file = None;
+        for location in &unsafety.unsafe_locations {
+            if current_file != Some(&location.file) {
+                if current_file.is_some() {
+                    html.push_str("</tbody></table>\n");
+                }
+                current_file = Some(&location.file);
+                writeln!(
+                    html,
+                    "<h3><code>{}</code></h3>\n<table><thead><tr>\
+                     <th>Line</th><th>Kind</th><th>Item</th><th>Used</th>\
+                     </tr></thead><tbody>",
+                    escape(&location.file.to_string_lossy())
+                )
+                .unwrap();
+            }
+            let finding = &location.finding;
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td><code>{}</code></td>\
+                 <td>{}</td></tr>",
+                finding.start.line,
+                kind_name(finding.kind),
+                escape(&finding.item_path),
+                if location.used { "yes" } else { "no" }
+            )
+            .unwrap();
+        }
+        if current_file.is_some() {
+            html.push_str("</tbody></table>\n");
+        }
+        write_function_names(
+            html,
+            "Unsafe functions",
+            &unsafety.declared_unsafe_functions,
+        );
+        write_function_names(
+            html,
+            "Functions containing unsafe code",
+            &unsafety.contains_unsafe_functions,
+        );
+        html.push_str("</details>\n");
+    }
+}
+
+fn write_function_names(html: &mut String, heading: &str, names: &[String]) {
+    if names.is_empty() {
+        return;
+    }
+    writeln!(html, "<h3>{}</h3>\n<ul>", heading).unwrap();
+    for name in names {
+        writeln!(html, "<li><code>{}</code></li>", escape(name)).unwrap();
+    }
+    html.push_str("</ul>\n");
+}
+
+fn kind_name(kind: UnsafeKind) -> &'static str {
+    match kind {
+        UnsafeKind::Function => "function",
+        UnsafeKind::Expression => "expression",
+        UnsafeKind::ItemImpl => "impl",
+        UnsafeKind::ItemTrait => "trait",
+        UnsafeKind::Method => "method",
+        UnsafeKind::Macro => "macro",
+    }
+}
+
+fn status_class(status: &CrateDetectionStatus) -> &'static str {
+    match status {
+        CrateDetectionStatus::NoneDetectedForbidsUnsafe => "forbids-unsafe",
+        CrateDetectionStatus::NoneDetectedAllowsUnsafe => "allows-unsafe",
+        CrateDetectionStatus::UnsafeDetected => "unsafe-detected",
+    }
+}
+
+/// Sorts the status column from the safest to the least safe package.
+fn status_order(status: &CrateDetectionStatus) -> u8 {
+    match status {
+        CrateDetectionStatus::NoneDetectedForbidsUnsafe => 0,
+        CrateDetectionStatus::NoneDetectedAllowsUnsafe => 1,
+        CrateDetectionStatus::UnsafeDetected => 2,
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod html_tests {
+    use super::*;
+
+    use crate::test_fixtures::create_package_id;
+
+    use cargo_geiger_serde::{
+        CounterBlock, LineColumn, PackageInfo, UnsafeFinding, UnsafeInfo,
+        UnsafeLocation,
+    };
+    use rstest::*;
+    use std::path::PathBuf;
+
+    #[rstest(
+        input_str,
+        expected_escaped,
+        case("foo", "foo"),
+        case("<T as Foo<'a>>::bar", "&lt;T as Foo&lt;&#39;a&gt;&gt;::bar"),
+        case("\"a\" & b", "&quot;a&quot; &amp; b")
+    )]
+    fn escape_test(input_str: &str, expected_escaped: &str) {
+        assert_eq!(escape(input_str), expected_escaped);
+    }
+
+    #[rstest]
+    fn to_html_test() {
+        let foo = create_package_id("foo", "1.0.0");
+        let bar = create_package_id("bar", "1.0.0");
+        let baz = create_package_id("baz", "1.0.0");
+        let mut report = SafetyReport::default();
+        report.packages.insert(
+            foo.clone(),
+            ReportEntry {
+                package: PackageInfo::new(foo.clone()),
+                unsafety: UnsafeInfo {
+                    declared_unsafe_functions: vec![String::from(
+                        "Vec<T>::set_len",
+                    )],
+                    unsafe_locations: vec![UnsafeLocation {
+                        file: PathBuf::from("/ws/foo/src/lib.rs"),
+                        used: true,
+                        finding: UnsafeFinding {
+                            kind: UnsafeKind::Function,
+                            item_path: String::from("Vec<T>::set_len"),
+                            start: LineColumn { line: 7, column: 1 },
+                            end: LineColumn { line: 9, column: 2 },
+                        },
+                    }],
+                    used: CounterBlock {
+                        functions: Count {
+                            safe: 0,
+                            unsafe_: 1,
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                files: None,
+            },
+        );
+        report.packages.insert(
+            bar.clone(),
+            ReportEntry {
+                package: PackageInfo::new(bar.clone()),
+                unsafety: UnsafeInfo {
+                    forbids_unsafe: true,
+                    ..Default::default()
+                },
+                files: None,
+            },
+        );
+        report.packages_without_metrics.insert(baz.clone());
+        let tree = vec![(0, foo), (1, bar), (2, baz.clone()), (1, baz)];
+
+        let html = to_html(&report, &tree);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.ends_with("</html>\n"));
+        assert!(!html.contains("<link") && !html.contains(" src="));
+        assert_eq!(
+            html.matches("<details open>").count(),
+            html.matches("</ul></details></li>").count()
+        );
+        assert_eq!(html.matches("<details open>").count(), 2);
+        assert!(html.contains(
+            "☢️ <a class=\"unsafe-detected\" href=\"#pkg-1\">foo 1.0.0</a>"
+        ));
+        assert!(html.contains(
+            "<summary>🔒 <a class=\"forbids-unsafe\" href=\"#pkg-0\">bar \
+             1.0.0</a></summary>"
+        ));
+        assert!(html
+            .contains("<li><span class=\"no-metrics\">baz 1.0.0</span></li>"));
+        assert!(html.contains(
+            "<td class=\"count\" data-sort=\"1\">1/1</td>\
+             <td class=\"count\" data-sort=\"0\">0/0</td>"
+        ));
+        assert!(html.contains("<code>/ws/foo/src/lib.rs</code>"));
+        assert!(html.contains(
+            "<tr><td>7</td><td>function</td>\
+             <td><code>Vec&lt;T&gt;::set_len</code></td><td>yes</td></tr>"
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+//! Markdown summary for pull request comments, optionally limited to the
+//! packages using the most unsafe code and with the changes compared to a
+//! baseline report.
+
+use crate::diff::signed;
+use crate::format::package_label;
+use crate::format::table::total_package_counts::TotalPackageCounts;
+use crate::format::CrateDetectionStatus;
+
+use cargo_geiger_serde::{
+    diff_reports, Count, CountDiff, CounterBlock, CounterBlockDiff, ReportDiff,
+    ReportEntry, SafetyReport,
+};
+use std::fmt::Write;
+
+const COUNTER_HEADER: &str =
+    "Functions | Expressions | Impls | Traits | Methods | Macros";
+const COUNTER_ALIGNMENT: &str = "--: | --: | --: | --: | --: | --:";
+
+/// Renders the report as Markdown. Only the `top` packages using the most
+/// unsafe code are listed if set, the totals always cover all packages. The
+/// changes compared to `baseline` are added if given.
+pub fn to_markdown(
+    report: &SafetyReport,
+    top: Option<usize>,
+    baseline: Option<&SafetyReport>,
+) -> String {
+    let mut entries = report.packages.values().collect::<Vec<_>>();
+    entries.sort_by(|a, b| {
+        used_unsafe_count(b)
+            .cmp(&used_unsafe_count(a))
+            .then_with(|| a.package.id.cmp(&b.package.id))
+    });
+    let mut total_package_counts = TotalPackageCounts::new();
+    for entry in &entries {
+        total_package_counts.add_package(&entry.unsafety);
+    }
+    let package_count = entries.len();
+    if let Some(top) = top {
+        entries.truncate(top);
+    }
+
+    let mut markdown = String::from("## cargo-geiger\n\n");
+    writeln!(
+        markdown,
+        "| Package | Version | {} | Status |\n| :-- | :-- | {} | :-: |",
+        COUNTER_HEADER, COUNTER_ALIGNMENT
+    )
+    .unwrap();
+    for entry in &entries {
+        let id = &entry.package.id;
+        writeln!(
+            markdown,
+            "| {} | {} | {} | {} |",
+            id.name,
+            id.version,
+            counter_cells(&entry.unsafety.used, &entry.unsafety.unused),
+            CrateDetectionStatus::from_unsafe_info(&entry.unsafety).emoji()
+        )
+        .unwrap();
+    }
+    writeln!(
+        markdown,
+        "| **Total** | | {} | {} |\n",
+        counter_cells(
+            &total_package_counts.total_counter_block,
+            &total_package_counts.total_unused_counter_block
+        ),
+        total_package_counts.get_total_detection_status().emoji()
+    )
+    .unwrap();
+    if entries.len() < package_count {
+        writeln!(
+            markdown,
+            "Showing the {} of {} packages using the most unsafe code.\n",
+            entries.len(),
+            package_count
+        )
+        .unwrap();
+    }
+    writeln!(
+        markdown,
+        "{} {} forbid unsafe code, {} {} use no unsafe code, {} {} use \
+         unsafe code. `x/y`: x unsafe items used by the build, y found in \
+         total.",
+        CrateDetectionStatus::NoneDetectedForbidsUnsafe.emoji(),
+        total_package_counts.none_detected_forbids_unsafe,
+        CrateDetectionStatus::NoneDetectedAllowsUnsafe.emoji(),
+        total_package_counts.none_detected_allows_unsafe,
+        CrateDetectionStatus::UnsafeDetected.emoji(),
+        total_package_counts.unsafe_detected
+    )
+    .unwrap();
+    if let Some(baseline) = baseline {
+        markdown.push('\n');
+        write_diff(&mut markdown, &diff_reports(baseline, report));
+    }
+    markdown
+}
+
+/// The changes compared to the baseline, `x/y` being the change of the unsafe
+/// code used by the build and of all unsafe code found in the package.
+fn write_diff(markdown: &mut String, report_diff: &ReportDiff) {
+    markdown.push_str("### Changes since the baseline\n\n");
+    if report_diff.is_empty() {
+        markdown.push_str("No changes.\n");
+        return;
+    }
+    writeln!(
+        markdown,
+        "| | Package | {} |\n| :-: | :-- | {} |",
+        COUNTER_HEADER, COUNTER_ALIGNMENT
+    )
+    .unwrap();
+    let empty = CounterBlock::default();
+    for entry in &report_diff.added_packages {
+        writeln!(
+            markdown,
+            "| + | {} | {} |",
+            package_label(&entry.package.id),
+            diff_cells(
+                &CounterBlockDiff::new(&empty, &entry.unsafety.used),
+                &CounterBlockDiff::new(&empty, &entry.unsafety.unused)
+            )
+        )
+        .unwrap();
+    }
+    for entry in &report_diff.removed_packages {
+        writeln!(
+            markdown,
+            "| - | {} | {} |",
+            package_label(&entry.package.id),
+            diff_cells(
+                &CounterBlockDiff::new(&entry.unsafety.used, &empty),
+                &CounterBlockDiff::new(&entry.unsafety.unused, &empty)
+            )
+        )
+        .unwrap();
+    }
+    for package_diff in &report_diff.changed_packages {
+        let mut label = package_label(&package_diff.old_id);
+        if package_diff.is_version_change() {
+            write!(label, " → {}", package_diff.new_id.version).unwrap();
+        }
+        match (
+            package_diff.old_forbids_unsafe,
+            package_diff.new_forbids_unsafe,
+        ) {
+            (false, true) => label.push_str(" (now forbids unsafe)"),
+            (true, false) => label.push_str(" (no longer forbids unsafe)"),
+            _ => {}
+        }
+        writeln!(
+            markdown,
+            "| ~ | {} | {} |",
+            label,
+            diff_cells(&package_diff.used, &package_diff.unused)
+        )
+        .unwrap();
+    }
+}
+
+fn counter_cells(used: &CounterBlock, unused: &CounterBlock) -> String {
+    let fmt = |used: &Count, unused: &Count| {
+        format!("{}/{}", used.unsafe_, used.unsafe_ + unused.unsafe_)
+    };
+    [
+        fmt(&used.functions, &unused.functions),
+        fmt(&used.exprs, &unused.exprs),
+        fmt(&used.item_impls, &unused.item_impls),
+        fmt(&used.item_traits, &unused.item_traits),
+        fmt(&used.methods, &unused.methods),
+        fmt(&used.macros, &unused.macros),
+    ]
+    .join(" | ")
+}
+
+fn diff_cells(used: &CounterBlockDiff, unused: &CounterBlockDiff) -> String {
+    let fmt = |used: &CountDiff, unused: &CountDiff| {
+        format!(
+            "{}/{}",
+            signed(used.unsafe_),
+            signed(used.unsafe_ + unused.unsafe_)
+        )
+    };
+    [
+        fmt(&used.functions, &unused.functions),
+        fmt(&used.exprs, &unused.exprs),
+        fmt(&used.item_impls, &unused.item_impls),
+        fmt(&used.item_traits, &unused.item_traits),
+        fmt(&used.methods, &unused.methods),
+        fmt(&used.macros, &unused.macros),
+    ]
+    .join(" | ")
+}
+
+/// All unsafe items used by the build, to find the worst offenders.
+fn used_unsafe_count(entry: &ReportEntry) -> u64 {
+    let used = &entry.unsafety.used;
+    used.functions.unsafe_
+        + used.exprs.unsafe_
+        + used.item_impls.unsafe_
+        + used.item_traits.unsafe_
+        + used.methods.unsafe_
+        + used.macros.unsafe_
+}
+
+#[cfg(test)]
+mod markdown_tests {
+    use super::*;
+
+    use crate::test_fixtures::{create_report, create_report_entry};
+
+    use rstest::*;
+
+    #[rstest(
+        input_top,
+        expected_markdown,
+        case(
+            None,
+            "## cargo-geiger\n\n\
+             | Package | Version | Functions | Expressions | Impls | Traits | \
+             Methods | Macros | Status |\n\
+             | :-- | :-- | --: | --: | --: | --: | --: | --: | :-: |\n\
+             | foo | 1.0.0 | 0/0 | 5/6 | 0/0 | 0/0 | 0/0 | 0/0 | ☢️ |\n\
+             | bar | 0.1.0 | 0/0 | 2/3 | 0/0 | 0/0 | 0/0 | 0/0 | ☢️ |\n\
+             | baz | 0.2.0 | 0/0 | 0/1 | 0/0 | 0/0 | 0/0 | 0/0 | 🔒 |\n\
+             | **Total** | | 0/0 | 7/10 | 0/0 | 0/0 | 0/0 | 0/0 | ☢️ |\n\n\
+             🔒 1 forbid unsafe code, ❓ 0 use no unsafe code, ☢️ 2 use \
+             unsafe code. `x/y`: x unsafe items used by the build, y found in \
+             total.\n"
+        ),
+        case(
+            Some(1),
+            "## cargo-geiger\n\n\
+             | Package | Version | Functions | Expressions | Impls | Traits | \
+             Methods | Macros | Status |\n\
+             | :-- | :-- | --: | --: | --: | --: | --: | --: | :-: |\n\
+             | foo | 1.0.0 | 0/0 | 5/6 | 0/0 | 0/0 | 0/0 | 0/0 | ☢️ |\n\
+             | **Total** | | 0/0 | 7/10 | 0/0 | 0/0 | 0/0 | 0/0 | ☢️ |\n\n\
+             Showing the 1 of 3 packages using the most unsafe code.\n\n\
+             🔒 1 forbid unsafe code, ❓ 0 use no unsafe code, ☢️ 2 use \
+             unsafe code. `x/y`: x unsafe items used by the build, y found in \
+             total.\n"
+        )
+    )]
+    fn to_markdown_test(input_top: Option<usize>, expected_markdown: &str) {
+        let report = create_report(vec![
+            create_report_entry("bar", "0.1.0", 2, 1, false),
+            create_report_entry("baz", "0.2.0", 0, 1, true),
+            create_report_entry("foo", "1.0.0", 5, 1, false),
+        ]);
+
+        assert_eq!(to_markdown(&report, input_top, None), expected_markdown);
+    }
+
+    #[rstest]
+    fn to_markdown_test_baseline() {
+        let baseline = create_report(vec![
+            create_report_entry("bar", "0.1.0", 1, 1, false),
+            create_report_entry("foo", "1.0.0", 2, 1, true),
+        ]);
+        let report = create_report(vec![
+            create_report_entry("baz", "0.2.0", 3, 1, false),
+            create_report_entry("foo", "1.1.0", 2, 1, false),
+        ]);
+
+        let markdown = to_markdown(&report, None, Some(&baseline));
+
+        assert!(markdown.ends_with(
+            "### Changes since the baseline\n\n\
+             | | Package | Functions | Expressions | Impls | Traits | \
+             Methods | Macros |\n\
+             | :-: | :-- | --: | --: | --: | --: | --: | --: |\n\
+             | + | baz 0.2.0 | 0/0 | +3/+4 | 0/0 | 0/0 | 0/0 | 0/0 |\n\
+             | - | bar 0.1.0 | 0/0 | -1/-2 | 0/0 | 0/0 | 0/0 | 0/0 |\n\
+             | ~ | foo 1.0.0 → 1.1.0 (no longer forbids unsafe) | 0/0 | 0/0 | \
+             0/0 | 0/0 | 0/0 | 0/0 |\n"
+        ));
+        assert!(to_markdown(&report, None, Some(&report))
+            .ends_with("### Changes since the baseline\n\nNo changes.\n"));
+    }
+}
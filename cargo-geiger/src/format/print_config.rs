@@ -1,4 +1,5 @@
 use crate::args::Args;
+use crate::exit_code;
 use crate::format::pattern::Pattern;
 use crate::format::{Charset, CrateDetectionStatus, FormatError};
 
@@ -7,6 +8,7 @@ use cargo::util::errors::CliError;
 use colored::Colorize;
 use geiger::IncludeTests;
 use petgraph::EdgeDirection;
+use std::str::FromStr;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Prefix {
@@ -17,7 +19,28 @@ pub enum Prefix {
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum OutputFormat {
+    CycloneDx,
+    Dot,
+    Html,
     Json,
+    Markdown,
+    Sarif,
+}
+
+impl FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<OutputFormat, &'static str> {
+        match s {
+            "cyclonedx" => Ok(OutputFormat::CycloneDx),
+            "dot" => Ok(OutputFormat::Dot),
+            "html" => Ok(OutputFormat::Html),
+            "json" => Ok(OutputFormat::Json),
+            "markdown" => Ok(OutputFormat::Markdown),
+            "sarif" => Ok(OutputFormat::Sarif),
+            _ => Err("invalid output format"),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -34,6 +57,10 @@ pub struct PrintConfig {
     pub format: Pattern,
 
     pub include_tests: IncludeTests,
+
+    /// The number of files scanned in parallel.
+    pub jobs: usize,
+
     pub prefix: Prefix,
     pub output_format: Option<OutputFormat>,
     pub verbosity: Verbosity,
@@ -56,7 +83,7 @@ impl PrintConfig {
                     message: e.to_string(),
                 })
                 .into(),
-                1,
+                exit_code::ERROR,
             )
         })?;
 
@@ -66,6 +93,8 @@ impl PrintConfig {
             IncludeTests::No
         };
 
+        let jobs = args.jobs.unwrap_or_else(num_cpus::get).max(1);
+
         let prefix = if args.prefix_depth {
             Prefix::Depth
         } else if args.no_indent {
@@ -87,6 +116,7 @@ impl PrintConfig {
             direction,
             format,
             include_tests,
+            jobs,
             output_format: args.output_format,
             prefix,
             verbosity,
@@ -112,6 +142,23 @@ mod print_config_tests {
     use colored::ColoredString;
     use rstest::*;
 
+    #[rstest]
+    fn output_format_from_str_test() {
+        assert_eq!(
+            OutputFormat::from_str("cyclonedx"),
+            Ok(OutputFormat::CycloneDx)
+        );
+        assert_eq!(OutputFormat::from_str("dot"), Ok(OutputFormat::Dot));
+        assert_eq!(OutputFormat::from_str("html"), Ok(OutputFormat::Html));
+        assert_eq!(OutputFormat::from_str("json"), Ok(OutputFormat::Json));
+        assert_eq!(
+            OutputFormat::from_str("markdown"),
+            Ok(OutputFormat::Markdown)
+        );
+        assert_eq!(OutputFormat::from_str("sarif"), Ok(OutputFormat::Sarif));
+        assert_eq!(OutputFormat::from_str("xml"), Err("invalid output format"));
+    }
+
     #[rstest(
         input_invert_bool,
         expected_edge_direction,
@@ -160,6 +207,28 @@ mod print_config_tests {
         );
     }
 
+    #[rstest(
+        input_jobs,
+        expected_jobs,
+        case(Some(4), 4),
+        case(Some(0), 1),
+        case(None, num_cpus::get())
+    )]
+    fn print_config_new_test_jobs(
+        input_jobs: Option<usize>,
+        expected_jobs: usize,
+    ) {
+        let args = Args {
+            jobs: input_jobs,
+            ..Default::default()
+        };
+
+        let print_config_result = PrintConfig::new(&args);
+
+        assert!(print_config_result.is_ok());
+        assert_eq!(print_config_result.unwrap().jobs, expected_jobs);
+    }
+
     #[rstest(
         input_prefix_depth_bool,
         input_no_indent_bool,
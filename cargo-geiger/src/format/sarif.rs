@@ -0,0 +1,265 @@
+//! SARIF 2.1.0 output for code scanning tools, one result per unsafe finding.
+
+use crate::format::package_label;
+
+use cargo_geiger_serde::{
+    ReportEntry, SafetyReport, UnsafeKind, UnsafeLocation,
+};
+use serde_json::{json, Value};
+use std::path::Path;
+use url::Url;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Base id for the paths of files inside the workspace.
+const SRCROOT: &str = "%SRCROOT%";
+
+/// The rule of every finding kind, `(kind, id, description)`. The index of a
+/// rule is its `ruleIndex` in the results.
+const RULES: [(UnsafeKind, &str, &str); 6] = [
+    (UnsafeKind::Function, "unsafe-fn", "Unsafe function"),
+    (
+        UnsafeKind::Expression,
+        "unsafe-block",
+        "Expression in an unsafe block or unsafe function",
+    ),
+    (
+        UnsafeKind::ItemImpl,
+        "unsafe-impl",
+        "Unsafe trait implementation",
+    ),
+    (UnsafeKind::ItemTrait, "unsafe-trait", "Unsafe trait"),
+    (UnsafeKind::Method, "unsafe-method", "Unsafe method"),
+    (
+        UnsafeKind::Macro,
+        "unsafe-macro",
+        "Macro invocation or definition containing unsafe code",
+    ),
+];
+
+/// Converts the report to a SARIF log with a single run. Files inside
+/// `workspace_root` are relative to `%SRCROOT%`, other files such as those of
+/// registry dependencies get absolute `file://` URIs.
+pub fn to_sarif(report: &SafetyReport, workspace_root: &Path) -> Value {
+    let mut entries = report.packages.values().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+    let results =
+        entries
+            .into_iter()
+            .flat_map(|entry| {
+                entry.unsafety.unsafe_locations.iter().map(move |location| {
+                    to_result(entry, location, workspace_root)
+                })
+            })
+            .collect::<Vec<_>>();
+    let rules = RULES
+        .iter()
+        .map(|(_, id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut run = json!({
+        "tool": {
+            "driver": {
+                "name": "cargo-geiger",
+                "version": env!("CARGO_PKG_VERSION"),
+                "informationUri":
+                    "https://github.com/rust-secure-code/cargo-geiger",
+                "rules": rules,
+            }
+        },
+        "results": results,
+    });
+    if let Ok(root_url) = Url::from_directory_path(workspace_root) {
+        run["originalUriBaseIds"] =
+            json!({ SRCROOT: { "uri": root_url.as_str() } });
+    }
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [run],
+    })
+}
+
+fn to_result(
+    entry: &ReportEntry,
+    location: &UnsafeLocation,
+    workspace_root: &Path,
+) -> Value {
+    let finding = &location.finding;
+    let rule_index = RULES
+        .iter()
+        .position(|(kind, _, _)| *kind == finding.kind)
+        .unwrap();
+    let (_, rule_id, description) = RULES[rule_index];
+    let message = if finding.item_path.is_empty() {
+        String::from(description)
+    } else {
+        format!("{} in `{}`", description, finding.item_path)
+    };
+    json!({
+        "ruleId": rule_id,
+        "ruleIndex": rule_index,
+        // Unsafe code that is not used by the build is only informational.
+        "level": if location.used { "warning" } else { "note" },
+        "message": { "text": message },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": artifact_location(
+                    &location.file,
+                    workspace_root,
+                ),
+                "region": {
+                    "startLine": finding.start.line,
+                    "startColumn": finding.start.column,
+                    "endLine": finding.end.line,
+                    "endColumn": finding.end.column,
+                },
+            }
+        }],
+        "properties": {
+            "package": package_label(&entry.package.id),
+            "used": location.used,
+        },
+    })
+}
+
+fn artifact_location(file: &Path, workspace_root: &Path) -> Value {
+    match file.strip_prefix(workspace_root) {
+        Ok(relative_path) => json!({
+            "uri": relative_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            "uriBaseId": SRCROOT,
+        }),
+        Err(_) => match Url::from_file_path(file) {
+            Ok(url) => json!({ "uri": url.as_str() }),
+            Err(()) => json!({ "uri": file.to_string_lossy() }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod sarif_tests {
+    use super::*;
+
+    use cargo_geiger_serde::{
+        LineColumn, PackageId, PackageInfo, Source, UnsafeFinding, UnsafeInfo,
+    };
+    use rstest::*;
+    use semver::Version;
+    use std::path::PathBuf;
+
+    #[rstest]
+    fn to_sarif_test() {
+        let id = PackageId {
+            name: String::from("foo"),
+            version: Version::parse("1.0.0").unwrap(),
+            source: Source::Path(Url::parse("file:///ws/foo").unwrap()),
+        };
+        let mut report = SafetyReport::default();
+        report.packages.insert(
+            id.clone(),
+            ReportEntry {
+                package: PackageInfo::new(id),
+                unsafety: UnsafeInfo {
+                    unsafe_locations: vec![
+                        create_location(
+                            "/ws/foo/src/lib.rs",
+                            true,
+                            UnsafeKind::Expression,
+                            "bar",
+                        ),
+                        create_location(
+                            "/registry/baz/src/lib.rs",
+                            false,
+                            UnsafeKind::ItemImpl,
+                            "",
+                        ),
+                    ],
+                    ..Default::default()
+                },
+                files: None,
+            },
+        );
+
+        let sarif = to_sarif(&report, Path::new("/ws"));
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][1]["id"], "unsafe-block");
+        assert_eq!(run["originalUriBaseIds"][SRCROOT]["uri"], "file:///ws/");
+        assert_eq!(
+            run["results"],
+            json!([
+                {
+                    "ruleId": "unsafe-block",
+                    "ruleIndex": 1,
+                    "level": "warning",
+                    "message": {
+                        "text": "Expression in an unsafe block or unsafe \
+                                 function in `bar`"
+                    },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": {
+                                "uri": "foo/src/lib.rs",
+                                "uriBaseId": "%SRCROOT%",
+                            },
+                            "region": {
+                                "startLine": 3,
+                                "startColumn": 5,
+                                "endLine": 4,
+                                "endColumn": 6,
+                            },
+                        }
+                    }],
+                    "properties": { "package": "foo 1.0.0", "used": true },
+                },
+                {
+                    "ruleId": "unsafe-impl",
+                    "ruleIndex": 2,
+                    "level": "note",
+                    "message": { "text": "Unsafe trait implementation" },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": {
+                                "uri": "file:///registry/baz/src/lib.rs",
+                            },
+                            "region": {
+                                "startLine": 3,
+                                "startColumn": 5,
+                                "endLine": 4,
+                                "endColumn": 6,
+                            },
+                        }
+                    }],
+                    "properties": { "package": "foo 1.0.0", "used": false },
+                },
+            ])
+        );
+    }
+
+    fn create_location(
+        file: &str,
+        used: bool,
+        kind: UnsafeKind,
+        item_path: &str,
+    ) -> UnsafeLocation {
+        UnsafeLocation {
+            file: PathBuf::from(file),
+            used,
+            finding: UnsafeFinding {
+                kind,
+                item_path: String::from(item_path),
+                start: LineColumn { line: 3, column: 5 },
+                end: LineColumn { line: 4, column: 6 },
+            },
+        }
+    }
+}
@@ -1,11 +1,12 @@
+pub mod total_package_counts;
+
 mod handle_text_tree_line;
-mod total_package_counts;
 
 use crate::format::emoji_symbols::EmojiSymbols;
 use crate::format::print_config::{colorize, PrintConfig};
 use crate::format::CrateDetectionStatus;
 use crate::mapping::CargoMetadataParameters;
-use crate::scan::GeigerContext;
+use crate::scan::{GeigerContext, RsFilesUsed};
 use crate::tree::TextTreeLine;
 
 use handle_text_tree_line::{
@@ -16,17 +17,17 @@ use total_package_counts::TotalPackageCounts;
 
 use cargo_geiger_serde::{Count, CounterBlock};
 use std::collections::HashSet;
-use std::path::PathBuf;
 
 // TODO: use a table library, or factor the tableness out in a smarter way. This
 // is probably easier now when the tree formatting is separated from the tree
 // traversal.
-pub const UNSAFE_COUNTERS_HEADER: [&str; 6] = [
+pub const UNSAFE_COUNTERS_HEADER: [&str; 7] = [
     "Functions ",
     "Expressions ",
     "Impls ",
     "Traits ",
     "Methods ",
+    "Macros ",
     "Dependency",
 ];
 
@@ -60,6 +61,7 @@ pub fn create_table_from_text_tree_lines(
             TextTreeLine::Package {
                 id: package_id,
                 tree_vines,
+                ..
             } => handle_text_tree_line_package(
                 cargo_metadata_parameters,
                 &emoji_symbols,
@@ -93,7 +95,7 @@ pub fn create_table_from_text_tree_lines(
 pub struct TableParameters<'a> {
     pub geiger_context: &'a GeigerContext,
     pub print_config: &'a PrintConfig,
-    pub rs_files_used: &'a HashSet<PathBuf>,
+    pub rs_files_used: &'a RsFilesUsed,
 }
 
 fn table_footer(
@@ -105,12 +107,13 @@ fn table_footer(
         format!("{}/{}", used.unsafe_, used.unsafe_ + not_used.unsafe_)
     };
     let output = format!(
-        "{: <10} {: <12} {: <6} {: <7} {: <7}",
+        "{: <10} {: <12} {: <6} {: <7} {: <8} {: <6}",
         fmt(&used.functions, &not_used.functions),
         fmt(&used.exprs, &not_used.exprs),
         fmt(&used.item_impls, &not_used.item_impls),
         fmt(&used.item_traits, &not_used.item_traits),
         fmt(&used.methods, &not_used.methods),
+        fmt(&used.macros, &not_used.macros),
     );
     colorize(output, &status)
 }
@@ -120,12 +123,13 @@ fn table_row(used: &CounterBlock, not_used: &CounterBlock) -> String {
         format!("{}/{}", used.unsafe_, used.unsafe_ + not_used.unsafe_)
     };
     format!(
-        "{: <10} {: <12} {: <6} {: <7} {: <7}",
+        "{: <10} {: <12} {: <6} {: <7} {: <8} {: <6}",
         fmt(&used.functions, &not_used.functions),
         fmt(&used.exprs, &not_used.exprs),
         fmt(&used.item_impls, &not_used.item_impls),
         fmt(&used.item_traits, &not_used.item_traits),
         fmt(&used.methods, &not_used.methods),
+        fmt(&used.macros, &not_used.macros),
     )
 }
 
@@ -151,7 +155,7 @@ mod table_tests {
     use geiger::RsFileMetrics;
     use rstest::*;
     use std::collections::HashMap;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
     use strum::IntoEnumIterator;
 
     #[rstest]
@@ -159,8 +163,9 @@ mod table_tests {
         let used_counter_block = create_counter_block();
         let not_used_counter_block = create_counter_block();
 
-        let expected_line =
-            String::from("2/4        4/8          6/12   8/16    10/20  ");
+        let expected_line = String::from(
+            "2/4        4/8          6/12   8/16    10/20    12/24 ",
+        );
 
         for crate_detection_status in CrateDetectionStatus::iter() {
             let table_footer = table_footer(
@@ -204,16 +209,20 @@ mod table_tests {
         .iter()
         .cloned()
         .collect();
-        let unsafety = unsafe_stats(&package_metrics, &rs_files_used);
+        let unsafety =
+            unsafe_stats(&package_metrics, &rs_files_used, Path::new(""));
 
         let table_row = table_row(&unsafety.used, &unsafety.unused);
-        assert_eq!(table_row, "4/6        8/12         12/18  16/24   20/30  ");
+        assert_eq!(
+            table_row,
+            "4/6        8/12         12/18  16/24   20/30    24/36 "
+        );
     }
 
     #[rstest]
     fn table_row_empty_test() {
         let empty_table_row = table_row_empty();
-        assert_eq!(empty_table_row.len(), 51);
+        assert_eq!(empty_table_row.len(), 59);
     }
 
     #[rstest(
@@ -255,6 +264,7 @@ mod table_tests {
                 forbids_unsafe,
                 declared_unsafe_functions: Vec::new(),
                 contains_unsafe_functions: Vec::new(),
+                unsafe_findings: Vec::new(),
             },
             is_crate_entry_point,
         }
@@ -282,6 +292,10 @@ mod table_tests {
                 safe: 9,
                 unsafe_: 10,
             },
+            macros: Count {
+                safe: 11,
+                unsafe_: 12,
+            },
         }
     }
 }
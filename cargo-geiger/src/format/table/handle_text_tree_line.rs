@@ -1,7 +1,9 @@
 use crate::format::emoji_symbols::EmojiSymbols;
 use crate::format::print_config::colorize;
 use crate::format::{get_kind_group_name, CrateDetectionStatus, SymbolKind};
-use crate::mapping::CargoMetadataParameters;
+use crate::mapping::{
+    CargoMetadataParameters, GetRoot, ToCargoMetadataPackage,
+};
 use crate::scan::unsafe_stats;
 
 use super::total_package_counts::TotalPackageCounts;
@@ -53,12 +55,23 @@ pub fn handle_text_tree_line_package(
         Some(m) => m,
         None => {
             *handle_package_parameters.warning_count += package_is_new as u64;
-            eprintln!("WARNING: No metrics found for package: {}", package_id);
+            eprintln!(
+                "WARNING: No metrics found for package: {}, none of its \
+                 source files could be scanned",
+                package_id
+            );
             return;
         }
     };
-    let unsafe_info =
-        unsafe_stats(package_metrics, table_parameters.rs_files_used);
+    let package_root = package_id
+        .to_cargo_metadata_package(cargo_metadata_parameters.metadata)
+        .map(|package| package.get_root())
+        .unwrap_or_default();
+    let unsafe_info = unsafe_stats(
+        package_metrics,
+        &table_parameters.rs_files_used.used_by_package(&package_id),
+        &package_root,
+    );
     if package_is_new {
         handle_package_parameters
             .total_package_counts
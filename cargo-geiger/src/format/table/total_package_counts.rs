@@ -1,6 +1,6 @@
 use crate::format::CrateDetectionStatus;
 
-use cargo_geiger_serde::CounterBlock;
+use cargo_geiger_serde::{CounterBlock, UnsafeInfo};
 
 pub struct TotalPackageCounts {
     pub none_detected_forbids_unsafe: i32,
@@ -21,6 +21,21 @@ impl TotalPackageCounts {
         }
     }
 
+    /// Adds a package of a report, every package should only be added once.
+    pub fn add_package(&mut self, unsafe_info: &UnsafeInfo) {
+        self.total_counter_block += unsafe_info.used.clone();
+        self.total_unused_counter_block += unsafe_info.unused.clone();
+        match CrateDetectionStatus::from_unsafe_info(unsafe_info) {
+            CrateDetectionStatus::NoneDetectedForbidsUnsafe => {
+                self.none_detected_forbids_unsafe += 1
+            }
+            CrateDetectionStatus::NoneDetectedAllowsUnsafe => {
+                self.none_detected_allows_unsafe += 1
+            }
+            CrateDetectionStatus::UnsafeDetected => self.unsafe_detected += 1,
+        }
+    }
+
     pub fn get_total_detection_status(&self) -> CrateDetectionStatus {
         match (
             self.none_detected_forbids_unsafe > 0,
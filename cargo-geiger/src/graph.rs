@@ -1,14 +1,14 @@
 use crate::args::{Args, DepsArgs, TargetArgs};
 use crate::cli::get_cfgs;
 use crate::mapping::{
-    CargoMetadataParameters, DepsNotReplaced, MatchesIgnoringSource,
+    CargoMetadataParameters, GetResolveNodeFromCargoMetadataPackageId,
 };
 
 use cargo::core::Workspace;
 use cargo::util::interning::InternedString;
 use cargo::util::CargoResult;
 use cargo::Config;
-use cargo_metadata::{DependencyKind, PackageId};
+use cargo_metadata::{DependencyKind, NodeDep, PackageId};
 use cargo_platform::{Cfg, Platform};
 use petgraph::graph::NodeIndex;
 use std::collections::hash_map::Entry;
@@ -45,12 +45,13 @@ pub struct Graph {
 
 // Almost unmodified compared to the original in cargo-tree, should be fairly
 // simple to move this and the dependency graph structure out to a library.
-/// Function to build a graph of packages dependencies
+/// Function to build a graph of packages dependencies, starting from every
+/// package in `root_package_ids`
 pub fn build_graph<'a>(
     args: &Args,
     cargo_metadata_parameters: &'a CargoMetadataParameters,
     config: &Config,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     workspace: &Workspace,
 ) -> CargoResult<Graph> {
     let config_host = config.load_global_rustc(Some(&workspace))?.host;
@@ -65,12 +66,13 @@ pub fn build_graph<'a>(
         graph: petgraph::Graph::new(),
         nodes: HashMap::new(),
     };
-    graph.nodes.insert(
-        root_package_id.clone(),
-        graph.graph.add_node(root_package_id.clone()),
-    );
-
-    let mut pending_packages = vec![root_package_id];
+    let mut pending_packages = Vec::new();
+    for root_package_id in root_package_ids {
+        if let Entry::Vacant(e) = graph.nodes.entry(root_package_id.clone()) {
+            e.insert(graph.graph.add_node(root_package_id.clone()));
+            pending_packages.push(root_package_id.clone());
+        }
+    }
 
     let graph_configuration = GraphConfiguration {
         target,
@@ -98,7 +100,7 @@ struct GraphConfiguration<'a> {
 }
 
 fn add_graph_node_if_not_present_and_edge(
-    dependency: &cargo_metadata::Dependency,
+    dependency_kind: DependencyKind,
     dependency_package_id: PackageId,
     graph: &mut Graph,
     index: NodeIndex,
@@ -114,9 +116,11 @@ fn add_graph_node_if_not_present_and_edge(
         };
     graph
         .graph
-        .add_edge(index, dependency_index, dependency.kind);
+        .add_edge(index, dependency_index, dependency_kind);
 }
 
+/// Adds the dependencies of the package as resolved by cargo, which only
+/// includes optional dependencies that are enabled by the selected features.
 fn add_package_dependencies_to_graph(
     cargo_metadata_parameters: &CargoMetadataParameters,
     package_id: PackageId,
@@ -125,49 +129,19 @@ fn add_package_dependencies_to_graph(
     pending_packages: &mut Vec<PackageId>,
 ) {
     let index = graph.nodes[&package_id];
-    let package = cargo_metadata_parameters
-        .krates
-        .node_for_kid(&package_id)
-        .unwrap()
-        .krate
-        .clone();
-
-    for (dependency_package_id, _) in cargo_metadata_parameters
+    let node = match cargo_metadata_parameters
         .metadata
-        .deps_not_replaced(package_id)
+        .get_resolve_node_from_cargo_metadata_package_id(&package_id)
     {
-        let dependency_iterator = package
-            .dependencies
-            .iter()
-            .filter(|d| {
-                d.matches_ignoring_source(
-                    cargo_metadata_parameters.krates,
-                    dependency_package_id.clone(),
-                )
-            })
-            .filter(|d| graph_configuration.extra_deps.allows(d.kind))
-            .filter(|d| {
-                d.target
-                    .as_ref()
-                    .and_then(|p| {
-                        graph_configuration.target.map(|t| {
-                            match graph_configuration.cfgs {
-                                None => false,
-                                Some(cfgs) => {
-                                    (Platform::from_str(p.repr.as_str()))
-                                        .unwrap()
-                                        .matches(t, cfgs)
-                                }
-                            }
-                        })
-                    })
-                    .unwrap_or(true)
-            });
+        Some(node) => node,
+        None => return,
+    };
 
-        for dependency in dependency_iterator {
+    for node_dep in &node.deps {
+        for dependency_kind in dependency_kinds(node_dep, graph_configuration) {
             add_graph_node_if_not_present_and_edge(
-                dependency,
-                dependency_package_id.clone(),
+                dependency_kind,
+                node_dep.pkg.clone(),
                 graph,
                 index,
                 pending_packages,
@@ -176,6 +150,45 @@ fn add_package_dependencies_to_graph(
     }
 }
 
+/// The kinds of `node_dep` that are part of the graph, each listed once.
+fn dependency_kinds(
+    node_dep: &NodeDep,
+    graph_configuration: &GraphConfiguration,
+) -> Vec<DependencyKind> {
+    // Cargo older than 1.41 does not report the kinds, the dependency is
+    // taken to be a normal one on every platform then.
+    if node_dep.dep_kinds.is_empty() {
+        return vec![DependencyKind::Normal];
+    }
+    let mut dependency_kinds = Vec::new();
+    for dep_kind_info in node_dep
+        .dep_kinds
+        .iter()
+        .filter(|d| graph_configuration.extra_deps.allows(d.kind))
+        .filter(|d| {
+            d.target
+                .as_ref()
+                .and_then(|p| {
+                    graph_configuration.target.map(
+                        |t| match graph_configuration.cfgs {
+                            None => false,
+                            Some(cfgs) => (Platform::from_str(p.repr.as_str()))
+                                .unwrap()
+                                .matches(t, cfgs),
+                        },
+                    )
+                })
+                .unwrap_or(true)
+        })
+    {
+        // The same kind can be listed once per target platform.
+        if !dependency_kinds.contains(&dep_kind_info.kind) {
+            dependency_kinds.push(dep_kind_info.kind);
+        }
+    }
+    dependency_kinds
+}
+
 fn build_graph_prerequisites<'a>(
     config_host: &'a InternedString,
     deps_args: &'a DepsArgs,
@@ -231,6 +244,47 @@ mod graph_tests {
         );
     }
 
+    #[rstest(
+        input_dep_kinds,
+        input_extra_deps,
+        expected_dependency_kinds,
+        case("[]", ExtraDeps::NoMore, vec![DependencyKind::Normal]),
+        case(
+            r#"[{ "kind": null, "target": null },
+                { "kind": "dev", "target": null }]"#,
+            ExtraDeps::NoMore,
+            vec![DependencyKind::Normal]
+        ),
+        case(
+            r#"[{ "kind": "build", "target": null },
+                { "kind": "dev", "target": null }]"#,
+            ExtraDeps::All,
+            vec![DependencyKind::Build, DependencyKind::Development]
+        ),
+        case(r#"[{ "kind": "dev", "target": null }]"#, ExtraDeps::Build, vec![])
+    )]
+    fn dependency_kinds_test(
+        input_dep_kinds: &str,
+        input_extra_deps: ExtraDeps,
+        expected_dependency_kinds: Vec<DependencyKind>,
+    ) {
+        let node_dep = serde_json::from_str::<NodeDep>(&format!(
+            r#"{{ "name": "foo", "pkg": "foo 1.0.0", "dep_kinds": {} }}"#,
+            input_dep_kinds
+        ))
+        .unwrap();
+        let graph_configuration = GraphConfiguration {
+            target: None,
+            cfgs: None,
+            extra_deps: input_extra_deps,
+        };
+
+        assert_eq!(
+            dependency_kinds(&node_dep, &graph_configuration),
+            expected_dependency_kinds
+        );
+    }
+
     #[rstest(
         input_deps_args,
         expected_extra_deps,
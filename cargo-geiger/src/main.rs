@@ -12,16 +12,24 @@ extern crate strum_macros;
 
 mod args;
 mod cli;
+mod diff;
+mod exit_code;
 mod format;
 mod graph;
 mod mapping;
 mod scan;
+#[cfg(test)]
+mod test_fixtures;
 mod tree;
 
 use crate::args::{Args, HELP};
-use crate::cli::{get_cargo_metadata, get_krates, get_workspace};
+use crate::cli::{
+    get_cargo_metadata, get_krates, get_workspace,
+    get_workspace_member_package_ids, report_schema,
+};
+use crate::diff::diff_report_files;
 use crate::graph::build_graph;
-use crate::mapping::{CargoMetadataParameters, QueryResolve};
+use crate::mapping::{CargoMetadataParameters, QueryResolve, RegistryNames};
 use crate::scan::scan;
 
 use cargo::core::shell::Shell;
@@ -39,58 +47,94 @@ fn real_main(args: &Args, config: &mut Config) -> CliResult {
         println!("{}", HELP);
         return Ok(());
     }
+    if args.print_schema {
+        println!("{}", report_schema(args.forbid_only));
+        return Ok(());
+    }
 
     args.update_config(config)?;
 
+    if let Some((old_path, new_path)) = &args.diff_paths {
+        return diff_report_files(old_path, new_path, args.output_format);
+    }
+
     let cargo_metadata = get_cargo_metadata(&args, config)?;
     let krates = get_krates(&cargo_metadata)?;
 
+    let registry_names = RegistryNames::new(config)?;
+
     let cargo_metadata_parameters = CargoMetadataParameters {
         metadata: &cargo_metadata,
         krates: &krates,
+        registry_names: &registry_names,
     };
 
     let workspace = get_workspace(config, args.manifest_path.clone())?;
 
-    let cargo_metadata_root_package_id;
-
-    if let Some(cargo_metadata_root_package) = cargo_metadata.root_package() {
-        cargo_metadata_root_package_id = cargo_metadata_root_package.id.clone();
+    let root_package_ids = if args.workspace {
+        if !args.package.is_empty() {
+            return Err(CliError::new(
+                anyhow::anyhow!(
+                    "--package can not be used together with --workspace"
+                ),
+                exit_code::ERROR,
+            ));
+        }
+        get_workspace_member_package_ids(
+            config,
+            &args.exclude,
+            &krates,
+            &cargo_metadata,
+        )?
+    } else if !args.exclude.is_empty() {
+        return Err(CliError::new(
+            anyhow::anyhow!(
+                "--exclude can only be used together with --workspace"
+            ),
+            exit_code::ERROR,
+        ));
+    } else if !args.package.is_empty() {
+        let mut root_package_ids = Vec::new();
+        for package_query in &args.package {
+            let package_id =
+                krates.query_resolve(package_query).map_err(|e| {
+                    CliError::new(anyhow::Error::new(e), exit_code::ERROR)
+                })?;
+            if !root_package_ids.contains(&package_id) {
+                root_package_ids.push(package_id);
+            }
+        }
+        root_package_ids
+    } else if let Some(cargo_metadata_root_package) =
+        cargo_metadata.root_package()
+    {
+        vec![cargo_metadata_root_package.id.clone()]
     } else {
         eprintln!(
-            "manifest path `{}` is a virtual manifest, but this command requires running against an actual package in this workspace",
+            "manifest path `{}` is a virtual manifest, but this command requires running against an actual package in this workspace, use --workspace to scan all members or --package to select one",
             match args.manifest_path.clone() {
                 Some(path) => path,
                 None => important_paths::find_root_manifest_for_wd(config.cwd())?,
             }.as_os_str().to_str().unwrap()
         );
 
-        return CliResult::Err(CliError::code(1));
-    }
+        return CliResult::Err(CliError::code(exit_code::ERROR));
+    };
 
     let graph = build_graph(
         args,
         &cargo_metadata_parameters,
         config,
-        cargo_metadata_root_package_id.clone(),
+        &root_package_ids,
         &workspace,
     )?;
 
-    let query_resolve_root_package_id = args.package.as_ref().map_or(
-        cargo_metadata_root_package_id.clone(),
-        |ref package_query| {
-            krates
-                .query_resolve(package_query)
-                .map_or(cargo_metadata_root_package_id, |package_id| package_id)
-        },
-    );
-
     scan(
         args,
         &cargo_metadata_parameters,
         config,
         &graph,
-        query_resolve_root_package_id,
+        &root_package_ids,
         &workspace,
     )
 }
@@ -104,8 +148,21 @@ fn main() {
             cargo::exit_with_error(e.into(), &mut shell)
         }
     };
-    let args = Args::parse_args(pico_args::Arguments::from_env()).unwrap();
-    if let Err(e) = real_main(&args, &mut config) {
+    let args = match Args::parse_args(pico_args::Arguments::from_env()) {
+        Ok(args) => args,
+        Err(e) => {
+            let mut shell = Shell::new();
+            cargo::exit_with_error(
+                CliError::new(anyhow::anyhow!("{}", e), exit_code::ERROR),
+                &mut shell,
+            )
+        }
+    };
+    if let Err(mut e) = real_main(&args, &mut config) {
+        // Errors converted with `?` get the exit code 101 of cargo.
+        if e.exit_code == 101 {
+            e.exit_code = exit_code::ERROR;
+        }
         let mut shell = Shell::new();
         cargo::exit_with_error(e, &mut shell)
     }
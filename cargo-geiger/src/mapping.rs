@@ -4,23 +4,93 @@ mod metadata;
 
 use ::krates::Krates;
 use cargo::core::dependency::DepKind;
+use cargo::sources::CRATES_IO_INDEX;
+use cargo::util::CargoResult;
+use cargo::Config;
 use cargo_metadata::Metadata;
-use std::collections::HashSet;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use std::path::PathBuf;
 
+const CRATES_IO_SPARSE_INDEX: &str = "sparse+https://index.crates.io/";
+
 pub struct CargoMetadataParameters<'a> {
     pub krates: &'a Krates,
     pub metadata: &'a Metadata,
+    pub registry_names: &'a RegistryNames,
+}
+
+/// Names of crates.io and of the registries in the `[registries]` table of
+/// the cargo config, by the source id of their index, e.g.
+/// `registry+https://github.com/rust-lang/crates.io-index`.
+#[derive(Debug, Default)]
+pub struct RegistryNames(HashMap<String, String>);
+
+impl RegistryNames {
+    pub fn new(config: &Config) -> CargoResult<RegistryNames> {
+        #[derive(Deserialize)]
+        struct RegistryConfig {
+            index: Option<String>,
+        }
+
+        let registries = config
+            .get::<Option<HashMap<String, RegistryConfig>>>("registries")?
+            .unwrap_or_default();
+        Ok(RegistryNames::from_indexes(
+            registries.into_iter().filter_map(|(name, registry)| {
+                registry.index.map(|index| (name, index))
+            }),
+        ))
+    }
+
+    /// Takes the names and index URLs of the configured registries, sparse
+    /// indexes start with `sparse+`.
+    fn from_indexes(
+        indexes: impl Iterator<Item = (String, String)>,
+    ) -> RegistryNames {
+        let crates_io = [
+            format!("registry+{}", CRATES_IO_INDEX),
+            String::from(CRATES_IO_SPARSE_INDEX),
+        ];
+        let mut registry_names = RegistryNames(
+            crates_io
+                .iter()
+                .map(|source_id| {
+                    (source_key(source_id), String::from("crates.io"))
+                })
+                .collect(),
+        );
+        for (name, index) in indexes {
+            let source_id = if index.starts_with("sparse+") {
+                index
+            } else {
+                format!("registry+{}", index)
+            };
+            registry_names.0.insert(source_key(&source_id), name);
+        }
+        registry_names
+    }
+
+    /// The name of the registry with this source id, `None` if it is not
+    /// configured.
+    pub fn get(&self, source_id: &str) -> Option<&str> {
+        self.0.get(&source_key(source_id)).map(String::as_str)
+    }
 }
 
-pub trait DepsNotReplaced {
-    fn deps_not_replaced(
+/// Cargo adds a trailing slash to sparse index URLs, ignore it for both
+/// sparse and git indexes.
+fn source_key(source_id: &str) -> String {
+    String::from(source_id.trim_end_matches('/'))
+}
+
+pub trait GetFeaturesFromCargoMetadataPackageId {
+    fn get_features_from_cargo_metadata_package_id(
         &self,
-        package_id: cargo_metadata::PackageId,
-    ) -> Vec<(
-        cargo_metadata::PackageId,
-        HashSet<cargo_metadata::Dependency>,
-    )>;
+        package_id: &cargo_metadata::PackageId,
+    ) -> Option<Vec<String>>;
 }
 
 pub trait GetLicenceFromCargoMetadataPackageId {
@@ -51,26 +121,80 @@ pub trait GetRepositoryFromCargoMetadataPackageId {
     ) -> Option<String>;
 }
 
+pub trait GetResolveNodeFromCargoMetadataPackageId {
+    fn get_resolve_node_from_cargo_metadata_package_id(
+        &self,
+        package_id: &cargo_metadata::PackageId,
+    ) -> Option<&cargo_metadata::Node>;
+}
+
 pub trait GetRoot {
     fn get_root(&self) -> PathBuf;
 }
 
-pub trait MatchesIgnoringSource {
-    fn matches_ignoring_source(
+pub trait QueryResolve {
+    fn query_resolve(
         &self,
-        krates: &Krates,
-        package_id: cargo_metadata::PackageId,
-    ) -> bool;
+        query: &str,
+    ) -> Result<cargo_metadata::PackageId, QueryResolveError>;
 }
 
-pub trait QueryResolve {
-    fn query_resolve(&self, query: &str) -> Option<cargo_metadata::PackageId>;
+/// Failure to select a package with a package id spec like `-p` takes.
+#[derive(Debug, PartialEq)]
+pub enum QueryResolveError {
+    /// The spec and the reason it could not be parsed.
+    InvalidSpec(String, String),
+
+    /// The spec and the ids of all packages that match it.
+    Ambiguous(String, Vec<String>),
+
+    /// No package matches the spec.
+    NotFound(String),
+}
+
+impl Error for QueryResolveError {}
+
+/// These are shown to users for a mistyped `-p` argument, unlike most errors
+/// in this crate, so Display is not forwarded to Debug.
+impl fmt::Display for QueryResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryResolveError::InvalidSpec(spec, reason) => {
+                write!(
+                    f,
+                    "invalid package id specification `{}`: {}",
+                    spec, reason
+                )
+            }
+            QueryResolveError::Ambiguous(spec, candidates) => {
+                writeln!(
+                    f,
+                    "package id specification `{}` is ambiguous, please \
+                     specify one of the following packages:",
+                    spec
+                )?;
+                for candidate in candidates {
+                    writeln!(f, "  {}", candidate)?;
+                }
+                Ok(())
+            }
+            QueryResolveError::NotFound(spec) => write!(
+                f,
+                "package id specification `{}` did not match any packages",
+                spec
+            ),
+        }
+    }
 }
 
 pub trait ToCargoCoreDepKind {
     fn to_cargo_core_dep_kind(&self) -> DepKind;
 }
 
+pub trait ToCargoCorePackageId {
+    fn to_cargo_core_package_id(&self) -> CargoResult<cargo::core::PackageId>;
+}
+
 pub trait ToCargoGeigerDependencyKind {
     fn to_cargo_geiger_dependency_kind(
         &self,
@@ -80,15 +204,38 @@ pub trait ToCargoGeigerDependencyKind {
 pub trait ToCargoGeigerPackageId {
     fn to_cargo_geiger_package_id(
         &self,
-        metadata: &Metadata,
-    ) -> cargo_geiger_serde::PackageId;
+        cargo_metadata_parameters: &CargoMetadataParameters,
+    ) -> Result<cargo_geiger_serde::PackageId, SourceMappingError>;
 }
 
 pub trait ToCargoGeigerSource {
     fn to_cargo_geiger_source(
         &self,
-        metadata: &Metadata,
-    ) -> cargo_geiger_serde::Source;
+        cargo_metadata_parameters: &CargoMetadataParameters,
+    ) -> Result<cargo_geiger_serde::Source, SourceMappingError>;
+}
+
+/// Failure to turn a cargo metadata package id into the package id of a
+/// report.
+#[derive(Debug, PartialEq)]
+pub enum SourceMappingError {
+    /// The package id is missing from the cargo metadata.
+    PackageNotInMetadata(String),
+
+    /// The directory of a package can not be turned into a `file:` URL.
+    InvalidPackageDirectory(PathBuf),
+
+    /// The package id of a path package has no `path+file://` URL.
+    InvalidPathPackageId(String),
+}
+
+impl Error for SourceMappingError {}
+
+/// Forward Display to Debug.
+impl fmt::Display for SourceMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
 }
 
 pub trait ToCargoMetadataPackage {
@@ -98,9 +245,28 @@ pub trait ToCargoMetadataPackage {
     ) -> Option<cargo_metadata::Package>;
 }
 
-pub trait ToCargoMetadataPackageId {
-    fn to_cargo_metadata_package_id(
+/// Failure to find the cargo metadata package of a package built by cargo.
+#[derive(Debug, PartialEq)]
+pub enum PackageIdMappingError {
+    /// No package has the same name, version and source.
+    NotFound(String),
+
+    /// The package id and the ids of all packages that match it.
+    Ambiguous(String, Vec<String>),
+}
+
+impl Error for PackageIdMappingError {}
+
+/// Forward Display to Debug.
+impl fmt::Display for PackageIdMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+pub trait TryToCargoMetadataPackageId {
+    fn try_to_cargo_metadata_package_id(
         &self,
         metadata: &Metadata,
-    ) -> Option<cargo_metadata::PackageId>;
+    ) -> Result<cargo_metadata::PackageId, PackageIdMappingError>;
 }
@@ -1,80 +1,121 @@
-use super::{ToCargoGeigerSource, ToCargoMetadataPackage};
+use super::{
+    CargoMetadataParameters, GetRoot, RegistryNames, SourceMappingError,
+    ToCargoGeigerSource, ToCargoMetadataPackage,
+};
 
-use cargo_metadata::Metadata;
+use cargo_geiger_serde::{GitReference, Source};
 use url::Url;
 
 impl ToCargoGeigerSource for cargo_metadata::PackageId {
     fn to_cargo_geiger_source(
         &self,
-        metadata: &Metadata,
-    ) -> cargo_geiger_serde::Source {
-        let package = self.to_cargo_metadata_package(metadata).unwrap();
+        cargo_metadata_parameters: &CargoMetadataParameters,
+    ) -> Result<Source, SourceMappingError> {
+        let package = self
+            .to_cargo_metadata_package(cargo_metadata_parameters.metadata)
+            .ok_or_else(|| {
+                SourceMappingError::PackageNotInMetadata(self.repr.clone())
+            })?;
 
-        match package.source {
-            Some(source) => handle_source_repr(&source.repr),
+        match &package.source {
+            Some(source) => match handle_source_repr(
+                &source.repr,
+                cargo_metadata_parameters.registry_names,
+            ) {
+                Some(source) => Ok(source),
+                // Sources unknown to cargo-geiger are identified by the
+                // directory the package was read from.
+                None => {
+                    let package_dir = package.get_root();
+                    Url::from_directory_path(&package_dir)
+                        .map(Source::Path)
+                        .map_err(|()| {
+                            SourceMappingError::InvalidPackageDirectory(
+                                package_dir,
+                            )
+                        })
+                }
+            },
             None => handle_path_source(self),
         }
     }
 }
 
-fn handle_source_repr(source_repr: &str) -> cargo_geiger_serde::Source {
-    let mut source_repr_vec = source_repr.split('+').collect::<Vec<&str>>();
-
-    let source_type = source_repr_vec[0];
+/// Maps a source id like `sparse+https://index.crates.io/` to a `Source`,
+/// `None` if the kind of source is unknown or the URL can not be parsed.
+fn handle_source_repr(
+    source_repr: &str,
+    registry_names: &RegistryNames,
+) -> Option<Source> {
+    let mut source_repr_parts = source_repr.splitn(2, '+');
+    let source_type = source_repr_parts.next()?;
+    let url = Url::parse(source_repr_parts.next()?).ok()?;
+    let registry_name = || {
+        registry_names
+            .get(source_repr)
+            .map(String::from)
+            .unwrap_or_else(|| url.to_string())
+    };
 
     match source_type {
-        "registry" => {
-            cargo_geiger_serde::Source::Registry {
-                // It looks like cargo metadata drops this information
-                name: String::from("crates.io"),
-                url: Url::parse(source_repr_vec.pop().unwrap()).unwrap(),
-            }
-        }
-        "git" => {
-            let raw_git_representation = source_repr_vec.pop().unwrap();
-            let raw_git_url = Url::parse(raw_git_representation).unwrap();
-            let git_url_without_query = format!(
-                "{}://{}{}",
-                raw_git_url.scheme(),
-                raw_git_url.host_str().unwrap(),
-                raw_git_url.path()
-            );
-            let revision = raw_git_url
-                .query_pairs()
-                .find(|(query_key, _)| query_key == "rev")
-                .unwrap()
-                .1;
-
-            cargo_geiger_serde::Source::Git {
-                url: Url::parse(&git_url_without_query).unwrap(),
-                rev: String::from(revision),
-            }
-        }
-        _ => {
-            panic!("Unrecognised source type: {}", source_type)
-        }
+        "registry" => Some(Source::Registry {
+            name: registry_name(),
+            url,
+        }),
+        "sparse" => Some(Source::SparseRegistry {
+            name: registry_name(),
+            url,
+        }),
+        "local-registry" => Some(Source::LocalRegistry(url)),
+        "directory" => Some(Source::Directory(url)),
+        "path" => Some(Source::Path(url)),
+        "git" => Some(handle_git_url(url)),
+        _ => None,
+    }
+}
+
+/// Git source ids look like `https://github.com/foo/bar?branch=main#<commit>`,
+/// without a query for the default branch.
+fn handle_git_url(mut url: Url) -> Source {
+    let reference = url
+        .query_pairs()
+        .find_map(|(key, value)| match key.as_ref() {
+            "branch" => Some(GitReference::Branch(value.into_owned())),
+            "tag" => Some(GitReference::Tag(value.into_owned())),
+            "rev" => Some(GitReference::Rev(value.into_owned())),
+            _ => None,
+        })
+        .unwrap_or(GitReference::DefaultBranch);
+    let precise = url.fragment().map(String::from);
+    url.set_query(None);
+    url.set_fragment(None);
+
+    Source::Git {
+        url,
+        reference,
+        precise,
     }
 }
 
 fn handle_path_source(
     package_id: &cargo_metadata::PackageId,
-) -> cargo_geiger_serde::Source {
-    let raw_repr = package_id.repr.clone();
-    let raw_path_repr = raw_repr[1..raw_repr.len() - 1]
-        .split("+file://")
-        .skip(1)
-        .collect::<Vec<&str>>()
-        .pop()
-        .unwrap();
-
-    let source_url: Url;
-    if cfg!(windows) {
-        source_url = Url::from_file_path(&raw_path_repr[1..]).unwrap();
-    } else {
-        source_url = Url::from_file_path(raw_path_repr).unwrap();
-    }
+) -> Result<Source, SourceMappingError> {
+    let invalid_path_package_id =
+        || SourceMappingError::InvalidPathPackageId(package_id.repr.clone());
+    let raw_repr = &package_id.repr;
+    let raw_path_repr = raw_repr
+        .get(1..raw_repr.len().saturating_sub(1))
+        .and_then(|repr| repr.split("+file://").nth(1))
+        .ok_or_else(invalid_path_package_id)?;
 
-    cargo_geiger_serde::Source::Path(source_url)
+    let raw_path = if cfg!(windows) {
+        raw_path_repr.get(1..).ok_or_else(invalid_path_package_id)?
+    } else {
+        raw_path_repr
+    };
+    Url::from_file_path(raw_path)
+        .map(Source::Path)
+        .map_err(|()| invalid_path_package_id())
 }
 
 #[cfg(test)]
@@ -89,24 +130,104 @@ mod geiger_tests {
         expected_source,
         case(
             "registry+https://github.com/rust-lang/crates.io-index",
-            cargo_geiger_serde::Source::Registry {
+            Some(Source::Registry {
                 name: String::from("crates.io"),
                 url: Url::parse("https://github.com/rust-lang/crates.io-index").unwrap()
-            }
+            })
+        ),
+        case(
+            "registry+https://example.com/index",
+            Some(Source::Registry {
+                name: String::from("my-registry"),
+                url: Url::parse("https://example.com/index").unwrap()
+            })
+        ),
+        case(
+            "registry+https://example.com/unknown-index",
+            Some(Source::Registry {
+                name: String::from("https://example.com/unknown-index"),
+                url: Url::parse("https://example.com/unknown-index").unwrap()
+            })
+        ),
+        case(
+            "sparse+https://index.crates.io/",
+            Some(Source::SparseRegistry {
+                name: String::from("crates.io"),
+                url: Url::parse("https://index.crates.io/").unwrap()
+            })
+        ),
+        case(
+            "sparse+https://example.com/sparse-index/",
+            Some(Source::SparseRegistry {
+                name: String::from("my-sparse-registry"),
+                url: Url::parse("https://example.com/sparse-index/").unwrap()
+            })
+        ),
+        case(
+            "local-registry+file:///vendor/registry",
+            Some(Source::LocalRegistry(
+                Url::parse("file:///vendor/registry").unwrap()
+            ))
+        ),
+        case(
+            "directory+file:///ws/vendor",
+            Some(Source::Directory(Url::parse("file:///ws/vendor").unwrap()))
         ),
         case(
             "git+https://github.com/rust-itertools/itertools.git?rev=8761fbefb3b209",
-            cargo_geiger_serde::Source::Git {
+            Some(Source::Git {
                 url: Url::parse("https://github.com/rust-itertools/itertools.git").unwrap(),
-                rev: String::from("8761fbefb3b209")
-            }
-        )
+                reference: GitReference::Rev(String::from("8761fbefb3b209")),
+                precise: None
+            })
+        ),
+        case(
+            "git+https://github.com/foo/bar?branch=main#0123abcd",
+            Some(Source::Git {
+                url: Url::parse("https://github.com/foo/bar").unwrap(),
+                reference: GitReference::Branch(String::from("main")),
+                precise: Some(String::from("0123abcd"))
+            })
+        ),
+        case(
+            "git+https://github.com/foo/bar?tag=v1.0.0#0123abcd",
+            Some(Source::Git {
+                url: Url::parse("https://github.com/foo/bar").unwrap(),
+                reference: GitReference::Tag(String::from("v1.0.0")),
+                precise: Some(String::from("0123abcd"))
+            })
+        ),
+        case(
+            "git+https://github.com/foo/bar#0123abcd",
+            Some(Source::Git {
+                url: Url::parse("https://github.com/foo/bar").unwrap(),
+                reference: GitReference::DefaultBranch,
+                precise: Some(String::from("0123abcd"))
+            })
+        ),
+        case("unknown+https://example.com", None),
+        case("registry+not a url", None),
+        case("registry", None)
     )]
     fn handle_source_repr_test(
         input_source_repr: &str,
-        expected_source: cargo_geiger_serde::Source,
+        expected_source: Option<Source>,
     ) {
-        let source = handle_source_repr(input_source_repr);
+        let registry_names = RegistryNames::from_indexes(
+            vec![
+                (
+                    String::from("my-registry"),
+                    String::from("https://example.com/index"),
+                ),
+                (
+                    String::from("my-sparse-registry"),
+                    String::from("sparse+https://example.com/sparse-index"),
+                ),
+            ]
+            .into_iter(),
+        );
+
+        let source = handle_source_repr(input_source_repr, &registry_names);
         assert_eq!(source, expected_source);
     }
 
@@ -117,7 +238,7 @@ mod geiger_tests {
                 repr: String::from("(path+file:///cargo_geiger/test_crates/test1_package_with_no_deps)"),
             };
 
-            let expected_source = cargo_geiger_serde::Source::Path(
+            let expected_source = Source::Path(
                 Url::from_file_path(
                     "/cargo_geiger/test_crates/test1_package_with_no_deps",
                 )
@@ -125,7 +246,19 @@ mod geiger_tests {
             );
 
             let source = handle_path_source(&package_id);
-            assert_eq!(source, expected_source);
+            assert_eq!(source, Ok(expected_source));
         }
     }
+
+    #[rstest]
+    fn handle_path_source_invalid_test() {
+        let package_id = cargo_metadata::PackageId {
+            repr: String::from("(registry+https://example.com/index)"),
+        };
+
+        assert_eq!(
+            handle_path_source(&package_id),
+            Err(SourceMappingError::InvalidPathPackageId(package_id.repr))
+        );
+    }
 }
@@ -2,7 +2,7 @@ use crate::mapping::{
     GetLicenceFromCargoMetadataPackageId,
     GetPackageNameFromCargoMetadataPackageId,
     GetPackageVersionFromCargoMetadataPackageId,
-    GetRepositoryFromCargoMetadataPackageId, QueryResolve,
+    GetRepositoryFromCargoMetadataPackageId, QueryResolve, QueryResolveError,
 };
 
 use krates::{Krates, PkgSpec};
@@ -49,13 +49,45 @@ impl GetRepositoryFromCargoMetadataPackageId for Krates {
 }
 
 impl QueryResolve for Krates {
-    fn query_resolve(&self, query: &str) -> Option<cargo_metadata::PackageId> {
-        let package_spec = PkgSpec::from_str(query).unwrap();
-        self.krates_by_name(package_spec.name.as_str())
+    fn query_resolve(
+        &self,
+        query: &str,
+    ) -> Result<cargo_metadata::PackageId, QueryResolveError> {
+        let package_spec = PkgSpec::from_str(&normalize_package_spec(query))
+            .map_err(|e| {
+                QueryResolveError::InvalidSpec(query.to_string(), e.to_string())
+            })?;
+        let mut package_ids = self
+            .krates_by_name(package_spec.name.as_str())
             .filter(|(_, node)| package_spec.matches(&node.krate))
-            .map(|(_, node)| node.krate.clone().id)
-            .collect::<Vec<cargo_metadata::PackageId>>()
-            .pop()
+            .map(|(_, node)| node.krate.id.clone())
+            .collect::<Vec<cargo_metadata::PackageId>>();
+        match package_ids.len() {
+            0 => Err(QueryResolveError::NotFound(query.to_string())),
+            1 => Ok(package_ids.remove(0)),
+            _ => {
+                let mut candidates = package_ids
+                    .into_iter()
+                    .map(|package_id| package_id.repr)
+                    .collect::<Vec<_>>();
+                candidates.sort();
+                Err(QueryResolveError::Ambiguous(query.to_string(), candidates))
+            }
+        }
+    }
+}
+
+/// `PkgSpec` only understands `name:version`, newer cargo versions also
+/// accept `name@version`, in the fragment of a url as well.
+fn normalize_package_spec(spec: &str) -> String {
+    match spec.find("://") {
+        Some(_) => match spec.rfind('#') {
+            Some(i) => {
+                format!("{}#{}", &spec[..i], spec[i + 1..].replace('@', ":"))
+            }
+            None => spec.to_string(),
+        },
+        None => spec.replace('@', ":"),
     }
 }
 
@@ -162,6 +194,82 @@ mod krates_tests {
         );
     }
 
+    #[rstest(
+        input_query_string,
+        expected_query_resolve_error,
+        case(
+            "not_a_dependency",
+            QueryResolveError::NotFound(String::from("not_a_dependency"))
+        ),
+        case(
+            "cargo_metadata:x.y.z",
+            QueryResolveError::InvalidSpec(
+                String::from("cargo_metadata:x.y.z"),
+                String::from(
+                    "package spec was invalid: failed to parse version"
+                )
+            )
+        )
+    )]
+    fn query_resolve_test_error(
+        input_query_string: &str,
+        expected_query_resolve_error: QueryResolveError,
+    ) {
+        let (krates, _) = construct_krates_and_metadata();
+        assert_eq!(
+            krates.query_resolve(input_query_string),
+            Err(expected_query_resolve_error)
+        );
+    }
+
+    #[rstest]
+    fn query_resolve_test_ambiguous() {
+        let (krates, metadata) = construct_krates_and_metadata();
+        // Both cfg-if 0.1 and 1.0 are in the dependency graph.
+        let mut expected_candidates = metadata
+            .packages
+            .iter()
+            .filter(|package| package.name == "cfg-if")
+            .map(|package| package.id.repr.clone())
+            .collect::<Vec<_>>();
+        expected_candidates.sort();
+        assert_eq!(expected_candidates.len(), 2);
+
+        assert_eq!(
+            krates.query_resolve("cfg-if"),
+            Err(QueryResolveError::Ambiguous(
+                String::from("cfg-if"),
+                expected_candidates
+            ))
+        );
+        assert!(krates.query_resolve("cfg-if@1.0.0").is_ok());
+    }
+
+    #[rstest(
+        input_spec,
+        expected_normalized_spec,
+        case("rand", "rand"),
+        case("rand@0.7.3", "rand:0.7.3"),
+        case("rand:0.7.3", "rand:0.7.3"),
+        case(
+            "https://github.com/rust-lang/crates.io-index#rand@0.7.3",
+            "https://github.com/rust-lang/crates.io-index#rand:0.7.3"
+        ),
+        case(
+            "ssh://git@github.com/rust-random/rand",
+            "ssh://git@github.com/rust-random/rand"
+        )
+    )]
+    fn normalize_package_spec_test(
+        input_spec: &str,
+        expected_normalized_spec: &str,
+    ) {
+        assert_eq!(
+            normalize_package_spec(input_spec),
+            expected_normalized_spec
+        );
+    }
+
     fn construct_krates_and_metadata() -> (Krates, Metadata) {
         let metadata = MetadataCommand::new()
             .manifest_path("./Cargo.toml")
@@ -1,46 +1,37 @@
 use super::{
-    DepsNotReplaced, GetPackageNameFromCargoMetadataPackageId,
-    GetPackageVersionFromCargoMetadataPackageId, GetRoot,
-    MatchesIgnoringSource, ToCargoGeigerPackageId, ToCargoMetadataPackageId,
+    GetFeaturesFromCargoMetadataPackageId,
+    GetResolveNodeFromCargoMetadataPackageId, GetRoot, ToCargoGeigerPackageId,
 };
 
 use crate::mapping::{
-    ToCargoGeigerDependencyKind, ToCargoGeigerSource, ToCargoMetadataPackage,
+    CargoMetadataParameters, PackageIdMappingError, SourceMappingError,
+    ToCargoCorePackageId, ToCargoGeigerDependencyKind, ToCargoGeigerSource,
+    ToCargoMetadataPackage, TryToCargoMetadataPackageId,
 };
 
-use cargo_metadata::{DependencyKind, Metadata, PackageId};
-use krates::Krates;
-use std::collections::HashSet;
+use cargo::core::SourceId;
+use cargo::CargoResult;
+use cargo_metadata::{DependencyKind, Metadata};
 use std::path::PathBuf;
 
-impl DepsNotReplaced for cargo_metadata::Metadata {
-    fn deps_not_replaced(
+impl GetFeaturesFromCargoMetadataPackageId for cargo_metadata::Metadata {
+    fn get_features_from_cargo_metadata_package_id(
         &self,
-        package_id: cargo_metadata::PackageId,
-    ) -> Vec<(
-        cargo_metadata::PackageId,
-        HashSet<cargo_metadata::Dependency>,
-    )> {
-        let mut cargo_metadata_deps_not_replaced = vec![];
-        let mut package_id_hashset = HashSet::<PackageId>::new();
-
-        for dep in package_id
-            .to_cargo_metadata_package(self)
-            .unwrap()
-            .dependencies
-        {
-            if let Some(package_id) = dep.to_cargo_metadata_package_id(self) {
-                if !package_id_hashset.contains(&package_id) {
-                    cargo_metadata_deps_not_replaced.push((
-                        package_id.clone(),
-                        HashSet::<cargo_metadata::Dependency>::new(),
-                    ));
-                    package_id_hashset.insert(package_id);
-                }
-            }
-        }
+        package_id: &cargo_metadata::PackageId,
+    ) -> Option<Vec<String>> {
+        self.get_resolve_node_from_cargo_metadata_package_id(package_id)
+            .map(|node| node.features.clone())
+    }
+}
 
-        cargo_metadata_deps_not_replaced
+impl GetResolveNodeFromCargoMetadataPackageId for cargo_metadata::Metadata {
+    fn get_resolve_node_from_cargo_metadata_package_id(
+        &self,
+        package_id: &cargo_metadata::PackageId,
+    ) -> Option<&cargo_metadata::Node> {
+        self.resolve.as_ref().and_then(|resolve| {
+            resolve.nodes.iter().find(|node| node.id == *package_id)
+        })
     }
 }
 
@@ -50,23 +41,19 @@ impl GetRoot for cargo_metadata::Package {
     }
 }
 
-impl MatchesIgnoringSource for cargo_metadata::Dependency {
-    fn matches_ignoring_source(
-        &self,
-        krates: &Krates,
-        package_id: cargo_metadata::PackageId,
-    ) -> bool {
-        Some(&self.name)
-            == krates
-                .get_package_name_from_cargo_metadata_package_id(&package_id)
-                .as_ref()
-            && self.req.matches(
-                &krates
-                    .get_package_version_from_cargo_metadata_package_id(
-                        &package_id,
-                    )
-                    .unwrap(),
-            )
+/// Path packages have no source in the cargo metadata, their source is the
+/// directory they are read from.
+impl ToCargoCorePackageId for cargo_metadata::Package {
+    fn to_cargo_core_package_id(&self) -> CargoResult<cargo::core::PackageId> {
+        let source_id = match &self.source {
+            Some(source) => SourceId::from_url(&source.repr)?,
+            None => SourceId::for_path(&self.get_root())?,
+        };
+        cargo::core::PackageId::new(
+            self.name.as_str(),
+            self.version.to_string().as_str(),
+            source_id,
+        )
     }
 }
 
@@ -90,31 +77,68 @@ impl ToCargoGeigerDependencyKind for cargo_metadata::DependencyKind {
 impl ToCargoGeigerPackageId for cargo_metadata::PackageId {
     fn to_cargo_geiger_package_id(
         &self,
-        metadata: &Metadata,
-    ) -> cargo_geiger_serde::PackageId {
-        let package = self.to_cargo_metadata_package(metadata).unwrap();
-        let metadata_source = self.to_cargo_geiger_source(metadata);
-
-        cargo_geiger_serde::PackageId {
+        cargo_metadata_parameters: &CargoMetadataParameters,
+    ) -> Result<cargo_geiger_serde::PackageId, SourceMappingError> {
+        let package = self
+            .to_cargo_metadata_package(cargo_metadata_parameters.metadata)
+            .ok_or_else(|| {
+                SourceMappingError::PackageNotInMetadata(self.repr.clone())
+            })?;
+        let metadata_source =
+            self.to_cargo_geiger_source(cargo_metadata_parameters)?;
+
+        Ok(cargo_geiger_serde::PackageId {
             name: package.name,
             version: package.version,
             source: metadata_source,
-        }
+        })
     }
 }
 
-impl ToCargoMetadataPackageId for cargo_metadata::Dependency {
-    fn to_cargo_metadata_package_id(
+impl TryToCargoMetadataPackageId for cargo::core::PackageId {
+    fn try_to_cargo_metadata_package_id(
         &self,
         metadata: &Metadata,
-    ) -> Option<cargo_metadata::PackageId> {
-        metadata
+    ) -> Result<cargo_metadata::PackageId, PackageIdMappingError> {
+        let mut package_ids = metadata
             .packages
             .iter()
-            .filter(|p| p.name == self.name && self.req.matches(&p.version))
-            .map(|p| p.id.clone())
-            .collect::<Vec<cargo_metadata::PackageId>>()
-            .pop()
+            .filter(|package| is_same_package(self, package))
+            .map(|package| package.id.clone())
+            .collect::<Vec<_>>();
+        match package_ids.len() {
+            0 => Err(PackageIdMappingError::NotFound(self.to_string())),
+            1 => Ok(package_ids.pop().unwrap()),
+            _ => Err(PackageIdMappingError::Ambiguous(
+                self.to_string(),
+                package_ids.into_iter().map(|id| id.repr).collect(),
+            )),
+        }
+    }
+}
+
+/// Compares the name, the full version and the source. Path packages have no
+/// source in the cargo metadata and are compared by their directory.
+fn is_same_package(
+    package_id: &cargo::core::PackageId,
+    package: &cargo_metadata::Package,
+) -> bool {
+    if package.name != package_id.name().as_str()
+        || package.version.to_string() != package_id.version().to_string()
+    {
+        return false;
+    }
+    let source_id = package_id.source_id();
+    match &package.source {
+        Some(source) => {
+            !source_id.is_path()
+                && source.repr == source_id.into_url().to_string()
+        }
+        None => {
+            source_id.is_path()
+                && source_id.url().to_file_path().ok().as_deref()
+                    == package.manifest_path.parent()
+        }
     }
 }
 
@@ -148,16 +172,17 @@ mod metadata_tests {
     use cargo::core::registry::PackageRegistry;
     use cargo::core::resolver::ResolveOpts;
     use cargo::core::{
-        Package, PackageId, PackageIdSpec, PackageSet, Resolve, Workspace,
+        Package, PackageId, PackageIdSpec, PackageSet, Resolve, SourceId,
+        Workspace,
     };
     use cargo::{ops, CargoResult, Config};
     use cargo_metadata::{CargoOpt, Metadata, MetadataCommand};
-    use krates::Builder as KratesBuilder;
+    use krates::{Builder as KratesBuilder, Krates};
     use rstest::*;
     use std::path::PathBuf;
 
     #[rstest]
-    fn deps_not_replaced_test() {
+    fn get_resolve_node_from_cargo_metadata_package_id_test() {
         let args = FeaturesArgs::default();
         let config = Config::default().unwrap();
         let (package, mut registry, workspace) =
@@ -170,22 +195,29 @@ mod metadata_tests {
         let (krates, metadata) = construct_krates_and_metadata();
         let cargo_metadata_package_id = package
             .package_id()
-            .to_cargo_metadata_package_id(&metadata)
+            .try_to_cargo_metadata_package_id(&metadata)
             .unwrap();
 
-        let deps_not_replaced = resolve.deps_not_replaced(package.package_id());
-        let cargo_metadata_deps_not_replaced =
-            metadata.deps_not_replaced(cargo_metadata_package_id);
+        let node = metadata
+            .get_resolve_node_from_cargo_metadata_package_id(
+                &cargo_metadata_package_id,
+            )
+            .unwrap();
+        assert_eq!(node.id, cargo_metadata_package_id);
 
-        let mut cargo_core_package_names = deps_not_replaced
+        let mut cargo_core_package_names = resolve
+            .deps_not_replaced(package.package_id())
             .map(|(p, _)| p.name().to_string())
             .collect::<Vec<String>>();
 
-        let mut cargo_metadata_package_names = cargo_metadata_deps_not_replaced
+        let mut cargo_metadata_package_names = node
+            .deps
             .iter()
-            .map(|(p, _)| {
+            .map(|node_dep| {
                 krates
-                    .get_package_name_from_cargo_metadata_package_id(p)
+                    .get_package_name_from_cargo_metadata_package_id(
+                        &node_dep.pkg,
+                    )
                     .unwrap()
             })
             .collect::<Vec<String>>();
@@ -197,42 +229,97 @@ mod metadata_tests {
     }
 
     #[rstest]
-    fn get_root_test() {
-        let (_, metadata) = construct_krates_and_metadata();
-        let package = metadata.root_package().unwrap();
-        let package_root = package.get_root();
+    fn to_cargo_core_package_id_test() {
+        let config = Config::default().unwrap();
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()
+            .unwrap();
+        let package = metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "cargo_metadata")
+            .unwrap();
         assert_eq!(
-            package_root,
-            package.manifest_path.parent().unwrap().to_path_buf()
+            package.to_cargo_core_package_id().unwrap(),
+            PackageId::new(
+                "cargo_metadata",
+                package.version.to_string().as_str(),
+                SourceId::crates_io(&config).unwrap(),
+            )
+            .unwrap()
+        );
+
+        let root_package = metadata.root_package().unwrap();
+        assert_eq!(
+            root_package.to_cargo_core_package_id().unwrap(),
+            PackageId::new(
+                root_package.name.as_str(),
+                root_package.version.to_string().as_str(),
+                SourceId::for_path(&root_package.get_root()).unwrap(),
+            )
+            .unwrap()
         );
     }
 
     #[rstest]
-    fn matches_ignoring_source_test() {
-        let (krates, metadata) = construct_krates_and_metadata();
-        let package = metadata.root_package().unwrap();
+    fn try_to_cargo_metadata_package_id_test() {
+        let config = Config::default().unwrap();
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()
+            .unwrap();
+        let package = metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "cargo_metadata")
+            .unwrap();
+        let version = package.version.to_string();
 
-        let dependency = package.dependencies.clone().pop().unwrap();
+        let crates_io_package_id = PackageId::new(
+            "cargo_metadata",
+            version.as_str(),
+            SourceId::crates_io(&config).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            crates_io_package_id.try_to_cargo_metadata_package_id(&metadata),
+            Ok(package.id.clone())
+        );
 
+        let git_package_id = PackageId::new(
+            "cargo_metadata",
+            version.as_str(),
+            SourceId::from_url("git+https://github.com/oli-obk/cargo_metadata")
+                .unwrap(),
+        )
+        .unwrap();
         assert_eq!(
-            dependency.matches_ignoring_source(&krates, package.clone().id),
-            false
+            git_package_id.try_to_cargo_metadata_package_id(&metadata),
+            Err(PackageIdMappingError::NotFound(git_package_id.to_string()))
         );
 
-        let dependency_package_id = krates
-            .krates()
-            .filter(|k| {
-                k.krate.name == dependency.name
-                    && dependency.req.matches(&k.krate.version)
-            })
-            .map(|k| k.id.clone())
-            .collect::<Vec<cargo_metadata::PackageId>>()
-            .pop()
-            .unwrap();
+        let root_package = metadata.root_package().unwrap();
+        let path_package_id = PackageId::new(
+            root_package.name.as_str(),
+            root_package.version.to_string().as_str(),
+            SourceId::for_path(&root_package.get_root()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            path_package_id.try_to_cargo_metadata_package_id(&metadata),
+            Ok(root_package.id.clone())
+        );
+    }
 
-        assert!(
-            dependency.matches_ignoring_source(&krates, dependency_package_id),
-            true
+    #[rstest]
+    fn get_root_test() {
+        let (_, metadata) = construct_krates_and_metadata();
+        let package = metadata.root_package().unwrap();
+        let package_root = package.get_root();
+        assert_eq!(
+            package_root,
+            package.manifest_path.parent().unwrap().to_path_buf()
         );
     }
 
@@ -255,12 +342,19 @@ mod metadata_tests {
 
     #[rstest]
     fn to_cargo_geiger_package_id_test() {
-        let (_, metadata) = construct_krates_and_metadata();
+        let (krates, metadata) = construct_krates_and_metadata();
+        let cargo_metadata_parameters = CargoMetadataParameters {
+            krates: &krates,
+            metadata: &metadata,
+            registry_names: &Default::default(),
+        };
 
         let root_package = metadata.root_package().unwrap();
 
-        let cargo_geiger_package_id =
-            root_package.id.to_cargo_geiger_package_id(&metadata);
+        let cargo_geiger_package_id = root_package
+            .id
+            .to_cargo_geiger_package_id(&cargo_metadata_parameters)
+            .unwrap();
 
         assert_eq!(cargo_geiger_package_id.name, root_package.name);
 
@@ -278,6 +372,24 @@ mod metadata_tests {
         );
     }
 
+    #[rstest]
+    fn get_features_from_cargo_metadata_package_id_test() {
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .features(CargoOpt::SomeFeatures(vec![String::from(
+                "vendored-openssl",
+            )]))
+            .exec()
+            .unwrap();
+        let package = metadata.root_package().unwrap();
+
+        let features = metadata
+            .get_features_from_cargo_metadata_package_id(&package.id)
+            .unwrap();
+
+        assert_eq!(features, vec![String::from("vendored-openssl")]);
+    }
+
     fn construct_krates_and_metadata() -> (Krates, Metadata) {
         let metadata = MetadataCommand::new()
             .manifest_path("./Cargo.toml")
@@ -353,24 +465,4 @@ mod metadata_tests {
             }
         }
     }
-
-    impl ToCargoMetadataPackageId for PackageId {
-        fn to_cargo_metadata_package_id(
-            &self,
-            metadata: &Metadata,
-        ) -> Option<cargo_metadata::PackageId> {
-            metadata
-                .packages
-                .iter()
-                .filter(|p| {
-                    p.name == self.name().to_string()
-                        && p.version.major == self.version().major
-                        && p.version.minor == self.version().minor
-                        && p.version.patch == self.version().patch
-                })
-                .map(|p| p.id.clone())
-                .collect::<Vec<cargo_metadata::PackageId>>()
-                .pop()
-        }
-    }
 }
@@ -1,9 +1,13 @@
+mod cache;
 mod default;
 mod find;
 mod forbid;
+mod policy;
 mod rs_file;
+mod thresholds;
 
 use crate::args::Args;
+use crate::exit_code;
 use crate::format::print_config::PrintConfig;
 use crate::graph::Graph;
 use crate::mapping::{
@@ -11,18 +15,27 @@ use crate::mapping::{
     ToCargoGeigerPackageId,
 };
 
-pub use rs_file::RsFileMetricsWrapper;
+pub use rs_file::{RsFileMetricsWrapper, RsFilesUsed};
+pub use thresholds::{Thresholds, UnsafeBudget};
 
 use default::scan_unsafe;
 use forbid::scan_forbid_unsafe;
+use policy::{load_policy, Policy};
 
 use cargo::core::Workspace;
-use cargo::{CliResult, Config};
-use cargo_geiger_serde::{CounterBlock, PackageInfo, UnsafeInfo};
+use cargo::util::CargoResult;
+use cargo::{CliError, CliResult, Config};
+use cargo_geiger_serde::{
+    CounterBlock, FileMetrics, PackageInfo, ReportMetadata, UnsafeInfo,
+    UnsafeLocation, REPORT_SCHEMA_VERSION,
+};
 use cargo_metadata::PackageId;
+use cargo_platform::Cfg;
 use petgraph::visit::EdgeRef;
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 /// Provides a more terse and searchable name for the wrapped generic
 /// collection.
@@ -50,6 +63,8 @@ pub struct ScanParameters<'a> {
     pub args: &'a Args,
     pub config: &'a Config,
     pub print_config: &'a PrintConfig,
+    /// The allowlist policy of the workspace, if it has one.
+    pub policy: Option<&'a Policy>,
 }
 
 pub fn scan(
@@ -57,38 +72,44 @@ pub fn scan(
     cargo_metadata_parameters: &CargoMetadataParameters,
     config: &Config,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     workspace: &Workspace,
 ) -> CliResult {
     let print_config = PrintConfig::new(args)?;
+    let policy = load_policy(cargo_metadata_parameters.metadata, workspace)?;
 
     let scan_parameters = ScanParameters {
         args: &args,
         config: &config,
         print_config: &print_config,
+        policy: policy.as_ref(),
     };
 
     if args.forbid_only {
         scan_forbid_unsafe(
             cargo_metadata_parameters,
             &graph,
-            root_package_id,
+            root_package_ids,
             &scan_parameters,
+            workspace,
         )
     } else {
         scan_unsafe(
             cargo_metadata_parameters,
             &graph,
-            root_package_id,
+            root_package_ids,
             &scan_parameters,
             workspace,
         )
     }
 }
 
+/// The paths of the unsafe locations are made relative to `package_root`
+/// where possible, like the paths of `file_stats`.
 pub fn unsafe_stats(
     pack_metrics: &PackageMetrics,
     rs_files_used: &HashSet<PathBuf>,
+    package_root: &Path,
 ) -> UnsafeInfo {
     // The crate level "forbids unsafe code" metric __used to__ only
     // depend on entry point source files that were __used by the
@@ -107,34 +128,157 @@ pub fn unsafe_stats(
 
     let mut declared_unsafe_functions = Vec::new();
     let mut contains_unsafe_functions = Vec::new();
+    let mut unsafe_locations = Vec::new();
 
+    let package_root = canonical_package_root(package_root);
     for (path_buf, rs_file_metrics_wrapper) in &pack_metrics.rs_path_to_metrics
     {
-        let target = if rs_files_used.contains(path_buf) {
-            &mut used
-        } else {
-            &mut unused
-        };
+        let is_used = rs_files_used.contains(path_buf);
+        let target = if is_used { &mut used } else { &mut unused };
         *target += rs_file_metrics_wrapper.metrics.counters.clone();
         declared_unsafe_functions.extend_from_slice(
             &rs_file_metrics_wrapper.metrics.declared_unsafe_functions,
         );
         contains_unsafe_functions.extend_from_slice(
             &rs_file_metrics_wrapper.metrics.contains_unsafe_functions,
-        )
+        );
+        unsafe_locations.extend(
+            rs_file_metrics_wrapper.metrics.unsafe_findings.iter().map(
+                |finding| UnsafeLocation {
+                    file: relative_path(path_buf, &package_root),
+                    used: is_used,
+                    finding: finding.clone(),
+                },
+            ),
+        );
     }
+    // The files are stored in a HashMap, sort to get a stable report.
+    unsafe_locations.sort_by(|a, b| {
+        (&a.file, a.finding.start, a.finding.end).cmp(&(
+            &b.file,
+            b.finding.start,
+            b.finding.end,
+        ))
+    });
     UnsafeInfo {
         used,
         unused,
         forbids_unsafe,
         declared_unsafe_functions,
         contains_unsafe_functions,
+        unsafe_locations,
+    }
+}
+
+/// The metrics of every file of a package for `--per-file`, sorted by path.
+/// Paths are made relative to `package_root` where possible.
+pub fn file_stats(
+    pack_metrics: &PackageMetrics,
+    rs_files_used: &HashSet<PathBuf>,
+    package_root: &Path,
+) -> Vec<FileMetrics> {
+    let package_root = canonical_package_root(package_root);
+    let mut files = pack_metrics
+        .rs_path_to_metrics
+        .iter()
+        .map(|(path_buf, rs_file_metrics_wrapper)| FileMetrics {
+            path: relative_path(path_buf, &package_root),
+            is_entry_point: rs_file_metrics_wrapper.is_crate_entry_point,
+            used: rs_files_used.contains(path_buf),
+            counters: rs_file_metrics_wrapper.metrics.counters.clone(),
+            forbids_unsafe: rs_file_metrics_wrapper.metrics.forbids_unsafe,
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+/// The keys of `rs_path_to_metrics` are canonicalized, so is the package root
+/// they are made relative to.
+fn canonical_package_root(package_root: &Path) -> PathBuf {
+    package_root
+        .canonicalize()
+        .unwrap_or_else(|_| package_root.to_path_buf())
+}
+
+/// Files outside of the package root keep their full path.
+fn relative_path(path: &Path, package_root: &Path) -> PathBuf {
+    path.strip_prefix(package_root)
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// The settings and toolchain of the scan, recorded in the report. `cfgs` are
+/// the evaluated cfgs of the target, if any.
+pub fn report_metadata(
+    args: &Args,
+    cfgs: Option<&[Cfg]>,
+    config: &Config,
+    workspace: &Workspace,
+) -> CargoResult<ReportMetadata> {
+    let rustc = config.load_global_rustc(Some(workspace))?;
+    Ok(construct_report_metadata(
+        args,
+        cfgs,
+        &rustc.version.to_string(),
+        &rustc.host,
+    ))
+}
+
+fn construct_report_metadata(
+    args: &Args,
+    cfgs: Option<&[Cfg]>,
+    rustc_version: &str,
+    host: &str,
+) -> ReportMetadata {
+    ReportMetadata {
+        format_version: REPORT_SCHEMA_VERSION,
+        geiger_version: String::from(env!("CARGO_PKG_VERSION")),
+        rustc_version: String::from(rustc_version),
+        target: args
+            .target_args
+            .target
+            .clone()
+            .unwrap_or_else(|| String::from(host)),
+        all_targets: args.target_args.all_targets,
+        target_cfgs: cfgs
+            .map(|cfgs| cfgs.iter().map(|cfg| cfg.to_string()).collect()),
+        features: args.features_args.features.clone(),
+        all_features: args.features_args.all_features,
+        no_default_features: args.features_args.no_default_features,
+        include_tests: args.include_tests,
+    }
+}
+
+/// A check of the report failed, the violations have already been printed.
+#[derive(Debug)]
+struct CheckFailedError {
+    check: &'static str,
+    violation_count: usize,
+}
+
+impl Error for CheckFailedError {}
+
+impl fmt::Display for CheckFailedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} check failed with {} {}",
+            self.check,
+            self.violation_count,
+            if self.violation_count == 1 {
+                "violation"
+            } else {
+                "violations"
+            }
+        )
     }
 }
 
 struct ScanDetails {
-    rs_files_used: HashSet<PathBuf>,
+    rs_files_used: RsFilesUsed,
     geiger_context: GeigerContext,
+    report_metadata: ReportMetadata,
 }
 
 fn construct_rs_files_used_lines(
@@ -170,24 +314,35 @@ fn list_files_used_but_not_scanned(
         .collect()
 }
 
+/// The metrics of every package reachable from the root packages, packages
+/// shared by several roots are only included once. The metrics are `None` for
+/// packages that could not be scanned.
 fn package_metrics(
     cargo_metadata_parameters: &CargoMetadataParameters,
     geiger_context: &GeigerContext,
     graph: &Graph,
-    root_package_id: PackageId,
-) -> Vec<(PackageInfo, Option<PackageMetrics>)> {
+    root_package_ids: &[PackageId],
+) -> Result<Vec<(PackageId, PackageInfo, Option<PackageMetrics>)>, CliError> {
     let mut package_metrics =
-        Vec::<(PackageInfo, Option<PackageMetrics>)>::new();
-    let root_index = graph.nodes[&root_package_id];
-    let mut indices = vec![root_index];
+        Vec::<(PackageId, PackageInfo, Option<PackageMetrics>)>::new();
+    let mut indices = Vec::new();
     let mut visited = HashSet::new();
+    for root_package_id in root_package_ids {
+        let root_index = graph.nodes[root_package_id];
+        if visited.insert(root_index) {
+            indices.push(root_index);
+        }
+    }
 
     while !indices.is_empty() {
         let i = indices.pop().unwrap();
         let package_id = graph.graph[i].clone();
         let mut package = PackageInfo::new(
             package_id
-                .to_cargo_geiger_package_id(cargo_metadata_parameters.metadata),
+                .to_cargo_geiger_package_id(cargo_metadata_parameters)
+                .map_err(|e| {
+                    CliError::new(anyhow::Error::new(e), exit_code::ERROR)
+                })?,
         );
         for edge in graph.graph.edges(i) {
             let dep_index = edge.target();
@@ -195,7 +350,10 @@ fn package_metrics(
                 indices.push(dep_index);
             }
             let dep = graph.graph[dep_index]
-                .to_cargo_geiger_package_id(cargo_metadata_parameters.metadata);
+                .to_cargo_geiger_package_id(cargo_metadata_parameters)
+                .map_err(|e| {
+                    CliError::new(anyhow::Error::new(e), exit_code::ERROR)
+                })?;
 
             package.add_dependency(
                 dep,
@@ -203,18 +361,20 @@ fn package_metrics(
             );
         }
         match geiger_context.package_id_to_metrics.get(&package_id) {
-            Some(m) => package_metrics.push((package, Some(m.clone()))),
+            Some(m) => {
+                package_metrics.push((package_id, package, Some(m.clone())))
+            }
             None => {
                 eprintln!(
                     "WARNING: No metrics found for package: {}",
                     package_id
                 );
-                package_metrics.push((package, None))
+                package_metrics.push((package_id, package, None))
             }
         }
     }
 
-    package_metrics
+    Ok(package_metrics)
 }
 
 #[cfg(test)]
@@ -224,10 +384,32 @@ mod scan_tests {
     use crate::scan::PackageMetrics;
     use rs_file::RsFileMetricsWrapper;
 
-    use cargo_geiger_serde::{Count, UnsafeInfo};
+    use cargo_geiger_serde::{
+        Count, LineColumn, UnsafeFinding, UnsafeInfo, UnsafeKind,
+    };
     use rstest::*;
     use std::{collections::HashSet, path::PathBuf};
 
+    #[rstest(
+        input_check,
+        input_violation_count,
+        expected_message,
+        case("Policy", 1, "Policy check failed with 1 violation"),
+        case("Threshold", 3, "Threshold check failed with 3 violations")
+    )]
+    fn check_failed_error_display_test(
+        input_check: &'static str,
+        input_violation_count: usize,
+        expected_message: &str,
+    ) {
+        let error = CheckFailedError {
+            check: input_check,
+            violation_count: input_violation_count,
+        };
+
+        assert_eq!(error.to_string(), expected_message);
+    }
+
     #[rstest]
     fn construct_rs_files_used_lines_test() {
         let mut rs_files_used = HashSet::<PathBuf>::new();
@@ -248,9 +430,59 @@ mod scan_tests {
         );
     }
 
+    #[rstest(
+        input_target,
+        input_cfgs,
+        expected_target,
+        expected_target_cfgs,
+        case(None, None, "x86_64-unknown-linux-gnu", None),
+        case(
+            Some("wasm32-unknown-unknown"),
+            Some(vec![
+                Cfg::Name(String::from("unix")),
+                Cfg::KeyPair(String::from("target_os"), String::from("linux")),
+            ]),
+            "wasm32-unknown-unknown",
+            Some(vec!["unix", "target_os = \"linux\""])
+        )
+    )]
+    fn construct_report_metadata_test(
+        input_target: Option<&str>,
+        input_cfgs: Option<Vec<Cfg>>,
+        expected_target: &str,
+        expected_target_cfgs: Option<Vec<&str>>,
+    ) {
+        let mut args = Args::default();
+        args.features_args.features = vec![String::from("foo")];
+        args.include_tests = true;
+        args.target_args.target = input_target.map(String::from);
+
+        let metadata = construct_report_metadata(
+            &args,
+            input_cfgs.as_deref(),
+            "1.47.0",
+            "x86_64-unknown-linux-gnu",
+        );
+
+        assert_eq!(metadata.format_version, REPORT_SCHEMA_VERSION);
+        assert_eq!(metadata.rustc_version, "1.47.0");
+        assert_eq!(metadata.target, expected_target);
+        assert_eq!(
+            metadata.target_cfgs,
+            expected_target_cfgs
+                .map(|cfgs| cfgs.into_iter().map(String::from).collect())
+        );
+        assert_eq!(metadata.features, vec![String::from("foo")]);
+        assert!(metadata.include_tests);
+    }
+
     #[rstest]
     fn unsafe_stats_from_nothing_are_empty() {
-        let stats = unsafe_stats(&Default::default(), &Default::default());
+        let stats = unsafe_stats(
+            &Default::default(),
+            &Default::default(),
+            Path::new(""),
+        );
         let expected = UnsafeInfo {
             forbids_unsafe: true,
             ..Default::default()
@@ -268,7 +500,8 @@ mod scan_tests {
                 .set_is_crate_entry_point(true)
                 .build(),
         )]);
-        let stats = unsafe_stats(&metrics, &set_of_paths(&["foo.rs"]));
+        let stats =
+            unsafe_stats(&metrics, &set_of_paths(&["foo.rs"]), Path::new(""));
         assert!(stats.forbids_unsafe)
     }
 
@@ -291,8 +524,11 @@ mod scan_tests {
                     .build(),
             ),
         ]);
-        let stats =
-            unsafe_stats(&metrics, &set_of_paths(&["foo.rs", "bar.rs"]));
+        let stats = unsafe_stats(
+            &metrics,
+            &set_of_paths(&["foo.rs", "bar.rs"]),
+            Path::new(""),
+        );
         assert!(!stats.forbids_unsafe)
     }
 
@@ -310,14 +546,115 @@ mod scan_tests {
                 MetricsBuilder::default().functions(200, 100).build(),
             ),
         ]);
-        let stats =
-            unsafe_stats(&metrics, &set_of_paths(&["foo.rs", "bar.rs"]));
+        let stats = unsafe_stats(
+            &metrics,
+            &set_of_paths(&["foo.rs", "bar.rs"]),
+            Path::new(""),
+        );
         assert_eq!(stats.used.functions.safe, 7);
         assert_eq!(stats.used.functions.unsafe_, 4);
         assert_eq!(stats.unused.functions.safe, 220);
         assert_eq!(stats.unused.functions.unsafe_, 110);
     }
 
+    #[rstest]
+    fn unsafe_stats_collect_sorted_unsafe_locations() {
+        let metrics = metrics_from_iter(vec![
+            (
+                "/ws/foo/src/foo.rs",
+                MetricsBuilder::default()
+                    .finding(UnsafeKind::Expression, 7)
+                    .finding(UnsafeKind::Function, 3)
+                    .build(),
+            ),
+            (
+                "/generated/bar.rs",
+                MetricsBuilder::default()
+                    .finding(UnsafeKind::Method, 12)
+                    .build(),
+            ),
+        ]);
+        let stats = unsafe_stats(
+            &metrics,
+            &set_of_paths(&["/ws/foo/src/foo.rs"]),
+            Path::new("/ws/foo"),
+        );
+        let locations = stats
+            .unsafe_locations
+            .iter()
+            .map(|l| (l.file.clone(), l.used, l.finding.kind, l.finding.start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec![
+                (
+                    PathBuf::from("/generated/bar.rs"),
+                    false,
+                    UnsafeKind::Method,
+                    LineColumn {
+                        line: 12,
+                        column: 1
+                    }
+                ),
+                (
+                    PathBuf::from("src/foo.rs"),
+                    true,
+                    UnsafeKind::Function,
+                    LineColumn { line: 3, column: 1 }
+                ),
+                (
+                    PathBuf::from("src/foo.rs"),
+                    true,
+                    UnsafeKind::Expression,
+                    LineColumn { line: 7, column: 1 }
+                ),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn file_stats_test() {
+        let metrics = metrics_from_iter(vec![
+            (
+                "/ws/foo/src/lib.rs",
+                MetricsBuilder::default()
+                    .forbids_unsafe(true)
+                    .set_is_crate_entry_point(true)
+                    .build(),
+            ),
+            (
+                "/ws/foo/src/bar.rs",
+                MetricsBuilder::default().functions(2, 1).build(),
+            ),
+            (
+                "/generated/baz.rs",
+                MetricsBuilder::default().functions(0, 3).build(),
+            ),
+        ]);
+        let files = file_stats(
+            &metrics,
+            &set_of_paths(&["/ws/foo/src/lib.rs", "/ws/foo/src/bar.rs"]),
+            Path::new("/ws/foo"),
+        );
+        assert_eq!(
+            files
+                .iter()
+                .map(|f| (
+                    f.path.clone(),
+                    f.is_entry_point,
+                    f.used,
+                    f.counters.functions.unsafe_,
+                    f.forbids_unsafe
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (PathBuf::from("/generated/baz.rs"), false, false, 3, false),
+                (PathBuf::from("src/bar.rs"), false, true, 1, false),
+                (PathBuf::from("src/lib.rs"), true, true, 0, true),
+            ]
+        );
+    }
+
     fn metrics_from_iter<I, P>(it: I) -> PackageMetrics
     where
         I: IntoIterator<Item = (P, RsFileMetricsWrapper)>,
@@ -355,6 +692,17 @@ mod scan_tests {
             self
         }
 
+        fn finding(mut self, kind: UnsafeKind, line: usize) -> Self {
+            let position = LineColumn { line, column: 1 };
+            self.inner.metrics.unsafe_findings.push(UnsafeFinding {
+                kind,
+                item_path: String::from("f"),
+                start: position,
+                end: position,
+            });
+            self
+        }
+
         fn set_is_crate_entry_point(mut self, yes: bool) -> Self {
             self.inner.is_crate_entry_point = yes;
             self
@@ -0,0 +1,393 @@
+use crate::mapping::GetFeaturesFromCargoMetadataPackageId;
+
+use cargo::core::Workspace;
+use cargo::ops;
+use cargo::util::Sha256;
+use cargo_metadata::{Metadata, PackageId};
+use cargo_platform::Cfg;
+use filetime::FileTime;
+use geiger::{
+    find_unsafe_in_file, ActiveCfgs, IncludeTests, RsFileMetrics,
+    ScanFileError, ANALYSIS_FORMAT_VERSION,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// Entries that were not used for this long are removed by `prune`.
+const MAX_ENTRY_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// `prune` walks the whole cache, it runs at most this often.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Touched after every `prune`, its modification time is the time of the
+/// last one. Kept in the directory of the current version, the cache
+/// directory itself can be shared with other tools.
+const LAST_PRUNE_FILE_NAME: &str = "last-prune";
+
+/// An on-disk cache of the scan results of single `.rs` files, stored as one
+/// JSON file per scanned `.rs` file.
+///
+/// Files of registry packages are keyed by the package id and the package
+/// checksum from `Cargo.lock`, since published packages never change. Files
+/// of other dependencies, like path and git dependencies, are keyed by their
+/// content. Workspace members are never cached. All keys also cover
+/// everything else that affects the result of a scan: the `IncludeTests`
+/// setting, the target cfgs and the enabled features of the package.
+///
+/// The entries of every `geiger` version and analysis format are kept in a
+/// directory of their own. Directories of other versions and entries that
+/// were not used for a while are removed, nothing else in the cache
+/// directory is touched.
+#[derive(Clone, Debug)]
+pub struct ScanCache {
+    dir: PathBuf,
+    include_tests: IncludeTests,
+    package_id_to_cache_key: HashMap<PackageId, PackageCacheKey>,
+}
+
+#[derive(Clone, Debug)]
+struct PackageCacheKey {
+    /// The package is known to never change, the file contents don't need
+    /// to be part of the key.
+    is_immutable: bool,
+
+    /// Everything that the scan results of all files of the package depend
+    /// on, besides the file itself.
+    key: String,
+}
+
+impl ScanCache {
+    pub fn new(
+        dir: PathBuf,
+        cfgs: Option<&[Cfg]>,
+        include_tests: IncludeTests,
+        metadata: &Metadata,
+        workspace: &Workspace,
+    ) -> Self {
+        // The cache only saves time, failing to prune it is not an error.
+        let _ = prune(&dir, SystemTime::now());
+        let dir = dir.join(version_dir_name());
+
+        // Keyed by name, version and source id, which is all that the
+        // packages of `Cargo.lock` and of the cargo metadata have in common.
+        let checksums = ops::load_pkg_lockfile(workspace)
+            .ok()
+            .flatten()
+            .map(|resolve| {
+                resolve
+                    .checksums()
+                    .iter()
+                    .filter_map(|(package_id, checksum)| {
+                        Some((
+                            (
+                                package_id.name().to_string(),
+                                package_id.version().to_string(),
+                                package_id.source_id().into_url().to_string(),
+                            ),
+                            checksum.clone()?,
+                        ))
+                    })
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        let cfgs = cfgs
+            .map(|cfgs| {
+                cfgs.iter()
+                    .map(Cfg::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .unwrap_or_default();
+
+        let mut package_id_to_cache_key = HashMap::new();
+        for package in &metadata.packages {
+            if metadata.workspace_members.contains(&package.id) {
+                continue;
+            }
+            let features = metadata
+                .get_features_from_cargo_metadata_package_id(&package.id)
+                .unwrap_or_default()
+                .join(",");
+            let checksum = package.source.as_ref().and_then(|source| {
+                checksums.get(&(
+                    package.name.clone(),
+                    package.version.to_string(),
+                    source.repr.clone(),
+                ))
+            });
+            let key = format!(
+                "{:?}\n{}\n{}\n{}\n{}",
+                include_tests,
+                cfgs,
+                features,
+                package.id.repr,
+                checksum.map(String::as_str).unwrap_or_default(),
+            );
+            package_id_to_cache_key.insert(
+                package.id.clone(),
+                PackageCacheKey {
+                    is_immutable: checksum.is_some(),
+                    key,
+                },
+            );
+        }
+
+        ScanCache {
+            dir,
+            include_tests,
+            package_id_to_cache_key,
+        }
+    }
+
+    /// Returns the cached scan result of the file, or scans it and stores the
+    /// result in the cache.
+    pub fn find_unsafe_in_file(
+        &self,
+        package_id: &PackageId,
+        path: &Path,
+        active_cfgs: &ActiveCfgs,
+    ) -> Result<RsFileMetrics, ScanFileError> {
+        let entry_path = match self.entry_path(package_id, path) {
+            Some(entry_path) => entry_path,
+            None => {
+                return find_unsafe_in_file(
+                    path,
+                    self.include_tests,
+                    active_cfgs,
+                )
+            }
+        };
+        if let Some(rs_file_metrics) = read_entry(&entry_path) {
+            // The modification time is the time of the last use, see `prune`.
+            let _ = filetime::set_file_mtime(&entry_path, FileTime::now());
+            return Ok(rs_file_metrics);
+        }
+        let rs_file_metrics =
+            find_unsafe_in_file(path, self.include_tests, active_cfgs)?;
+        // The cache only saves time, failing to write to it is not an error.
+        let _ = write_entry(&entry_path, &rs_file_metrics);
+        Ok(rs_file_metrics)
+    }
+
+    /// The path of the cache entry for the file, `None` if the file should
+    /// not be cached.
+    fn entry_path(
+        &self,
+        package_id: &PackageId,
+        path: &Path,
+    ) -> Option<PathBuf> {
+        let package_cache_key = self.package_id_to_cache_key.get(package_id)?;
+        let mut sha256 = Sha256::new();
+        sha256.update(package_cache_key.key.as_bytes());
+        sha256.update(path.to_string_lossy().as_bytes());
+        if !package_cache_key.is_immutable {
+            sha256.update(&fs::read(path).ok()?);
+        }
+        let key = sha256.finish_hex();
+        Some(self.dir.join(&key[..2]).join(format!("{}.json", key)))
+    }
+}
+
+/// The directory of the entries written by this `geiger` version and
+/// analysis format.
+fn version_dir_name() -> String {
+    format!(
+        "geiger-{}-format-{}",
+        geiger::VERSION,
+        ANALYSIS_FORMAT_VERSION
+    )
+}
+
+/// Whether `name` is the name of a directory returned by `version_dir_name`,
+/// of any version.
+fn is_version_dir_name(name: &str) -> bool {
+    let rest = match name.strip_prefix("geiger-") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    match rest.rfind("-format-") {
+        Some(i) => i > 0 && rest[i + "-format-".len()..].parse::<u32>().is_ok(),
+        None => false,
+    }
+}
+
+/// Removes the directories of other versions and the entries that were not
+/// used within `MAX_ENTRY_AGE`, unless that was already done within
+/// `PRUNE_INTERVAL`.
+fn prune(dir: &Path, now: SystemTime) -> io::Result<()> {
+    let is_older_than = |path: &Path, age: Duration| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| modified + age < now)
+            .unwrap_or(true)
+    };
+    let version_dir_name = version_dir_name();
+    let version_dir = dir.join(&version_dir_name);
+    let last_prune_path = version_dir.join(LAST_PRUNE_FILE_NAME);
+    if !is_older_than(&last_prune_path, PRUNE_INTERVAL) {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let is_other_version_dir = match entry.file_name().to_str() {
+            Some(name) => name != version_dir_name && is_version_dir_name(name),
+            None => false,
+        };
+        if is_other_version_dir && entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    for entry in WalkDir::new(&version_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| entry.path() != last_prune_path)
+    {
+        if is_older_than(entry.path(), MAX_ENTRY_AGE) {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    fs::create_dir_all(&version_dir)?;
+    fs::write(last_prune_path, b"")
+}
+
+fn read_entry(entry_path: &Path) -> Option<RsFileMetrics> {
+    let entry = fs::read(entry_path).ok()?;
+    serde_json::from_slice(&entry).ok()
+}
+
+fn write_entry(
+    entry_path: &Path,
+    rs_file_metrics: &RsFileMetrics,
+) -> io::Result<()> {
+    if let Some(parent) = entry_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write to a temporary file first, other cargo-geiger processes could be
+    // reading the same entry.
+    let temp_path = entry_path.with_extension(format!("{}.tmp", process::id()));
+    fs::write(&temp_path, serde_json::to_vec(rs_file_metrics)?)?;
+    fs::rename(&temp_path, entry_path)
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    use crate::cli::get_workspace;
+
+    use cargo::Config;
+    use cargo_metadata::MetadataCommand;
+    use rstest::*;
+    use tempfile::tempdir;
+
+    #[rstest]
+    fn scan_cache_find_unsafe_in_file_test() {
+        let config = Config::default().unwrap();
+        let workspace = get_workspace(&config, None).unwrap();
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()
+            .unwrap();
+        let cache_dir = tempdir().unwrap();
+        let scan_cache = ScanCache::new(
+            cache_dir.path().to_path_buf(),
+            None,
+            IncludeTests::No,
+            &metadata,
+            &workspace,
+        );
+
+        // Workspace members are not cached.
+        let root_package = metadata.root_package().unwrap();
+        let main_rs = root_package.manifest_path.with_file_name("src/main.rs");
+        assert!(scan_cache.entry_path(&root_package.id, &main_rs).is_none());
+
+        let dependency = metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "walkdir")
+            .unwrap();
+        let lib_rs = dependency.manifest_path.with_file_name("src/lib.rs");
+        let entry_path =
+            scan_cache.entry_path(&dependency.id, &lib_rs).unwrap();
+        assert!(entry_path.starts_with(cache_dir.path()));
+        assert!(!entry_path.exists());
+
+        let active_cfgs = ActiveCfgs::default();
+        let rs_file_metrics = scan_cache
+            .find_unsafe_in_file(&dependency.id, &lib_rs, &active_cfgs)
+            .unwrap();
+        assert!(entry_path.exists());
+        assert_eq!(read_entry(&entry_path), Some(rs_file_metrics.clone()));
+        // A cache hit marks the entry as used, so `prune` keeps it.
+        let written = SystemTime::now() - MAX_ENTRY_AGE;
+        filetime::set_file_mtime(&entry_path, FileTime::from(written)).unwrap();
+        assert_eq!(
+            scan_cache
+                .find_unsafe_in_file(&dependency.id, &lib_rs, &active_cfgs)
+                .unwrap(),
+            rs_file_metrics
+        );
+        let used = fs::metadata(&entry_path).unwrap().modified().unwrap();
+        assert!(used > written + PRUNE_INTERVAL);
+    }
+
+    #[rstest(
+        input_name,
+        expected_is_version_dir_name,
+        case("geiger-0.4.5-format-1", true),
+        case("geiger-0.5.0-rc.1-format-12", true),
+        case("geiger-cache", false),
+        case("geiger--format-1", false),
+        case("geiger-0.4.5-format-x", false),
+        case("src", false),
+        case(".git", false)
+    )]
+    fn is_version_dir_name_test(
+        input_name: &str,
+        expected_is_version_dir_name: bool,
+    ) {
+        assert_eq!(
+            is_version_dir_name(input_name),
+            expected_is_version_dir_name
+        );
+    }
+
+    #[rstest]
+    fn prune_test() {
+        let cache_dir = tempdir().unwrap();
+        let old_version_dir = cache_dir.path().join("geiger-0.0.1-format-1");
+        let version_dir = cache_dir.path().join(version_dir_name());
+        let entry_path = version_dir.join("ab").join("abcd.json");
+        let other_dir = cache_dir.path().join("src");
+        fs::create_dir_all(&old_version_dir).unwrap();
+        fs::create_dir_all(entry_path.parent().unwrap()).unwrap();
+        fs::create_dir_all(&other_dir).unwrap();
+        fs::write(&entry_path, b"{}").unwrap();
+
+        // Recently written entries and directories not made by geiger are
+        // kept.
+        prune(cache_dir.path(), SystemTime::now()).unwrap();
+        assert!(!old_version_dir.exists());
+        assert!(entry_path.exists());
+        assert!(other_dir.exists());
+
+        // Nothing is pruned again within `PRUNE_INTERVAL`.
+        fs::create_dir_all(&old_version_dir).unwrap();
+        prune(cache_dir.path(), SystemTime::now()).unwrap();
+        assert!(old_version_dir.exists());
+
+        let later = SystemTime::now() + MAX_ENTRY_AGE + PRUNE_INTERVAL;
+        prune(cache_dir.path(), later).unwrap();
+        assert!(!old_version_dir.exists());
+        assert!(!entry_path.exists());
+        assert!(version_dir.exists());
+        assert!(other_dir.exists());
+    }
+}
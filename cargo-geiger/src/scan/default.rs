@@ -1,30 +1,50 @@
+mod baseline;
 mod table;
 
 use crate::args::FeaturesArgs;
+use crate::cli::get_cfgs;
+use crate::diff::read_report;
+use crate::exit_code;
+use crate::format::cyclonedx::to_cyclonedx;
+use crate::format::dot::{to_dot, DependencyEdge};
+use crate::format::html::to_html;
+use crate::format::markdown::to_markdown;
 use crate::format::print_config::OutputFormat;
+use crate::format::sarif::to_sarif;
 use crate::graph::Graph;
-use crate::mapping::CargoMetadataParameters;
-use crate::scan::rs_file::resolve_rs_file_deps;
+use crate::mapping::{
+    CargoMetadataParameters, GetRoot, SourceMappingError, ToCargoCorePackageId,
+    ToCargoGeigerDependencyKind, ToCargoGeigerPackageId,
+    ToCargoMetadataPackage,
+};
+use crate::scan::rs_file::{
+    resolve_rs_file_deps, resolve_rs_file_deps_from_module_tree,
+};
+use crate::tree::traversal::walk_dependency_tree;
+use crate::tree::TextTreeLine;
 
+use super::cache::ScanCache;
 use super::find::find_unsafe;
 use super::{
-    list_files_used_but_not_scanned, package_metrics, unsafe_stats,
-    ScanDetails, ScanMode, ScanParameters,
+    file_stats, list_files_used_but_not_scanned, package_metrics,
+    report_metadata, unsafe_stats, GeigerContext, RsFilesUsed, ScanDetails,
+    ScanMode, ScanParameters,
 };
 
+use baseline::{compare_to_baseline, update_baseline};
 use table::scan_to_table;
 
 use cargo::core::compiler::CompileMode;
-use cargo::core::Workspace;
-use cargo::ops::CompileOptions;
-use cargo::{CliError, CliResult, Config};
-use cargo_geiger_serde::{ReportEntry, SafetyReport};
+use cargo::core::{PackageIdSpec, Workspace};
+use cargo::ops::{CompileOptions, Packages};
+use cargo::{CargoResult, CliError, CliResult, Config};
+use cargo_geiger_serde::{ReportEntry, ReportMetadata, SafetyReport};
 use cargo_metadata::PackageId;
 
 pub fn scan_unsafe(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
     workspace: &Workspace,
 ) -> CliResult {
@@ -33,14 +53,14 @@ pub fn scan_unsafe(
             cargo_metadata_parameters,
             graph,
             output_format,
-            root_package_id,
+            root_package_ids,
             scan_parameters,
             workspace,
         ),
         None => scan_to_table(
             cargo_metadata_parameters,
             graph,
-            root_package_id,
+            root_package_ids,
             scan_parameters,
             workspace,
         ),
@@ -133,46 +153,255 @@ fn build_compile_options<'a>(
 
 fn scan(
     cargo_metadata_parameters: &CargoMetadataParameters,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
     workspace: &Workspace,
 ) -> Result<ScanDetails, CliError> {
-    let compile_options = build_compile_options(
-        &scan_parameters.args.features_args,
+    let cfgs = get_cfgs(
+        scan_parameters.config,
+        &scan_parameters.args.target_args.target,
+        workspace,
+    )?;
+    let report_metadata = report_metadata(
+        scan_parameters.args,
+        cfgs.as_deref(),
         scan_parameters.config,
-    );
-    let rs_files_used =
-        resolve_rs_file_deps(&compile_options, workspace).unwrap();
+        workspace,
+    )?;
+    let rs_files_used = if scan_parameters.args.no_build {
+        resolve_rs_file_deps_from_module_tree(
+            cargo_metadata_parameters.metadata,
+            graph,
+            cfgs.as_deref(),
+            scan_parameters.print_config.include_tests,
+        )
+    } else {
+        let mut compile_options = build_compile_options(
+            &scan_parameters.args.features_args,
+            scan_parameters.config,
+        );
+        compile_options.spec = Packages::from_flags(
+            scan_parameters.args.workspace,
+            scan_parameters.args.exclude.clone(),
+            package_specs(
+                cargo_metadata_parameters,
+                root_package_ids,
+                scan_parameters,
+            )?,
+        )?;
+        resolve_rs_file_deps(
+            &compile_options,
+            cargo_metadata_parameters.metadata,
+            scan_parameters.args.target_dir.as_deref(),
+            workspace,
+        )
+        .map_err(|e| CliError::new(anyhow::Error::new(e), exit_code::ERROR))?
+    };
+    let scan_cache = if scan_parameters.args.no_cache {
+        None
+    } else {
+        let cache_dir = match &scan_parameters.args.cache_dir {
+            Some(path) => scan_parameters.config.cwd().join(path),
+            None => scan_parameters
+                .config
+                .home()
+                .as_path_unlocked()
+                .join("geiger-cache"),
+        };
+        Some(ScanCache::new(
+            cache_dir,
+            cfgs.as_deref(),
+            scan_parameters.print_config.include_tests,
+            cargo_metadata_parameters.metadata,
+            workspace,
+        ))
+    };
     let geiger_context = find_unsafe(
         cargo_metadata_parameters,
         scan_parameters.config,
+        cfgs.as_deref(),
+        graph,
         ScanMode::Full,
         scan_parameters.print_config,
+        scan_cache.as_ref(),
     )?;
     Ok(ScanDetails {
         rs_files_used,
         geiger_context,
+        report_metadata,
     })
 }
 
+/// The packages selected with `-p` as specs for the build, which already
+/// resolved to a single package each. The specs include the source, so they
+/// still select a single package if another source has one with the same
+/// name and version. Empty if no packages were selected.
+fn package_specs(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    root_package_ids: &[PackageId],
+    scan_parameters: &ScanParameters,
+) -> CargoResult<Vec<String>> {
+    if scan_parameters.args.package.is_empty() {
+        return Ok(Vec::new());
+    }
+    root_package_ids
+        .iter()
+        .filter_map(|package_id| {
+            package_id
+                .to_cargo_metadata_package(cargo_metadata_parameters.metadata)
+        })
+        .map(|package| {
+            let package_id = package.to_cargo_core_package_id()?;
+            Ok(PackageIdSpec::from_package_id(package_id).to_string())
+        })
+        .collect()
+}
+
 fn scan_to_report(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
     output_format: OutputFormat,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
     workspace: &Workspace,
 ) -> CliResult {
     let ScanDetails {
         rs_files_used,
         geiger_context,
-    } = scan(cargo_metadata_parameters, scan_parameters, workspace)?;
-    let mut report = SafetyReport::default();
-    for (package, package_metrics_option) in package_metrics(
+        report_metadata,
+    } = scan(
+        cargo_metadata_parameters,
+        graph,
+        root_package_ids,
+        scan_parameters,
+        workspace,
+    )?;
+    let report = safety_report(
         cargo_metadata_parameters,
         &geiger_context,
         graph,
-        root_package_id,
-    ) {
+        root_package_ids,
+        &rs_files_used,
+        report_metadata,
+        scan_parameters.args.per_file,
+    )?;
+    let s = match output_format {
+        OutputFormat::CycloneDx => {
+            serde_json::to_string(&to_cyclonedx(&report)).unwrap()
+        }
+        OutputFormat::Dot => to_dot(
+            &report,
+            &dependency_edges(cargo_metadata_parameters, graph)?,
+            scan_parameters.args.invert,
+        ),
+        OutputFormat::Html => to_html(
+            &report,
+            &dependency_tree(
+                cargo_metadata_parameters,
+                graph,
+                root_package_ids,
+                scan_parameters,
+            )?,
+        ),
+        OutputFormat::Json => serde_json::to_string(&report).unwrap(),
+        OutputFormat::Markdown => {
+            // The baseline is about to be replaced with `--update-baseline`,
+            // there is nothing to compare to.
+            let baseline = match &scan_parameters.args.baseline {
+                Some(path) if !scan_parameters.args.update_baseline => {
+                    Some(read_report(&scan_parameters.config.cwd().join(path))?)
+                }
+                _ => None,
+            };
+            to_markdown(&report, scan_parameters.args.top, baseline.as_ref())
+        }
+        OutputFormat::Sarif => {
+            serde_json::to_string(&to_sarif(&report, workspace.root())).unwrap()
+        }
+    };
+    println!("{}", s);
+    if let Some(log_path) = &scan_parameters.args.unsafe_fn_log {
+        log_unsafe_functions(log_path, &report)?;
+    }
+    check_report(scan_parameters, &report)
+}
+
+/// Every edge of the dependency graph, `to_dot` leaves out the packages that
+/// are not in the report.
+fn dependency_edges(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    graph: &Graph,
+) -> Result<Vec<DependencyEdge>, CliError> {
+    graph
+        .graph
+        .raw_edges()
+        .iter()
+        .map(|edge| {
+            Ok((
+                graph.graph[edge.source()]
+                    .to_cargo_geiger_package_id(cargo_metadata_parameters)?,
+                graph.graph[edge.target()]
+                    .to_cargo_geiger_package_id(cargo_metadata_parameters)?,
+                edge.weight.to_cargo_geiger_dependency_kind(),
+            ))
+        })
+        .collect::<Result<_, SourceMappingError>>()
+        .map_err(|e| CliError::new(anyhow::Error::new(e), exit_code::ERROR))
+}
+
+/// The packages in the trees of all root packages, in the order of the table,
+/// each with its depth in the tree.
+fn dependency_tree(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
+    scan_parameters: &ScanParameters,
+) -> Result<Vec<(usize, cargo_geiger_serde::PackageId)>, CliError> {
+    let mut tree = Vec::new();
+    for root_package_id in root_package_ids {
+        for text_tree_line in walk_dependency_tree(
+            cargo_metadata_parameters,
+            graph,
+            scan_parameters.print_config,
+            root_package_id.clone(),
+        ) {
+            if let TextTreeLine::Package { id, depth, .. } = text_tree_line {
+                tree.push((
+                    depth,
+                    id.to_cargo_geiger_package_id(cargo_metadata_parameters)
+                        .map_err(|e| {
+                            CliError::new(
+                                anyhow::Error::new(e),
+                                exit_code::ERROR,
+                            )
+                        })?,
+                ));
+            }
+        }
+    }
+    Ok(tree)
+}
+
+fn safety_report(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    geiger_context: &GeigerContext,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
+    rs_files_used: &RsFilesUsed,
+    report_metadata: ReportMetadata,
+    per_file: bool,
+) -> Result<SafetyReport, CliError> {
+    let mut report = SafetyReport {
+        metadata: report_metadata,
+        ..Default::default()
+    };
+    for (package_id, package, package_metrics_option) in package_metrics(
+        cargo_metadata_parameters,
+        geiger_context,
+        graph,
+        root_package_ids,
+    )? {
         let package_metrics = match package_metrics_option {
             Some(m) => m,
             None => {
@@ -180,25 +409,80 @@ fn scan_to_report(
                 continue;
             }
         };
-        let unsafe_info = unsafe_stats(&package_metrics, &rs_files_used);
+        let package_rs_files_used = rs_files_used.used_by_package(&package_id);
+        let package_root = package_id
+            .to_cargo_metadata_package(cargo_metadata_parameters.metadata)
+            .map(|metadata_package| metadata_package.get_root())
+            .unwrap_or_default();
+        let unsafe_info = unsafe_stats(
+            &package_metrics,
+            &package_rs_files_used,
+            &package_root,
+        );
+        let files = if per_file {
+            Some(file_stats(
+                &package_metrics,
+                &package_rs_files_used,
+                &package_root,
+            ))
+        } else {
+            None
+        };
         let entry = ReportEntry {
             package,
             unsafety: unsafe_info,
+            files,
         };
         report.packages.insert(entry.package.id.clone(), entry);
     }
     report.used_but_not_scanned_files =
-        list_files_used_but_not_scanned(&geiger_context, &rs_files_used)
+        list_files_used_but_not_scanned(geiger_context, &rs_files_used.all())
             .into_iter()
             .collect();
-    let s = match output_format {
-        OutputFormat::Json => serde_json::to_string(&report).unwrap(),
+    Ok(report)
+}
+
+/// Checks the report against the policy, the thresholds and the baseline. All
+/// problems are printed, the error of the first failed check is returned.
+fn check_report(
+    scan_parameters: &ScanParameters,
+    report: &SafetyReport,
+) -> CliResult {
+    let policy_result = match scan_parameters.policy {
+        Some(policy) => policy.check_report(report),
+        None => Ok(()),
     };
-    println!("{}", s);
-    if let Some(log_path) = &scan_parameters.args.unsafe_fn_log {
-        log_unsafe_functions(log_path, &report)?;
+    let thresholds = match scan_parameters.policy {
+        Some(policy) => policy
+            .thresholds
+            .overridden_by(&scan_parameters.args.thresholds),
+        None => scan_parameters.args.thresholds.clone(),
+    };
+    let thresholds_result = thresholds.check_report(report);
+    let baseline_result = check_baseline(scan_parameters, report);
+    policy_result.and(thresholds_result).and(baseline_result)
+}
+
+/// Compares the report to the `--baseline` report, or replaces the baseline
+/// with `--update-baseline`. Does nothing without `--baseline`.
+fn check_baseline(
+    scan_parameters: &ScanParameters,
+    report: &SafetyReport,
+) -> CliResult {
+    let baseline_path = match &scan_parameters.args.baseline {
+        Some(path) => scan_parameters.config.cwd().join(path),
+        None => return Ok(()),
+    };
+    if scan_parameters.args.update_baseline {
+        update_baseline(&baseline_path, report)?;
+        scan_parameters.config.shell().status(
+            "Updated",
+            format!("baseline `{}`", baseline_path.display()),
+        )?;
+        Ok(())
+    } else {
+        compare_to_baseline(&baseline_path, report)
     }
-    Ok(())
 }
 
 #[cfg(test)]
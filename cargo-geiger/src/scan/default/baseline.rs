@@ -0,0 +1,233 @@
+//! The `--baseline` ratchet, failing when unsafe usage grows compared to a
+//! committed report.
+
+use crate::diff::read_report;
+use crate::exit_code;
+use crate::format::package_label;
+
+use cargo::{CliError, CliResult};
+use cargo_geiger_serde::{
+    diff_reports, CountDiff, CounterBlockDiff, SafetyReport,
+};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Fails if the report has regressed compared to the baseline report stored
+/// at `baseline_path`, after printing every regression.
+pub fn compare_to_baseline(
+    baseline_path: &Path,
+    report: &SafetyReport,
+) -> CliResult {
+    let baseline = read_report(baseline_path)?;
+    let regressions = find_regressions(&baseline, report);
+    for regression in &regressions {
+        eprintln!("REGRESSION: {}", regression);
+    }
+    if regressions.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::new(
+            anyhow::Error::new(BaselineRegressionError {
+                baseline_path: baseline_path.to_path_buf(),
+                regression_count: regressions.len(),
+            }),
+            exit_code::BASELINE_REGRESSION,
+        ))
+    }
+}
+
+pub fn update_baseline(
+    baseline_path: &Path,
+    report: &SafetyReport,
+) -> CliResult {
+    // Pretty printed, the baseline is meant to be committed and reviewed.
+    let mut s = serde_json::to_string_pretty(report).unwrap();
+    s.push('\n');
+    fs::write(baseline_path, s).map_err(|e| {
+        anyhow::Error::new(e).context(format!(
+            "failed to write baseline `{}`",
+            baseline_path.display()
+        ))
+    })?;
+    Ok(())
+}
+
+#[derive(Debug)]
+struct BaselineRegressionError {
+    baseline_path: PathBuf,
+    regression_count: usize,
+}
+
+impl Error for BaselineRegressionError {}
+
+impl fmt::Display for BaselineRegressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} regressed compared to baseline `{}`",
+            self.regression_count,
+            if self.regression_count == 1 {
+                "package"
+            } else {
+                "packages"
+            },
+            self.baseline_path.display()
+        )
+    }
+}
+
+/// A package regressed if the unsafe code used by the build grew, if it no
+/// longer forbids unsafe code, or if it is new and uses unsafe code. Unsafe
+/// code that is not used by the build is ignored.
+fn find_regressions(
+    baseline: &SafetyReport,
+    report: &SafetyReport,
+) -> Vec<String> {
+    let report_diff = diff_reports(baseline, report);
+    let mut regressions = Vec::new();
+    for entry in &report_diff.added_packages {
+        if entry.unsafety.used.has_unsafe() {
+            regressions.push(format!(
+                "`{}` is not in the baseline and uses unsafe code",
+                package_label(&entry.package.id)
+            ));
+        }
+    }
+    for package_diff in &report_diff.changed_packages {
+        let label = if package_diff.is_version_change() {
+            format!(
+                "`{}` (`{}` in the baseline)",
+                package_label(&package_diff.new_id),
+                package_label(&package_diff.old_id)
+            )
+        } else {
+            format!("`{}`", package_label(&package_diff.new_id))
+        };
+        let increases = unsafe_increases(&package_diff.used);
+        if !increases.is_empty() {
+            regressions.push(format!(
+                "{} uses more unsafe code: {}",
+                label,
+                increases.join(", ")
+            ));
+        }
+        if package_diff.old_forbids_unsafe && !package_diff.new_forbids_unsafe {
+            regressions
+                .push(format!("{} no longer forbids unsafe code", label));
+        }
+    }
+    regressions
+}
+
+fn unsafe_increases(used: &CounterBlockDiff) -> Vec<String> {
+    let counts: [(&str, &CountDiff); 6] = [
+        ("functions", &used.functions),
+        ("expressions", &used.exprs),
+        ("impls", &used.item_impls),
+        ("traits", &used.item_traits),
+        ("methods", &used.methods),
+        ("macros", &used.macros),
+    ];
+    counts
+        .iter()
+        .filter(|(_, count)| count.unsafe_ > 0)
+        .map(|(name, count)| format!("{} +{}", name, count.unsafe_))
+        .collect()
+}
+
+#[cfg(test)]
+mod baseline_tests {
+    use super::*;
+
+    use crate::test_fixtures::{create_report, create_report_entry};
+
+    use cargo_geiger_serde::ReportEntry;
+    use rstest::*;
+
+    #[rstest(
+        input_baseline_entries,
+        input_report_entries,
+        expected_regressions,
+        case(
+            vec![("foo", "1.0.0", 2, 0, false)],
+            vec![("foo", "1.0.0", 2, 0, false)],
+            vec![]
+        ),
+        case(
+            vec![("foo", "1.0.0", 2, 0, false), ("bar", "0.1.0", 1, 0, false)],
+            vec![("foo", "1.0.0", 1, 5, false)],
+            vec![]
+        ),
+        case(
+            vec![("foo", "1.0.0", 2, 0, false)],
+            vec![("foo", "1.1.0", 3, 0, false)],
+            vec![
+                "`foo 1.1.0` (`foo 1.0.0` in the baseline) uses more unsafe \
+                 code: expressions +1"
+            ]
+        ),
+        case(
+            vec![("foo", "1.0.0", 0, 0, true)],
+            vec![("foo", "1.0.0", 0, 0, false)],
+            vec!["`foo 1.0.0` no longer forbids unsafe code"]
+        ),
+        case(
+            vec![("foo", "1.0.0", 0, 0, true)],
+            vec![
+                ("foo", "1.0.0", 0, 0, true),
+                ("bar", "0.1.0", 0, 3, false),
+                ("baz", "0.2.0", 1, 0, false)
+            ],
+            vec!["`baz 0.2.0` is not in the baseline and uses unsafe code"]
+        )
+    )]
+    fn find_regressions_test(
+        input_baseline_entries: Vec<(&str, &str, u64, u64, bool)>,
+        input_report_entries: Vec<(&str, &str, u64, u64, bool)>,
+        expected_regressions: Vec<&str>,
+    ) {
+        let baseline = create_report(to_report_entries(input_baseline_entries));
+        let report = create_report(to_report_entries(input_report_entries));
+
+        assert_eq!(find_regressions(&baseline, &report), expected_regressions);
+    }
+
+    #[rstest(
+        input_regression_count,
+        expected_message,
+        case(
+            1,
+            "1 package regressed compared to baseline `geiger-baseline.json`"
+        ),
+        case(
+            3,
+            "3 packages regressed compared to baseline `geiger-baseline.json`"
+        )
+    )]
+    fn baseline_regression_error_display_test(
+        input_regression_count: usize,
+        expected_message: &str,
+    ) {
+        let error = BaselineRegressionError {
+            baseline_path: PathBuf::from("geiger-baseline.json"),
+            regression_count: input_regression_count,
+        };
+
+        assert_eq!(error.to_string(), expected_message);
+    }
+
+    /// Entries of `(name, version, used unsafe exprs, unused unsafe exprs,
+    /// forbids unsafe)`.
+    fn to_report_entries(
+        entries: Vec<(&str, &str, u64, u64, bool)>,
+    ) -> Vec<ReportEntry> {
+        entries
+            .into_iter()
+            .map(|(name, version, used, unused, forbids_unsafe)| {
+                create_report_entry(name, version, used, unused, forbids_unsafe)
+            })
+            .collect()
+    }
+}
@@ -1,3 +1,4 @@
+use crate::exit_code;
 use crate::format::emoji_symbols::EmojiSymbols;
 use crate::format::table::{
     create_table_from_text_tree_lines, TableParameters, UNSAFE_COUNTERS_HEADER,
@@ -11,7 +12,7 @@ use super::super::{
     construct_rs_files_used_lines, list_files_used_but_not_scanned,
     ScanDetails, ScanParameters,
 };
-use super::scan;
+use super::{check_report, safety_report, scan};
 
 use cargo::core::shell::Verbosity;
 use cargo::core::Workspace;
@@ -24,7 +25,7 @@ use std::fmt;
 pub fn scan_to_table(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
     workspace: &Workspace,
 ) -> CliResult {
@@ -33,11 +34,18 @@ pub fn scan_to_table(
     let ScanDetails {
         rs_files_used,
         geiger_context,
-    } = scan(cargo_metadata_parameters, scan_parameters, workspace)?;
+        report_metadata,
+    } = scan(
+        cargo_metadata_parameters,
+        graph,
+        root_package_ids,
+        scan_parameters,
+        workspace,
+    )?;
 
     if scan_parameters.print_config.verbosity == Verbosity::Verbose {
         let mut rs_files_used_lines =
-            construct_rs_files_used_lines(&rs_files_used);
+            construct_rs_files_used_lines(&rs_files_used.all());
         scan_output_lines.append(&mut rs_files_used_lines);
     }
 
@@ -45,12 +53,18 @@ pub fn scan_to_table(
     let mut output_key_lines = construct_key_lines(&emoji_symbols);
     scan_output_lines.append(&mut output_key_lines);
 
-    let text_tree_lines = walk_dependency_tree(
-        cargo_metadata_parameters,
-        &graph,
-        &scan_parameters.print_config,
-        root_package_id,
-    );
+    // One tree per root package.
+    let text_tree_lines = root_package_ids
+        .iter()
+        .flat_map(|root_package_id| {
+            walk_dependency_tree(
+                cargo_metadata_parameters,
+                &graph,
+                &scan_parameters.print_config,
+                root_package_id.clone(),
+            )
+        })
+        .collect();
     let table_parameters = TableParameters {
         geiger_context: &geiger_context,
         print_config: &scan_parameters.print_config,
@@ -70,7 +84,7 @@ pub fn scan_to_table(
     }
 
     let used_but_not_scanned =
-        list_files_used_but_not_scanned(&geiger_context, &rs_files_used);
+        list_files_used_but_not_scanned(&geiger_context, &rs_files_used.all());
     warning_count += used_but_not_scanned.len() as u64;
     for path in &used_but_not_scanned {
         eprintln!(
@@ -79,10 +93,21 @@ pub fn scan_to_table(
         );
     }
 
+    let report = safety_report(
+        cargo_metadata_parameters,
+        &geiger_context,
+        graph,
+        root_package_ids,
+        &rs_files_used,
+        report_metadata,
+        false,
+    )?;
+    check_report(scan_parameters, &report)?;
+
     if warning_count > 0 {
         Err(CliError::new(
             anyhow::Error::new(FoundWarningsError { warning_count }),
-            1,
+            exit_code::WARNINGS,
         ))
     } else {
         Ok(())
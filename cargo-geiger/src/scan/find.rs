@@ -1,68 +1,131 @@
 use crate::format::print_config::PrintConfig;
-use crate::mapping::{CargoMetadataParameters, GetRoot};
+use crate::graph::Graph;
+use crate::mapping::{
+    CargoMetadataParameters, GetFeaturesFromCargoMetadataPackageId, GetRoot,
+};
 use crate::scan::rs_file::{
     into_is_entry_point_and_path_buf, into_rs_code_file, into_target_kind,
     is_file_with_ext, RsFile, RsFileMetricsWrapper,
 };
 use crate::scan::PackageMetrics;
 
+use super::cache::ScanCache;
 use super::{GeigerContext, ScanMode};
 
 use cargo::util::CargoResult;
 use cargo::{CliError, Config};
-use cargo_metadata::PackageId;
-use geiger::{find_unsafe_in_file, IncludeTests, RsFileMetrics, ScanFileError};
+use cargo_metadata::{Metadata, PackageId};
+use cargo_platform::Cfg;
+use geiger::{
+    find_unsafe_in_file, ActiveCfgs, IncludeTests, RsFileMetrics, ScanFileError,
+};
 use std::collections::HashMap;
+use std::panic;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
 use walkdir::WalkDir;
 
+/// Scans the packages in the dependency graph, code behind `#[cfg]`
+/// attributes that evaluate to false for `cfgs` and the enabled features of
+/// each package is skipped. Files found in `scan_cache` are not parsed again.
 pub fn find_unsafe(
     cargo_metadata_parameters: &CargoMetadataParameters,
     config: &Config,
+    cfgs: Option<&[Cfg]>,
+    graph: &Graph,
     mode: ScanMode,
     print_config: &PrintConfig,
+    scan_cache: Option<&ScanCache>,
 ) -> Result<GeigerContext, CliError> {
+    let metadata = cargo_metadata_parameters.metadata;
+    // Packages for other targets, optional dependencies that are not enabled
+    // and the like are part of the metadata, but not of the graph.
+    let packages = metadata
+        .packages
+        .iter()
+        .filter(|package| graph.nodes.contains_key(&package.id))
+        .collect::<Vec<_>>();
+    let skipped_package_count = metadata.packages.len() - packages.len();
+
     let mut progress = cargo::util::Progress::new("Scanning", config);
     let geiger_context = find_unsafe_in_packages(
-        print_config.allow_partial_results,
-        cargo_metadata_parameters,
-        print_config.include_tests,
+        metadata,
+        &packages,
+        cfgs,
         mode,
+        print_config,
+        scan_cache,
         |i, count| -> CargoResult<()> { progress.tick(i, count) },
     );
     progress.clear();
     config.shell().status("Scanning", "done")?;
+    if skipped_package_count > 0 {
+        config.shell().status(
+            "Skipped",
+            format!(
+                "{} package(s) that are not in the dependency graph",
+                skipped_package_count
+            ),
+        )?;
+    }
     Ok(geiger_context)
 }
 
 fn find_unsafe_in_packages<F>(
-    allow_partial_results: bool,
-    cargo_metadata_parameters: &CargoMetadataParameters,
-    include_tests: IncludeTests,
+    metadata: &Metadata,
+    packages: &[&cargo_metadata::Package],
+    cfgs: Option<&[Cfg]>,
     mode: ScanMode,
+    print_config: &PrintConfig,
+    scan_cache: Option<&ScanCache>,
     mut progress_step: F,
 ) -> GeigerContext
 where
     F: FnMut(usize, usize) -> CargoResult<()>,
 {
     let mut package_id_to_metrics = HashMap::new();
-    let packages = cargo_metadata_parameters.metadata.packages.to_vec();
-    let package_code_files: Vec<_> =
-        find_rs_files_in_packages(&packages).collect();
-    let package_code_file_count = package_code_files.len();
-    for (i, (package_id, rs_code_file)) in
-        package_code_files.into_iter().enumerate()
+    let package_code_files =
+        find_rs_files_in_packages(packages.iter().copied())
+            .map(|(package_id, rs_code_file)| {
+                let (is_entry_point, path_buf) =
+                    into_is_entry_point_and_path_buf(rs_code_file);
+                (package_id, is_entry_point, path_buf)
+            })
+            .filter(|(_, is_entry_point, _)| match mode {
+                ScanMode::EntryPointsOnly => *is_entry_point,
+                ScanMode::Full => true,
+            })
+            .collect::<Vec<_>>();
+    let package_id_to_active_cfgs = packages
+        .iter()
+        .map(|package| {
+            let active_cfgs = ActiveCfgs::new(
+                cfgs.map(<[Cfg]>::to_vec),
+                metadata
+                    .get_features_from_cargo_metadata_package_id(&package.id),
+            );
+            (package.id.clone(), active_cfgs)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let results = find_unsafe_in_files_in_parallel(
+        &package_code_files,
+        package_id_to_active_cfgs,
+        print_config.include_tests,
+        print_config.jobs,
+        scan_cache,
+        &mut progress_step,
+    );
+    for ((package_id, is_entry_point, path_buf), result) in
+        package_code_files.into_iter().zip(results)
     {
-        let (is_entry_point, path_buf) =
-            into_is_entry_point_and_path_buf(rs_code_file);
-        if let (false, ScanMode::EntryPointsOnly) = (is_entry_point, &mode) {
-            continue;
-        }
-        match find_unsafe_in_file(&path_buf, include_tests) {
+        match result {
             Err(error) => {
                 handle_unsafe_in_file_error(
-                    allow_partial_results,
+                    print_config.allow_partial_results,
                     error,
                     &path_buf,
                 );
@@ -77,21 +140,93 @@ where
                 );
             }
         }
-        let _ = progress_step(i, package_code_file_count);
     }
 
-    let cargo_core_package_metrics = package_id_to_metrics
-        .iter()
-        .map(|(cargo_metadata_package_id, package_metrics)| {
-            (cargo_metadata_package_id.clone(), package_metrics.clone())
-        })
-        .collect::<HashMap<PackageId, PackageMetrics>>();
-
     GeigerContext {
-        package_id_to_metrics: cargo_core_package_metrics,
+        package_id_to_metrics,
     }
 }
 
+/// Scans the files on `jobs` worker threads. The results are returned in the
+/// same order as `package_code_files`, independent of the order in which the
+/// workers finish, and `progress_step` is called once for every scanned file.
+fn find_unsafe_in_files_in_parallel<F>(
+    package_code_files: &[(PackageId, bool, PathBuf)],
+    package_id_to_active_cfgs: HashMap<PackageId, ActiveCfgs>,
+    include_tests: IncludeTests,
+    jobs: usize,
+    scan_cache: Option<&ScanCache>,
+    progress_step: &mut F,
+) -> Vec<Result<RsFileMetrics, ScanFileError>>
+where
+    F: FnMut(usize, usize) -> CargoResult<()>,
+{
+    let file_count = package_code_files.len();
+    let work = Arc::new(
+        package_code_files
+            .iter()
+            .map(|(package_id, _, path_buf)| {
+                (
+                    package_id.clone(),
+                    package_id_to_active_cfgs[package_id].clone(),
+                    path_buf.clone(),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+    let scan_cache = Arc::new(scan_cache.cloned());
+    let next_index = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = mpsc::channel();
+    let workers = (0..jobs.max(1).min(file_count))
+        .map(|_| {
+            let work = Arc::clone(&work);
+            let next_index = Arc::clone(&next_index);
+            let scan_cache = Arc::clone(&scan_cache);
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                let (package_id, active_cfgs, path_buf) = match work.get(index)
+                {
+                    Some(item) => item,
+                    None => break,
+                };
+                let result = match scan_cache.as_ref() {
+                    Some(scan_cache) => scan_cache.find_unsafe_in_file(
+                        package_id,
+                        path_buf,
+                        active_cfgs,
+                    ),
+                    None => find_unsafe_in_file(
+                        path_buf,
+                        include_tests,
+                        active_cfgs,
+                    ),
+                };
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    // Only the workers hold senders now, the receiver stops when all are done.
+    drop(sender);
+
+    let mut results = (0..file_count).map(|_| None).collect::<Vec<_>>();
+    for (i, (index, result)) in receiver.iter().enumerate() {
+        results[index] = Some(result);
+        let _ = progress_step(i, file_count);
+    }
+    for worker in workers {
+        if let Err(panic) = worker.join() {
+            panic::resume_unwind(panic);
+        }
+    }
+    results
+        .into_iter()
+        .map(|result| result.expect("every file is scanned by a worker"))
+        .collect()
+}
+
 fn find_rs_files_in_dir(dir: &Path) -> impl Iterator<Item = PathBuf> {
     let walker = WalkDir::new(dir).into_iter();
     walker.filter_map(|entry| {
@@ -144,10 +279,10 @@ fn find_rs_files_in_package(package: &cargo_metadata::Package) -> Vec<RsFile> {
     rs_files
 }
 
-fn find_rs_files_in_packages(
-    packages: &[cargo_metadata::Package],
-) -> impl Iterator<Item = (PackageId, RsFile)> + '_ {
-    packages.iter().flat_map(|package| {
+fn find_rs_files_in_packages<'a>(
+    packages: impl IntoIterator<Item = &'a cargo_metadata::Package> + 'a,
+) -> impl Iterator<Item = (PackageId, RsFile)> + 'a {
+    packages.into_iter().flat_map(|package| {
         find_rs_files_in_package(package)
             .into_iter()
             .map(move |p| (package.id.clone(), p))
@@ -188,6 +323,8 @@ fn update_package_id_to_metrics_with_rs_file_metrics(
 mod find_tests {
     use super::*;
 
+    use crate::args::Args;
+
     use cargo_metadata::{CargoOpt, MetadataCommand};
     use rstest::*;
     use std::fs::File;
@@ -230,6 +367,63 @@ mod find_tests {
         assert_eq!(actual_rs_file_names, rs_file_names);
     }
 
+    #[rstest(input_jobs, case(1), case(4))]
+    fn find_unsafe_in_packages_test(input_jobs: usize) {
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .no_deps()
+            .exec()
+            .unwrap();
+        let root_package = metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "cargo-geiger")
+            .unwrap();
+        let expected_file_count =
+            find_rs_files_in_packages(vec![root_package]).count();
+
+        let args = Args {
+            jobs: Some(input_jobs),
+            ..Default::default()
+        };
+        let print_config = PrintConfig::new(&args).unwrap();
+
+        let mut progress_steps = Vec::new();
+        let geiger_context = find_unsafe_in_packages(
+            &metadata,
+            &[root_package],
+            None,
+            ScanMode::Full,
+            &print_config,
+            None,
+            |i, count| -> CargoResult<()> {
+                progress_steps.push((i, count));
+                Ok(())
+            },
+        );
+
+        assert_eq!(
+            progress_steps,
+            (0..expected_file_count)
+                .map(|i| (i, expected_file_count))
+                .collect::<Vec<_>>()
+        );
+
+        // Only the given packages are scanned.
+        assert_eq!(
+            geiger_context
+                .package_id_to_metrics
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&root_package.id]
+        );
+        let main_rs = root_package.get_root().join("src/main.rs");
+        let wrapper = &geiger_context.package_id_to_metrics[&root_package.id]
+            .rs_path_to_metrics[&main_rs.canonicalize().unwrap()];
+        assert!(wrapper.is_crate_entry_point);
+        assert!(wrapper.metrics.forbids_unsafe);
+    }
+
     #[rstest]
     fn find_rs_file_in_package() {
         let package = get_current_workspace_package();
@@ -298,8 +492,12 @@ mod find_tests {
         let rs_file = rs_files_in_package.pop().unwrap();
         let (_, path_buf) = into_is_entry_point_and_path_buf(rs_file);
 
-        let rs_file_metrics =
-            find_unsafe_in_file(path_buf.as_path(), IncludeTests::Yes).unwrap();
+        let rs_file_metrics = find_unsafe_in_file(
+            path_buf.as_path(),
+            IncludeTests::Yes,
+            &ActiveCfgs::default(),
+        )
+        .unwrap();
 
         update_package_id_to_metrics_with_rs_file_metrics(
             input_is_entry_point,
@@ -1,64 +1,115 @@
 mod table;
 
-use crate::format::print_config::{OutputFormat, PrintConfig};
+use crate::format::cyclonedx::quick_report_to_cyclonedx;
+use crate::format::print_config::OutputFormat;
 use crate::graph::Graph;
 use crate::mapping::CargoMetadataParameters;
 
 use super::find::find_unsafe;
-use super::{package_metrics, ScanMode, ScanParameters};
+use super::{
+    package_metrics, report_metadata, GeigerContext, ScanMode, ScanParameters,
+};
 
 use table::scan_forbid_to_table;
 
-use cargo::{CliResult, Config};
-use cargo_geiger_serde::{QuickReportEntry, QuickSafetyReport};
+use cargo::core::Workspace;
+use cargo::{CliError, CliResult};
+use cargo_geiger_serde::{QuickReportEntry, QuickSafetyReport, ReportMetadata};
 use cargo_metadata::PackageId;
 
 pub fn scan_forbid_unsafe(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
+    workspace: &Workspace,
 ) -> CliResult {
     match scan_parameters.args.output_format {
         Some(output_format) => scan_forbid_to_report(
             cargo_metadata_parameters,
-            scan_parameters.config,
             graph,
             output_format,
-            scan_parameters.print_config,
-            root_package_id,
+            root_package_ids,
+            scan_parameters,
+            workspace,
         ),
         None => scan_forbid_to_table(
             cargo_metadata_parameters,
             scan_parameters.config,
             graph,
             scan_parameters.print_config,
-            root_package_id,
+            scan_parameters.policy,
+            root_package_ids,
         ),
     }
 }
 
 fn scan_forbid_to_report(
     cargo_metadata_parameters: &CargoMetadataParameters,
-    config: &Config,
     graph: &Graph,
     output_format: OutputFormat,
-    print_config: &PrintConfig,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
+    scan_parameters: &ScanParameters,
+    workspace: &Workspace,
 ) -> CliResult {
+    // Only `#![forbid(unsafe_code)]` in the entry points matters here, which
+    // is not affected by cfg evaluation.
     let geiger_context = find_unsafe(
         cargo_metadata_parameters,
-        config,
+        scan_parameters.config,
+        None,
+        graph,
         ScanMode::EntryPointsOnly,
-        print_config,
+        scan_parameters.print_config,
+        None,
     )?;
-    let mut report = QuickSafetyReport::default();
-    for (package, package_metrics) in package_metrics(
+    let report = quick_safety_report(
         cargo_metadata_parameters,
         &geiger_context,
         graph,
-        root_package_id,
-    ) {
+        root_package_ids,
+        report_metadata(
+            scan_parameters.args,
+            None,
+            scan_parameters.config,
+            workspace,
+        )?,
+    )?;
+    let s = match output_format {
+        OutputFormat::CycloneDx => {
+            serde_json::to_string(&quick_report_to_cyclonedx(&report)).unwrap()
+        }
+        OutputFormat::Json => serde_json::to_string(&report).unwrap(),
+        // Rejected by `scan` before anything is scanned.
+        OutputFormat::Dot
+        | OutputFormat::Html
+        | OutputFormat::Markdown
+        | OutputFormat::Sarif => unreachable!(),
+    };
+    println!("{}", s);
+    match scan_parameters.policy {
+        Some(policy) => policy.check_quick_report(&report),
+        None => Ok(()),
+    }
+}
+
+fn quick_safety_report(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    geiger_context: &GeigerContext,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
+    report_metadata: ReportMetadata,
+) -> Result<QuickSafetyReport, CliError> {
+    let mut report = QuickSafetyReport {
+        metadata: report_metadata,
+        ..Default::default()
+    };
+    for (_, package, package_metrics) in package_metrics(
+        cargo_metadata_parameters,
+        geiger_context,
+        graph,
+        root_package_ids,
+    )? {
         let pack_metrics = match package_metrics {
             Some(m) => m,
             None => {
@@ -77,9 +128,5 @@ fn scan_forbid_to_report(
         };
         report.packages.insert(entry.package.id.clone(), entry);
     }
-    let s = match output_format {
-        OutputFormat::Json => serde_json::to_string(&report).unwrap(),
-    };
-    println!("{}", s);
-    Ok(())
+    Ok(report)
 }
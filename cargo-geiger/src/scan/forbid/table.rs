@@ -9,9 +9,12 @@ use crate::tree::traversal::walk_dependency_tree;
 use crate::tree::TextTreeLine;
 
 use super::super::find::find_unsafe;
+use super::super::policy::Policy;
 use super::super::ScanMode;
+use super::quick_safety_report;
 
 use cargo::{CliResult, Config};
+use cargo_geiger_serde::ReportMetadata;
 use cargo_metadata::PackageId;
 use colored::Colorize;
 
@@ -20,7 +23,8 @@ pub fn scan_forbid_to_table(
     config: &Config,
     graph: &Graph,
     print_config: &PrintConfig,
-    root_package_id: PackageId,
+    policy: Option<&Policy>,
+    root_package_ids: &[PackageId],
 ) -> CliResult {
     let mut scan_output_lines = Vec::<String>::new();
     let emoji_symbols = EmojiSymbols::new(print_config.charset);
@@ -28,12 +32,25 @@ pub fn scan_forbid_to_table(
     let mut output_key_lines = construct_key_lines(&emoji_symbols);
     scan_output_lines.append(&mut output_key_lines);
 
-    let tree_lines = walk_dependency_tree(
+    let geiger_ctx = find_unsafe(
         cargo_metadata_parameters,
+        config,
+        None,
         &graph,
-        &print_config,
-        root_package_id,
-    );
+        ScanMode::EntryPointsOnly,
+        print_config,
+        None,
+    )?;
+
+    // One tree per root package.
+    let tree_lines = root_package_ids.iter().flat_map(|root_package_id| {
+        walk_dependency_tree(
+            cargo_metadata_parameters,
+            &graph,
+            &print_config,
+            root_package_id.clone(),
+        )
+    });
 
     for tree_line in tree_lines {
         match tree_line {
@@ -49,14 +66,8 @@ pub fn scan_forbid_to_table(
             TextTreeLine::Package {
                 id: package_id,
                 tree_vines,
+                ..
             } => {
-                let geiger_ctx = find_unsafe(
-                    cargo_metadata_parameters,
-                    config,
-                    ScanMode::EntryPointsOnly,
-                    print_config,
-                )?;
-
                 handle_package_text_tree_line(
                     cargo_metadata_parameters,
                     &emoji_symbols,
@@ -74,7 +85,16 @@ pub fn scan_forbid_to_table(
         println!("{}", scan_output_line);
     }
 
-    Ok(())
+    match policy {
+        Some(policy) => policy.check_quick_report(&quick_safety_report(
+            cargo_metadata_parameters,
+            &geiger_ctx,
+            graph,
+            root_package_ids,
+            ReportMetadata::default(),
+        )?),
+        None => Ok(()),
+    }
 }
 
 fn construct_key_lines(emoji_symbols: &EmojiSymbols) -> Vec<String> {
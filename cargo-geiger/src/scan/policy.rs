@@ -0,0 +1,293 @@
+//! The allowlist policy, read from `geiger.toml` or from
+//! `[workspace.metadata.geiger]` in the root `Cargo.toml`.
+//!
+//! ```toml
+//! [[allow]]
+//! name = "libc"
+//! version = "^0.2"
+//! reason = "FFI bindings to the C standard library"
+//! ```
+//!
+//! Only the listed packages may use unsafe code, every other package has to
+//! declare `#![forbid(unsafe_code)]`.
+
+use super::thresholds::Thresholds;
+use super::CheckFailedError;
+use crate::exit_code;
+
+use cargo::core::Workspace;
+use cargo::{CargoResult, CliError, CliResult};
+use cargo_geiger_serde::{PackageId, QuickSafetyReport, SafetyReport};
+use cargo_metadata::Metadata;
+use semver::VersionReq;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+
+const POLICY_FILE_NAME: &str = "geiger.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    allow: Vec<AllowedPackage>,
+    /// Limits for the unsafe code used by the build, the `--max-*` flags take
+    /// precedence.
+    #[serde(default)]
+    pub thresholds: Thresholds,
+}
+
+/// A package that is allowed to use unsafe code.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AllowedPackage {
+    name: String,
+    /// Only versions matching this requirement are allowed, all versions if
+    /// `None`.
+    #[serde(default, deserialize_with = "deserialize_version_req")]
+    version: Option<VersionReq>,
+    /// Why the package needs unsafe code.
+    reason: String,
+}
+
+/// Reads the policy of the workspace. `geiger.toml` next to the root
+/// `Cargo.toml` takes precedence over `[workspace.metadata.geiger]`. Returns
+/// `None` if neither exists.
+pub fn load_policy(
+    metadata: &Metadata,
+    workspace: &Workspace,
+) -> CargoResult<Option<Policy>> {
+    let policy_path = workspace.root().join(POLICY_FILE_NAME);
+    if policy_path.exists() {
+        let policy = fs::read_to_string(&policy_path)
+            .map_err(anyhow::Error::new)
+            .and_then(|s| toml::from_str(&s).map_err(anyhow::Error::new))
+            .map_err(|e| {
+                e.context(format!(
+                    "failed to read policy `{}`",
+                    policy_path.display()
+                ))
+            })?;
+        return Ok(Some(policy));
+    }
+    match metadata.workspace_metadata.get("geiger") {
+        Some(value) => {
+            let policy =
+                serde_json::from_value(value.clone()).map_err(|e| {
+                    anyhow::Error::new(e)
+                        .context("failed to read `[workspace.metadata.geiger]`")
+                })?;
+            Ok(Some(policy))
+        }
+        None => Ok(None),
+    }
+}
+
+impl Policy {
+    /// Fails with `exit_code::POLICY_VIOLATION` after printing every violation
+    /// if a package not in the allowlist does not forbid unsafe code or uses
+    /// unsafe code.
+    pub fn check_report(&self, report: &SafetyReport) -> CliResult {
+        let mut entries = report.packages.values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+        report_violations(
+            entries
+                .into_iter()
+                .filter_map(|entry| {
+                    self.check_package(
+                        &entry.package.id,
+                        entry.unsafety.forbids_unsafe,
+                        entry.unsafety.used.has_unsafe(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Like `check_report`, only `#![forbid(unsafe_code)]` is known in
+    /// `--forbid-only` mode.
+    pub fn check_quick_report(&self, report: &QuickSafetyReport) -> CliResult {
+        let mut entries = report.packages.values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+        report_violations(
+            entries
+                .into_iter()
+                .filter_map(|entry| {
+                    self.check_package(
+                        &entry.package.id,
+                        entry.forbids_unsafe,
+                        false,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn check_package(
+        &self,
+        package_id: &PackageId,
+        forbids_unsafe: bool,
+        uses_unsafe: bool,
+    ) -> Option<String> {
+        if forbids_unsafe && !uses_unsafe {
+            return None;
+        }
+        let mut violation = format!(
+            "`{} {}` is not in the allowlist and {}",
+            package_id.name,
+            package_id.version,
+            if uses_unsafe {
+                "uses unsafe code"
+            } else {
+                "does not declare #![forbid(unsafe_code)]"
+            }
+        );
+        for allowed_package in &self.allow {
+            if allowed_package.name != package_id.name {
+                continue;
+            }
+            match &allowed_package.version {
+                Some(version_req)
+                    if !version_req.matches(&package_id.version) =>
+                {
+                    violation.push_str(&format!(
+                        ", only `{} {}` is allowed ({})",
+                        allowed_package.name,
+                        version_req,
+                        allowed_package.reason
+                    ));
+                }
+                _ => return None,
+            }
+        }
+        Some(violation)
+    }
+}
+
+fn report_violations(violations: Vec<String>) -> CliResult {
+    for violation in &violations {
+        eprintln!("POLICY VIOLATION: {}", violation);
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::new(
+            anyhow::Error::new(CheckFailedError {
+                check: "Policy",
+                violation_count: violations.len(),
+            }),
+            exit_code::POLICY_VIOLATION,
+        ))
+    }
+}
+
+fn deserialize_version_req<'de, D>(
+    deserializer: D,
+) -> Result<Option<VersionReq>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| VersionReq::parse(&s).map_err(D::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod policy_tests {
+    use super::*;
+
+    use crate::test_fixtures::create_package_id;
+
+    use rstest::*;
+
+    #[rstest(
+        input_version,
+        input_forbids_unsafe,
+        input_uses_unsafe,
+        expected_violation,
+        case("0.2.80", false, true, None),
+        case("1.0.0", true, false, None),
+        case(
+            "0.3.0",
+            false,
+            true,
+            Some(
+                "`libc 0.3.0` is not in the allowlist and uses unsafe code, \
+                 only `libc >=0.2.0, <0.3.0` is allowed (FFI)"
+            )
+        )
+    )]
+    fn check_package_test_allowed_package(
+        input_version: &str,
+        input_forbids_unsafe: bool,
+        input_uses_unsafe: bool,
+        expected_violation: Option<&str>,
+    ) {
+        let policy: Policy = toml::from_str(
+            r#"
+                [[allow]]
+                name = "libc"
+                version = "^0.2"
+                reason = "FFI"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy.check_package(
+                &create_package_id("libc", input_version),
+                input_forbids_unsafe,
+                input_uses_unsafe
+            ),
+            expected_violation.map(String::from)
+        );
+    }
+
+    #[rstest(
+        input_forbids_unsafe,
+        input_uses_unsafe,
+        expected_violation,
+        case(true, false, None),
+        case(
+            false,
+            false,
+            Some(
+                "`foo 1.0.0` is not in the allowlist and does not declare \
+                 #![forbid(unsafe_code)]"
+            )
+        ),
+        case(
+            true,
+            true,
+            Some("`foo 1.0.0` is not in the allowlist and uses unsafe code")
+        )
+    )]
+    fn check_package_test_not_allowed_package(
+        input_forbids_unsafe: bool,
+        input_uses_unsafe: bool,
+        expected_violation: Option<&str>,
+    ) {
+        let policy = Policy::default();
+
+        assert_eq!(
+            policy.check_package(
+                &create_package_id("foo", "1.0.0"),
+                input_forbids_unsafe,
+                input_uses_unsafe
+            ),
+            expected_violation.map(String::from)
+        );
+    }
+
+    #[rstest(
+        input_policy,
+        case("[[allow]]\nname = \"libc\"\n"),
+        case(
+            "[[allow]]\nname = \"libc\"\nversion = \"one\"\nreason = \"FFI\"\n"
+        ),
+        case("[[allow]]\nname = \"libc\"\nreason = \"FFI\"\nunknown = 1\n")
+    )]
+    fn policy_test_invalid(input_policy: &str) {
+        assert!(toml::from_str::<Policy>(input_policy).is_err());
+    }
+}
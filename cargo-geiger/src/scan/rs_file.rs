@@ -1,22 +1,33 @@
 mod custom_executor;
 
-use custom_executor::{CustomExecutor, CustomExecutorInnerContext};
+use crate::graph::Graph;
+use crate::mapping::{
+    GetFeaturesFromCargoMetadataPackageId, PackageIdMappingError,
+    TryToCargoMetadataPackageId,
+};
+
+use custom_executor::{
+    CustomExecutor, CustomExecutorInnerContext, RecordedUnit,
+};
 
 use cargo::core::compiler::Executor;
 use cargo::core::manifest::TargetKind;
 use cargo::core::Workspace;
 use cargo::ops;
-use cargo::ops::{CleanOptions, CompileOptions};
-use cargo::util::{interning::InternedString, paths, CargoResult};
+use cargo::ops::CompileOptions;
+use cargo::util::{paths, CargoResult, Filesystem};
 use cargo::Config;
-use geiger::RsFileMetrics;
-use std::collections::HashSet;
+use cargo_metadata::{Metadata, PackageId};
+use cargo_platform::Cfg;
+use geiger::{find_module_files, ActiveCfgs, IncludeTests, RsFileMetrics};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, PoisonError};
-use walkdir::{DirEntry, WalkDir};
+use walkdir::DirEntry;
 
 /// Provides information needed to scan for crate root
 /// `#![forbid(unsafe_code)]`.
@@ -48,6 +59,52 @@ pub struct RsFileMetricsWrapper {
     pub is_crate_entry_point: bool,
 }
 
+/// The `.rs` files used by the build, for every package and every kind of
+/// target, e.g. the library or the build script, that was compiled.
+/// The wrapped PathBufs are canonicalized.
+#[derive(Debug, Default)]
+pub struct RsFilesUsed {
+    pub package_id_to_target_kind_to_paths:
+        HashMap<PackageId, HashMap<TargetKind, HashSet<PathBuf>>>,
+}
+
+impl RsFilesUsed {
+    pub fn insert(
+        &mut self,
+        package_id: PackageId,
+        target_kind: TargetKind,
+        path: PathBuf,
+    ) {
+        self.package_id_to_target_kind_to_paths
+            .entry(package_id)
+            .or_default()
+            .entry(target_kind)
+            .or_default()
+            .insert(path);
+    }
+
+    /// The files used by any target of the package.
+    pub fn used_by_package(&self, package_id: &PackageId) -> HashSet<PathBuf> {
+        self.package_id_to_target_kind_to_paths
+            .get(package_id)
+            .into_iter()
+            .flat_map(HashMap::values)
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// The files used by any target of any package.
+    pub fn all(&self) -> HashSet<PathBuf> {
+        self.package_id_to_target_kind_to_paths
+            .values()
+            .flat_map(HashMap::values)
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
 #[derive(Debug)]
 pub enum RsResolveError {
     /// This should not happen unless incorrect assumptions have been made in
@@ -67,7 +124,8 @@ pub enum RsResolveError {
     /// Like io::Error but with the related path.
     Io(io::Error, PathBuf),
 
-    Walkdir(walkdir::Error),
+    /// A package was built that has no single match in the cargo metadata.
+    PackageIdMapping(PackageIdMappingError),
 }
 
 impl Error for RsResolveError {}
@@ -140,89 +198,194 @@ pub fn is_file_with_ext(entry: &DirEntry, file_ext: &str) -> bool {
     ext.to_string_lossy() == file_ext
 }
 
-/// Trigger a `cargo clean` + `cargo check` and listen to the cargo/rustc
-/// communication to figure out which source files were used by the build.
+/// Find the source files used by the build without building anything, by
+/// following the module tree from the entry point of every target that
+/// `cargo check` would build, for the packages in the dependency graph.
+/// Workspace members contribute their library, binaries and build script,
+/// other packages their library and build script. A target whose module tree
+/// cannot be read is reported with a warning and skipped.
+pub fn resolve_rs_file_deps_from_module_tree(
+    metadata: &Metadata,
+    graph: &Graph,
+    cfgs: Option<&[Cfg]>,
+    include_tests: IncludeTests,
+) -> RsFilesUsed {
+    let mut rs_files_used = RsFilesUsed::default();
+    let packages = metadata
+        .packages
+        .iter()
+        .filter(|package| graph.nodes.contains_key(&package.id));
+    for package in packages {
+        let is_workspace_member =
+            metadata.workspace_members.contains(&package.id);
+        let active_cfgs = ActiveCfgs::new(
+            cfgs.map(<[Cfg]>::to_vec),
+            metadata.get_features_from_cargo_metadata_package_id(&package.id),
+        );
+        for target in &package.targets {
+            let is_built = target.kind.iter().any(|kind| match kind.as_str() {
+                "lib" | "rlib" | "dylib" | "cdylib" | "staticlib"
+                | "proc-macro" | "custom-build" => true,
+                "bin" => is_workspace_member,
+                _ => false,
+            });
+            if !is_built || !target.src_path.exists() {
+                continue;
+            }
+            let module_files = match find_module_files(
+                &target.src_path,
+                include_tests,
+                &active_cfgs,
+            ) {
+                Ok(module_files) => module_files,
+                Err(e) => {
+                    eprintln!(
+                        "WARNING: Failed to follow the module tree of {}: {:?}",
+                        target.src_path.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            let target_kind = into_target_kind(target.kind.clone());
+            for path in module_files {
+                rs_files_used.insert(
+                    package.id.clone(),
+                    target_kind.clone(),
+                    path,
+                );
+            }
+        }
+    }
+    rs_files_used
+}
+
+/// Trigger a `cargo check` and listen to the cargo/rustc communication to
+/// figure out which source files were used by the build.
+///
+/// The build goes to `target_dir`, by default the `geiger` directory inside
+/// the target directory of the workspace, so the user's own build artifacts
+/// are left alone and no `cargo clean` is needed.
 pub fn resolve_rs_file_deps(
     compile_options: &CompileOptions,
+    metadata: &Metadata,
+    target_dir: Option<&Path>,
     workspace: &Workspace,
-) -> Result<HashSet<PathBuf>, RsResolveError> {
+) -> Result<RsFilesUsed, RsResolveError> {
     let config = workspace.config();
-    // Need to run a cargo clean to identify all new .d deps files.
-    // TODO: Figure out how this can be avoided to improve performance, clean
-    // Rust builds are __slow__.
-    let clean_options = CleanOptions {
-        config: &config,
-        spec: vec![],
-        targets: vec![],
-        profile_specified: false,
-        // A temporary hack to get cargo 0.43 to build, TODO: look closer at the updated cargo API
-        // later.
-        requested_profile: InternedString::new("dev"),
-        doc: false,
-    };
-
-    ops::clean(workspace, &clean_options)
+    let geiger_workspace = geiger_workspace(target_dir, workspace)
         .map_err(|e| RsResolveError::Cargo(e.to_string()))?;
 
-    let inner_arc = Arc::new(Mutex::new(CustomExecutorInnerContext::default()));
+    let recorded_units_path = geiger_workspace
+        .target_dir()
+        .join(RECORDED_UNITS_FILE_NAME)
+        .into_path_unlocked();
+    let inner_arc = Arc::new(Mutex::new(CustomExecutorInnerContext {
+        recorded_units: read_recorded_units(&recorded_units_path),
+        ..Default::default()
+    }));
     {
         compile_with_exec(
             compile_options,
             config,
             inner_arc.clone(),
-            workspace,
+            &geiger_workspace,
         )?;
     }
 
     let workspace_root = workspace.root().to_path_buf();
     let inner_mutex =
         Arc::try_unwrap(inner_arc).map_err(|_| RsResolveError::ArcUnwrap())?;
-    let (rs_files, out_dir_args) = {
-        let ctx = inner_mutex.into_inner()?;
-        (ctx.rs_file_args, ctx.out_dir_args)
-    };
-    let mut path_buf_hash_set = HashSet::<PathBuf>::new();
-    for out_dir in out_dir_args {
-        // TODO: Figure out if the `.d` dep files are used by one or more rustc
-        // calls. It could be useful to know which `.d` dep files belong to
-        // which rustc call. That would allow associating each `.rs` file found
-        // in each dep file with a PackageId.
-        add_dir_entries_to_path_buf_hash_set(
-            out_dir,
-            &mut path_buf_hash_set,
-            workspace_root.clone(),
-        )?;
-    }
-    for path_buf in rs_files {
-        // rs_files must already be canonicalized
-        path_buf_hash_set.insert(path_buf);
+    let inner_ctx = inner_mutex.into_inner()?;
+    write_recorded_units(&recorded_units_path, inner_ctx.recorded_units)?;
+    let unit_files = inner_ctx.unit_files;
+    let mut rs_files_used = RsFilesUsed::default();
+    for ((package_id, target_kind), files) in unit_files {
+        let cargo_metadata_package_id = package_id
+            .try_to_cargo_metadata_package_id(metadata)
+            .map_err(RsResolveError::PackageIdMapping)?;
+        let mut path_buf_hash_set = HashSet::<PathBuf>::new();
+        for dep_info_file in files.dep_info_files {
+            add_dep_info_to_path_buf_hash_set(
+                &dep_info_file,
+                &mut path_buf_hash_set,
+                &workspace_root,
+            )?;
+        }
+        // rs_file_args must already be canonicalized
+        path_buf_hash_set.extend(files.rs_file_args);
+        for path_buf in path_buf_hash_set {
+            rs_files_used.insert(
+                cargo_metadata_package_id.clone(),
+                target_kind.clone(),
+                path_buf,
+            );
+        }
     }
 
-    Ok(path_buf_hash_set)
+    Ok(rs_files_used)
+}
+
+/// A copy of `workspace` that builds into the cargo-geiger target directory.
+fn geiger_workspace<'cfg>(
+    target_dir: Option<&Path>,
+    workspace: &Workspace<'cfg>,
+) -> CargoResult<Workspace<'cfg>> {
+    let config = workspace.config();
+    let target_dir = match target_dir {
+        Some(path) => Filesystem::new(config.cwd().join(path)),
+        None => workspace.target_dir().join("geiger"),
+    };
+    // A virtual manifest has no current package, all members are built.
+    let manifest_path = match workspace.current_opt() {
+        Some(package) => package.manifest_path().to_path_buf(),
+        None => workspace.root().join("Cargo.toml"),
+    };
+    let mut geiger_workspace = Workspace::new(&manifest_path, config)?;
+    geiger_workspace.set_target_dir(target_dir);
+    Ok(geiger_workspace)
+}
+
+/// Stores the files of the units built into the cargo-geiger target
+/// directory, so later scans don't need to rebuild fresh units.
+const RECORDED_UNITS_FILE_NAME: &str = "geiger-units.json";
+
+/// A missing or unreadable record only means that all units are rebuilt.
+fn read_recorded_units(path: &Path) -> HashMap<String, RecordedUnit> {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
 }
 
-fn add_dir_entries_to_path_buf_hash_set(
-    out_dir: PathBuf,
+/// Units whose dep-info file is gone, for example after a `cargo clean`, are
+/// dropped from the record.
+fn write_recorded_units(
+    path: &Path,
+    mut recorded_units: HashMap<String, RecordedUnit>,
+) -> Result<(), RsResolveError> {
+    recorded_units.retain(|_, unit| unit.dep_info_file.exists());
+    let json = serde_json::to_vec(&recorded_units)
+        .map_err(|e| RsResolveError::Io(e.into(), path.to_path_buf()))?;
+    fs::write(path, json).map_err(|e| RsResolveError::Io(e, path.to_path_buf()))
+}
+
+fn add_dep_info_to_path_buf_hash_set(
+    dep_info_file: &Path,
     path_buf_hash_set: &mut HashSet<PathBuf>,
-    workspace_root: PathBuf,
+    workspace_root: &Path,
 ) -> Result<(), RsResolveError> {
-    for entry in WalkDir::new(&out_dir) {
-        let entry = entry.map_err(RsResolveError::Walkdir)?;
-        if !is_file_with_ext(&entry, "d") {
-            continue;
-        }
-        let dependencies = parse_rustc_dep_info(entry.path()).map_err(|e| {
-            RsResolveError::DepParse(e.to_string(), entry.path().to_path_buf())
-        })?;
-        let canonical_paths = dependencies
-            .into_iter()
-            .flat_map(|t| t.1)
-            .map(PathBuf::from)
-            .map(|pb| workspace_root.join(pb))
-            .map(|pb| pb.canonicalize().map_err(|e| RsResolveError::Io(e, pb)));
-        for path_buf in canonical_paths {
-            path_buf_hash_set.insert(path_buf?);
-        }
+    let dependencies = parse_rustc_dep_info(dep_info_file).map_err(|e| {
+        RsResolveError::DepParse(e.to_string(), dep_info_file.to_path_buf())
+    })?;
+    let canonical_paths = dependencies
+        .into_iter()
+        .flat_map(|t| t.1)
+        .map(PathBuf::from)
+        .map(|pb| workspace_root.join(pb))
+        .map(|pb| pb.canonicalize().map_err(|e| RsResolveError::Io(e, pb)));
+    for path_buf in canonical_paths {
+        path_buf_hash_set.insert(path_buf?);
     }
 
     Ok(())
@@ -286,6 +449,7 @@ fn parse_rustc_dep_info(
 mod rs_file_tests {
     use super::*;
     use rstest::*;
+    use walkdir::WalkDir;
 
     #[rstest(
         input_rs_file,
@@ -441,4 +605,66 @@ mod rs_file_tests {
             assert_eq!(is_file_with_ext(&entry, "rs"), false);
         }
     }
+
+    #[rstest]
+    fn resolve_rs_file_deps_from_module_tree_test() {
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()
+            .unwrap();
+
+        let graph_without = |name: &str| {
+            let mut inner_graph = petgraph::Graph::new();
+            let nodes = metadata
+                .packages
+                .iter()
+                .filter(|p| p.name != name)
+                .map(|p| (p.id.clone(), inner_graph.add_node(p.id.clone())))
+                .collect();
+            Graph {
+                graph: inner_graph,
+                nodes,
+            }
+        };
+
+        let rs_files_used = resolve_rs_file_deps_from_module_tree(
+            &metadata,
+            &graph_without(""),
+            None,
+            IncludeTests::No,
+        );
+        let all_rs_files_used = rs_files_used.all();
+
+        let canonical = |path: &str| Path::new(path).canonicalize().unwrap();
+        assert!(all_rs_files_used.contains(&canonical("src/main.rs")));
+        assert!(all_rs_files_used
+            .contains(&canonical("src/scan/rs_file/custom_executor.rs")));
+        assert!(all_rs_files_used.contains(&canonical("../geiger/src/cfg.rs")));
+        assert!(!all_rs_files_used.contains(&canonical("tests/mod.rs")));
+
+        let root_package_id = &metadata.root_package().unwrap().id;
+        let geiger_package_id = &metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "geiger")
+            .unwrap()
+            .id;
+        assert!(rs_files_used
+            .used_by_package(geiger_package_id)
+            .contains(&canonical("../geiger/src/cfg.rs")));
+        assert!(!rs_files_used
+            .used_by_package(root_package_id)
+            .contains(&canonical("../geiger/src/cfg.rs")));
+
+        let rs_files_used = resolve_rs_file_deps_from_module_tree(
+            &metadata,
+            &graph_without("geiger"),
+            None,
+            IncludeTests::No,
+        );
+        assert!(rs_files_used.used_by_package(geiger_package_id).is_empty());
+        assert!(rs_files_used
+            .all()
+            .contains(&canonical("src/scan/rs_file/custom_executor.rs")));
+    }
 }
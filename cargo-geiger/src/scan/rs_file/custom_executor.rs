@@ -1,7 +1,9 @@
-use cargo::core::compiler::{CompileMode, Executor, Unit};
+use cargo::core::compiler::{CompileKind, CompileMode, Executor, Unit};
+use cargo::core::manifest::TargetKind;
 use cargo::core::{PackageId, Target};
 use cargo::util::{CargoResult, ProcessBuilder};
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::ffi::OsString;
 use std::fmt;
@@ -26,6 +28,7 @@ pub struct CustomExecutor {
 
 #[derive(Debug)]
 enum CustomExecutorError {
+    CrateNameMissing(String),
     InnerContextMutex(String),
     Io(io::Error, PathBuf),
     OutDirKeyMissing(String),
@@ -38,9 +41,9 @@ impl Executor for CustomExecutor {
     fn exec(
         &self,
         cmd: &ProcessBuilder,
-        _id: PackageId,
-        _target: &Target,
-        _mode: CompileMode,
+        id: PackageId,
+        target: &Target,
+        mode: CompileMode,
         _on_stdout_line: &mut dyn FnMut(&str) -> CargoResult<()>,
         _on_stderr_line: &mut dyn FnMut(&str) -> CargoResult<()>,
     ) -> CargoResult<()> {
@@ -56,6 +59,11 @@ impl Executor for CustomExecutor {
                 CustomExecutorError::OutDirValueMissing(cmd.to_string())
             })
             .map(PathBuf::from)?;
+        let dep_info_file = dep_info_file_name(args)
+            .map(|name| out_dir.join(name))
+            .ok_or_else(|| {
+                CustomExecutorError::CrateNameMissing(cmd.to_string())
+            })?;
 
         // This can be different from the cwd used to launch the wrapping cargo
         // plugin. Discovered while fixing
@@ -70,6 +78,7 @@ impl Executor for CustomExecutor {
             let mut ctx = self.inner_ctx.lock().map_err(|e| {
                 CustomExecutorError::InnerContextMutex(e.to_string())
             })?;
+            let mut rs_file_args = HashSet::new();
             for (arg_name, _) in args
                 .iter()
                 .map(|s| (s, s.to_string_lossy().to_lowercase()))
@@ -79,9 +88,28 @@ impl Executor for CustomExecutor {
                 let path = raw_path
                     .canonicalize()
                     .map_err(|e| CustomExecutorError::Io(e, raw_path))?;
-                ctx.rs_file_args.insert(path);
+                rs_file_args.insert(path);
             }
-            ctx.out_dir_args.insert(out_dir);
+            let unit_files = ctx
+                .unit_files
+                .entry((id, target.kind().clone()))
+                .or_default();
+            unit_files.rs_file_args.extend(rs_file_args.iter().cloned());
+            unit_files.dep_info_files.insert(dep_info_file.clone());
+            let key = unit_key(
+                id,
+                target,
+                mode,
+                compile_target(args).as_deref(),
+                &features(args),
+            );
+            ctx.recorded_units.insert(
+                key,
+                RecordedUnit {
+                    rs_file_args,
+                    dep_info_file,
+                },
+            );
         }
         cmd.exec()?;
         Ok(())
@@ -89,11 +117,114 @@ impl Executor for CustomExecutor {
 
     /// Queried when queuing each unit of work. If it returns true, then the
     /// unit will always be rebuilt, independent of whether it needs to be.
-    fn force_rebuild(&self, _unit: &Unit) -> bool {
-        true // Overriding the default to force all units to be processed.
+    ///
+    /// Fresh units never reach `exec`, so their files are taken from the
+    /// record of the build that produced them. Only units without a recorded
+    /// dep-info file are rebuilt to learn about their source files. Build
+    /// script runs never reach `exec` either and are left to cargo.
+    fn force_rebuild(&self, unit: &Unit) -> bool {
+        if unit.mode.is_run_custom_build() {
+            return false;
+        }
+        let mut ctx = match self.inner_ctx.lock() {
+            Ok(ctx) => ctx,
+            Err(_) => return true,
+        };
+        let compile_target = match &unit.kind {
+            CompileKind::Host => None,
+            CompileKind::Target(target) => Some(target.rustc_target()),
+        };
+        let features = unit
+            .features
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        let key = unit_key(
+            unit.pkg.package_id(),
+            &unit.target,
+            unit.mode,
+            compile_target,
+            &features,
+        );
+        let recorded_unit = match ctx.recorded_units.get(&key) {
+            Some(recorded_unit) if recorded_unit.dep_info_file.exists() => {
+                recorded_unit.clone()
+            }
+            _ => return true,
+        };
+        let unit_files = ctx
+            .unit_files
+            .entry((unit.pkg.package_id(), unit.target.kind().clone()))
+            .or_default();
+        unit_files.rs_file_args.extend(recorded_unit.rs_file_args);
+        unit_files
+            .dep_info_files
+            .insert(recorded_unit.dep_info_file);
+        false
     }
 }
 
+/// Identifies a unit across builds by what both `exec` and `force_rebuild`
+/// know about it.
+fn unit_key(
+    package_id: PackageId,
+    target: &Target,
+    mode: CompileMode,
+    compile_target: Option<&str>,
+    features: &[String],
+) -> String {
+    format!(
+        "{} {:?} {} {:?} {} {}",
+        package_id,
+        target.kind(),
+        target.name(),
+        mode,
+        compile_target.unwrap_or("host"),
+        features.join(",")
+    )
+}
+
+/// The `--target` passed to rustc, `None` for units built for the host.
+fn compile_target(args: &[OsString]) -> Option<String> {
+    args.iter()
+        .position(|s| s == "--target")
+        .and_then(|idx| args.get(idx + 1))
+        .map(|s| s.to_string_lossy().into_owned())
+}
+
+/// The features enabled by `--cfg feature="..."`, in the sorted order cargo
+/// passes them in.
+fn features(args: &[OsString]) -> Vec<String> {
+    args.windows(2)
+        .filter(|pair| pair[0] == "--cfg")
+        .filter_map(|pair| {
+            pair[1]
+                .to_str()?
+                .strip_prefix("feature=\"")?
+                .strip_suffix('"')
+                .map(String::from)
+        })
+        .collect()
+}
+
+/// The name of the dep-info file rustc writes to the `--out-dir`, made up of
+/// the crate name and the `-C extra-filename` suffix that cargo adds to every
+/// output file. Only looking at the files written by the current build keeps
+/// stale dep-info files of earlier builds out of the result.
+fn dep_info_file_name(args: &[OsString]) -> Option<String> {
+    let crate_name = args
+        .iter()
+        .position(|s| s == "--crate-name")
+        .and_then(|idx| args.get(idx + 1))?
+        .to_string_lossy();
+    let extra_filename = args
+        .iter()
+        .filter_map(|s| s.to_str())
+        .find_map(|s| s.strip_prefix("extra-filename="))
+        .unwrap_or_default();
+    Some(format!("{}{}.d", crate_name, extra_filename))
+}
+
 /// Forward Display to Debug. See the crate root documentation.
 impl fmt::Display for CustomExecutorError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -105,10 +236,92 @@ impl Error for CustomExecutorError {}
 
 #[derive(Debug, Default)]
 pub struct CustomExecutorInnerContext {
-    /// Stores all lib.rs, main.rs etc. passed to rustc during the build.
+    /// The files of every rustc call, grouped by the package and the kind of
+    /// target that was compiled.
+    pub unit_files: HashMap<(PackageId, TargetKind), UnitFiles>,
+
+    /// The files of every unit built by this or an earlier build into the
+    /// same target directory, by `unit_key`.
+    pub recorded_units: HashMap<String, RecordedUnit>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordedUnit {
+    /// Stores all lib.rs, main.rs etc. passed to rustc.
+    pub rs_file_args: HashSet<PathBuf>,
+
+    /// The dep-info file written by rustc.
+    pub dep_info_file: PathBuf,
+}
+
+#[derive(Debug, Default)]
+pub struct UnitFiles {
+    /// Stores all lib.rs, main.rs etc. passed to rustc.
     pub rs_file_args: HashSet<PathBuf>,
 
-    /// Investigate if this needs to be intercepted like this or if it can be
-    /// looked up in a nicer way.
-    pub out_dir_args: HashSet<PathBuf>,
+    /// The dep-info files written by rustc.
+    pub dep_info_files: HashSet<PathBuf>,
+}
+
+#[cfg(test)]
+mod custom_executor_tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest(
+        input_args,
+        expected_dep_info_file_name,
+        case(
+            vec!["--crate-name", "geiger", "-C", "extra-filename=-0123abcd"],
+            Some(String::from("geiger-0123abcd.d"))
+        ),
+        case(
+            vec!["--crate-name", "build_script_build", "-C", "opt-level=0"],
+            Some(String::from("build_script_build.d"))
+        ),
+        case(vec!["--edition=2018", "src/lib.rs"], None)
+    )]
+    fn dep_info_file_name_test(
+        input_args: Vec<&str>,
+        expected_dep_info_file_name: Option<String>,
+    ) {
+        let args = input_args
+            .into_iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        assert_eq!(dep_info_file_name(&args), expected_dep_info_file_name);
+    }
+
+    #[rstest(
+        input_args,
+        expected_compile_target,
+        expected_features,
+        case(
+            vec![
+                "--crate-name", "geiger",
+                "--cfg", "feature=\"default\"",
+                "--cfg", "feature=\"std\"",
+                "--target", "x86_64-unknown-linux-gnu",
+            ],
+            Some(String::from("x86_64-unknown-linux-gnu")),
+            vec![String::from("default"), String::from("std")]
+        ),
+        case(
+            vec!["--crate-name", "geiger", "--cfg", "test"],
+            None,
+            vec![]
+        )
+    )]
+    fn compile_target_and_features_test(
+        input_args: Vec<&str>,
+        expected_compile_target: Option<String>,
+        expected_features: Vec<String>,
+    ) {
+        let args = input_args
+            .into_iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        assert_eq!(compile_target(&args), expected_compile_target);
+        assert_eq!(features(&args), expected_features);
+    }
 }
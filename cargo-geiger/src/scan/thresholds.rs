@@ -0,0 +1,282 @@
+//! Numeric budgets for the unsafe code used by the build, set with the
+//! `--max-*` flags or in the `thresholds` table of the policy.
+//!
+//! ```toml
+//! [thresholds.per-crate]
+//! exprs = 100
+//! [thresholds.total]
+//! functions = 10
+//! ```
+
+use super::CheckFailedError;
+use crate::exit_code;
+
+use cargo::{CliError, CliResult};
+use cargo_geiger_serde::{CounterBlock, SafetyReport};
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Thresholds {
+    /// Applies to each package on its own.
+    #[serde(default)]
+    pub per_crate: UnsafeBudget,
+    /// Applies to the sum over all packages.
+    #[serde(default)]
+    pub total: UnsafeBudget,
+}
+
+/// The maximum number of unsafe items of each kind, unlimited if `None`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UnsafeBudget {
+    pub exprs: Option<u64>,
+    pub functions: Option<u64>,
+    pub impls: Option<u64>,
+    pub macros: Option<u64>,
+}
+
+impl Thresholds {
+    pub fn is_empty(&self) -> bool {
+        self.per_crate.is_empty() && self.total.is_empty()
+    }
+
+    /// The thresholds with every limit set in `overrides` replaced.
+    pub fn overridden_by(&self, overrides: &Thresholds) -> Thresholds {
+        Thresholds {
+            per_crate: self.per_crate.overridden_by(&overrides.per_crate),
+            total: self.total.overridden_by(&overrides.total),
+        }
+    }
+
+    /// Fails with `exit_code::THRESHOLD_EXCEEDED` after printing every
+    /// exceeded limit.
+    pub fn check_report(&self, report: &SafetyReport) -> CliResult {
+        let violations = self.find_violations(report);
+        for violation in &violations {
+            eprintln!("THRESHOLD EXCEEDED: {}", violation);
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(CliError::new(
+                anyhow::Error::new(CheckFailedError {
+                    check: "Threshold",
+                    violation_count: violations.len(),
+                }),
+                exit_code::THRESHOLD_EXCEEDED,
+            ))
+        }
+    }
+
+    fn find_violations(&self, report: &SafetyReport) -> Vec<String> {
+        let mut entries = report.packages.values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+        let mut violations = Vec::new();
+        let mut total = CounterBlock::default();
+        for entry in entries {
+            for (count, max, kind) in
+                self.per_crate.exceeded(&entry.unsafety.used)
+            {
+                violations.push(format!(
+                    "`{} {}` uses {} unsafe {}, the maximum per crate is {}",
+                    entry.package.id.name,
+                    entry.package.id.version,
+                    count,
+                    kind,
+                    max
+                ));
+            }
+            total += entry.unsafety.used.clone();
+        }
+        for (count, max, kind) in self.total.exceeded(&total) {
+            violations.push(format!(
+                "All packages together use {} unsafe {}, the maximum is {}",
+                count, kind, max
+            ));
+        }
+        violations
+    }
+}
+
+impl UnsafeBudget {
+    fn is_empty(&self) -> bool {
+        self.exprs.is_none()
+            && self.functions.is_none()
+            && self.impls.is_none()
+            && self.macros.is_none()
+    }
+
+    fn overridden_by(&self, overrides: &UnsafeBudget) -> UnsafeBudget {
+        UnsafeBudget {
+            exprs: overrides.exprs.or(self.exprs),
+            functions: overrides.functions.or(self.functions),
+            impls: overrides.impls.or(self.impls),
+            macros: overrides.macros.or(self.macros),
+        }
+    }
+
+    /// The `(count, maximum, kind)` of every limit exceeded by `used`.
+    fn exceeded(&self, used: &CounterBlock) -> Vec<(u64, u64, &'static str)> {
+        vec![
+            (used.exprs.unsafe_, self.exprs, "expressions"),
+            (used.functions.unsafe_, self.functions, "functions"),
+            (used.item_impls.unsafe_, self.impls, "impls"),
+            (used.macros.unsafe_, self.macros, "macros"),
+        ]
+        .into_iter()
+        .filter_map(|(count, max, kind)| match max {
+            Some(max) if count > max => Some((count, max, kind)),
+            _ => None,
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod thresholds_tests {
+    use super::*;
+
+    use cargo_geiger_serde::{
+        Count, PackageId, PackageInfo, ReportEntry, Source, UnsafeInfo,
+    };
+    use rstest::*;
+    use semver::Version;
+    use url::Url;
+
+    #[rstest(
+        input_thresholds,
+        expected_violations,
+        case(Thresholds::default(), vec![]),
+        case(
+            Thresholds {
+                per_crate: UnsafeBudget {
+                    exprs: Some(5),
+                    impls: Some(0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            vec![
+                "`bar 0.1.0` uses 1 unsafe impls, the maximum per crate is 0",
+                "`foo 1.0.0` uses 10 unsafe expressions, the maximum per \
+                 crate is 5",
+            ]
+        ),
+        case(
+            Thresholds {
+                total: UnsafeBudget {
+                    exprs: Some(12),
+                    functions: Some(2),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            vec![
+                "All packages together use 13 unsafe expressions, the \
+                 maximum is 12",
+            ]
+        ),
+        case(
+            Thresholds {
+                per_crate: UnsafeBudget {
+                    macros: Some(0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            vec!["`bar 0.1.0` uses 2 unsafe macros, the maximum per crate is 0"]
+        )
+    )]
+    fn find_violations_test(
+        input_thresholds: Thresholds,
+        expected_violations: Vec<&str>,
+    ) {
+        let mut report = SafetyReport::default();
+        for &(name, version, exprs, functions, impls, macros) in
+            &[("foo", "1.0.0", 10, 2, 0, 0), ("bar", "0.1.0", 3, 0, 1, 2)]
+        {
+            let id = PackageId {
+                name: String::from(name),
+                version: Version::parse(version).unwrap(),
+                source: Source::Registry {
+                    name: String::from("crates.io"),
+                    url: Url::parse(
+                        "https://github.com/rust-lang/crates.io-index",
+                    )
+                    .unwrap(),
+                },
+            };
+            let used = CounterBlock {
+                exprs: Count {
+                    safe: 0,
+                    unsafe_: exprs,
+                },
+                functions: Count {
+                    safe: 0,
+                    unsafe_: functions,
+                },
+                item_impls: Count {
+                    safe: 0,
+                    unsafe_: impls,
+                },
+                macros: Count {
+                    safe: 0,
+                    unsafe_: macros,
+                },
+                ..Default::default()
+            };
+            let entry = ReportEntry {
+                package: PackageInfo::new(id.clone()),
+                unsafety: UnsafeInfo {
+                    used,
+                    ..Default::default()
+                },
+                files: None,
+            };
+            report.packages.insert(id, entry);
+        }
+
+        assert_eq!(
+            input_thresholds.find_violations(&report),
+            expected_violations
+        );
+    }
+
+    #[rstest]
+    fn overridden_by_test() {
+        let thresholds = Thresholds {
+            per_crate: UnsafeBudget {
+                exprs: Some(5),
+                functions: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let overrides = Thresholds {
+            per_crate: UnsafeBudget {
+                exprs: Some(10),
+                ..Default::default()
+            },
+            total: UnsafeBudget {
+                impls: Some(2),
+                ..Default::default()
+            },
+        };
+
+        assert_eq!(
+            thresholds.overridden_by(&overrides),
+            Thresholds {
+                per_crate: UnsafeBudget {
+                    exprs: Some(10),
+                    functions: Some(1),
+                    ..Default::default()
+                },
+                total: UnsafeBudget {
+                    impls: Some(2),
+                    ..Default::default()
+                },
+            }
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Packages and reports shared by the unit tests of the report formats, the
+//! report diffs and the checks run on a report.
+
+use cargo_geiger_serde::{
+    Count, CounterBlock, PackageId, PackageInfo, ReportEntry, SafetyReport,
+    Source, UnsafeInfo,
+};
+use semver::Version;
+use url::Url;
+
+const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// A package from crates.io.
+pub fn create_package_id(name: &str, version: &str) -> PackageId {
+    PackageId {
+        name: String::from(name),
+        version: Version::parse(version).unwrap(),
+        source: Source::Registry {
+            name: String::from("crates.io"),
+            url: Url::parse(CRATES_IO_INDEX).unwrap(),
+        },
+    }
+}
+
+pub fn create_report(entries: Vec<ReportEntry>) -> SafetyReport {
+    let mut report = SafetyReport::default();
+    for entry in entries {
+        report.packages.insert(entry.package.id.clone(), entry);
+    }
+    report
+}
+
+/// A package from crates.io whose only unsafe usage is expressions.
+pub fn create_report_entry(
+    name: &str,
+    version: &str,
+    used_unsafe_exprs: u64,
+    unused_unsafe_exprs: u64,
+    forbids_unsafe: bool,
+) -> ReportEntry {
+    ReportEntry {
+        package: PackageInfo::new(create_package_id(name, version)),
+        unsafety: UnsafeInfo {
+            used: create_counter_block(used_unsafe_exprs),
+            unused: create_counter_block(unused_unsafe_exprs),
+            forbids_unsafe,
+            ..Default::default()
+        },
+        files: None,
+    }
+}
+
+fn create_counter_block(unsafe_exprs: u64) -> CounterBlock {
+    CounterBlock {
+        exprs: Count {
+            safe: 0,
+            unsafe_: unsafe_exprs,
+        },
+        ..Default::default()
+    }
+}
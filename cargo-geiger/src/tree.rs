@@ -9,8 +9,12 @@ use cargo_metadata::{DependencyKind, PackageId};
 /// dependency graph traversal.
 #[derive(Debug, PartialEq)]
 pub enum TextTreeLine {
-    /// A text line for a package
-    Package { id: PackageId, tree_vines: String },
+    /// A text line for a package, `depth` is 0 for the root package
+    Package {
+        id: PackageId,
+        depth: usize,
+        tree_vines: String,
+    },
     /// There are extra dependencies coming and we should print a group header,
     /// eg. "[build-dependencies]".
     ExtraDepsGroup {
@@ -133,6 +137,7 @@ mod tree_tests {
             charset: Charset::Ascii,
             allow_partial_results: false,
             include_tests: IncludeTests::Yes,
+            jobs: 1,
             output_format: None,
         }
     }
@@ -28,6 +28,7 @@ pub fn walk_dependency_node(
 
     let mut all_out_text_tree_lines = vec![TextTreeLine::Package {
         id: package.clone(),
+        depth: walk_dependency_parameters.levels_continue.len(),
         tree_vines,
     }];
 
@@ -255,6 +256,7 @@ mod dependency_node_tests {
             direction: edge_direction,
             format: Pattern(vec![]),
             include_tests: IncludeTests::Yes,
+            jobs: 1,
             prefix: Prefix::Depth,
             output_format: None,
             verbosity: Verbosity::Verbose,
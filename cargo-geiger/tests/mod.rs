@@ -3,8 +3,9 @@
 
 use assert_cmd::prelude::*;
 use cargo_geiger_serde::{
-    Count, CounterBlock, PackageId, PackageInfo, QuickReportEntry,
-    QuickSafetyReport, ReportEntry, SafetyReport, Source, UnsafeInfo,
+    Count, CounterBlock, LineColumn, PackageId, PackageInfo, QuickReportEntry,
+    QuickSafetyReport, ReportEntry, ReportMetadata, SafetyReport, Source,
+    UnsafeFinding, UnsafeInfo, UnsafeKind, UnsafeLocation,
 };
 use insta::assert_snapshot;
 use rstest::rstest;
@@ -73,6 +74,11 @@ fn serialize_test4_report() {
     Test4.run();
 }
 
+#[test]
+fn serialize_test5_workspace_report() {
+    Test5.run();
+}
+
 #[test]
 fn serialize_test6_report() {
     Test6.run();
@@ -103,6 +109,11 @@ fn serialize_test4_quick_report() {
     Test4.run_quick();
 }
 
+#[test]
+fn serialize_test5_workspace_quick_report() {
+    Test5.run_quick();
+}
+
 #[test]
 fn serialize_test6_quick_report() {
     Test6.run_quick();
@@ -115,6 +126,7 @@ fn serialize_test7_quick_report() {
 
 trait Test {
     const NAME: &'static str;
+    const EXTRA_ARGS: &'static [&'static str] = &[];
 
     fn expected_report(&self, cx: &Context) -> SafetyReport;
     fn expected_report_entry(&self, cx: &Context) -> ReportEntry;
@@ -124,19 +136,23 @@ trait Test {
     }
 
     fn run(&self) {
-        let (output, cx) = run_geiger_json(Self::NAME);
+        let (output, cx) = run_geiger_json(Self::NAME, Self::EXTRA_ARGS);
         assert!(output.status.success());
-        let actual =
+        let mut actual =
             serde_json::from_slice::<SafetyReport>(&output.stdout).unwrap();
+        // The metadata depends on the toolchain.
+        actual.metadata = ReportMetadata::default();
+        strip_dependency_unsafe_locations(&mut actual);
         assert_eq!(actual, self.expected_report(&cx));
     }
 
     fn run_quick(&self) {
-        let (output, cx) = run_geiger_json_quick(Self::NAME);
+        let (output, cx) = run_geiger_json_quick(Self::NAME, Self::EXTRA_ARGS);
         assert!(output.status.success());
-        let actual =
+        let mut actual =
             serde_json::from_slice::<QuickSafetyReport>(&output.stdout)
                 .unwrap();
+        actual.metadata = ReportMetadata::default();
         assert_eq!(actual, self.expected_quick_report(&cx));
     }
 }
@@ -163,10 +179,41 @@ impl Test for Test1 {
                         safe: 4,
                         unsafe_: 2,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
+                unsafe_locations: vec![
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Function,
+                        "f",
+                        (3, 5),
+                        (3, 18),
+                    ),
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Expression,
+                        "f",
+                        (4, 5),
+                        (4, 21),
+                    ),
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Expression,
+                        "g",
+                        (9, 9),
+                        (9, 55),
+                    ),
+                ],
                 ..Default::default()
             },
+            files: None,
         }
     }
 }
@@ -205,8 +252,51 @@ impl Test for Test2 {
                     },
                     ..Default::default()
                 },
+                unsafe_locations: vec![
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Function,
+                        "f",
+                        (3, 5),
+                        (3, 18),
+                    ),
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Expression,
+                        "f",
+                        (4, 5),
+                        (4, 36),
+                    ),
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Expression,
+                        "f",
+                        (5, 5),
+                        (5, 36),
+                    ),
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Expression,
+                        "f",
+                        (7, 5),
+                        (7, 14),
+                    ),
+                    make_unsafe_location(
+                        "src/lib.rs",
+                        true,
+                        UnsafeKind::Expression,
+                        "f",
+                        (7, 9),
+                        (7, 14),
+                    ),
+                ],
                 ..Default::default()
             },
+            files: None,
         }
     }
 }
@@ -245,10 +335,23 @@ impl Test for Test3 {
                         safe: 6,
                         unsafe_: 1,
                     },
+                    macros: Count {
+                        safe: 2,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
+                unsafe_locations: vec![make_unsafe_location(
+                    "src/main.rs",
+                    true,
+                    UnsafeKind::Expression,
+                    "main",
+                    (2, 14),
+                    (2, 50),
+                )],
                 ..Default::default()
             },
+            files: None,
         }
     }
 }
@@ -281,6 +384,10 @@ impl Test for Test4 {
                         safe: 1,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 unused: CounterBlock {
@@ -294,8 +401,61 @@ impl Test for Test4 {
                     },
                     ..Default::default()
                 },
+                unsafe_locations: vec![make_unsafe_location(
+                    "member1/src/main.rs",
+                    false,
+                    UnsafeKind::Expression,
+                    "main",
+                    (4, 14),
+                    (4, 18),
+                )],
                 ..Default::default()
             },
+            files: None,
+        }
+    }
+}
+
+struct Test5;
+
+impl Test for Test5 {
+    const NAME: &'static str = "test5_workspace_with_virtual_manifest";
+    const EXTRA_ARGS: &'static [&'static str] = &["--workspace"];
+
+    fn expected_report(&self, cx: &Context) -> SafetyReport {
+        single_entry_safety_report(self.expected_report_entry(cx))
+    }
+
+    fn expected_report_entry(&self, cx: &Context) -> ReportEntry {
+        ReportEntry {
+            package: PackageInfo::new(PackageId {
+                name: "member1".into(),
+                version: Version::new(0, 1, 0),
+                source: make_workspace_source(cx, Self::NAME, "member1"),
+            }),
+            unsafety: UnsafeInfo {
+                used: CounterBlock {
+                    functions: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
+                    exprs: Count {
+                        safe: 1,
+                        unsafe_: 1,
+                    },
+                    ..Default::default()
+                },
+                unsafe_locations: vec![make_unsafe_location(
+                    "src/main.rs",
+                    true,
+                    UnsafeKind::Expression,
+                    "main",
+                    (4, 14),
+                    (4, 18),
+                )],
+                ..Default::default()
+            },
+            files: None,
         }
     }
 }
@@ -335,11 +495,16 @@ impl Test for Test6 {
                         safe: 1,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 forbids_unsafe: true,
                 ..Default::default()
             },
+            files: None,
         }
     }
 }
@@ -372,11 +537,16 @@ impl Test for Test7 {
                         safe: 1,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 forbids_unsafe: true,
                 ..Default::default()
             },
+            files: None,
         }
     }
 }
@@ -385,12 +555,18 @@ fn run_geiger(test_name: &str) -> Output {
     run_geiger_with(test_name, None::<&str>).0
 }
 
-fn run_geiger_json(test_name: &str) -> (Output, Context) {
-    run_geiger_with(test_name, &["--json"])
+fn run_geiger_json(test_name: &str, extra_args: &[&str]) -> (Output, Context) {
+    run_geiger_with(test_name, ["--json"].iter().chain(extra_args))
 }
 
-fn run_geiger_json_quick(test_name: &str) -> (Output, Context) {
-    run_geiger_with(test_name, &["--forbid-only", "--json"])
+fn run_geiger_json_quick(
+    test_name: &str,
+    extra_args: &[&str],
+) -> (Output, Context) {
+    run_geiger_with(
+        test_name,
+        ["--forbid-only", "--json"].iter().chain(extra_args),
+    )
 }
 
 fn run_geiger_with<I>(test_name: &str, extra_args: I) -> (Output, Context)
@@ -478,6 +654,60 @@ fn make_package_id(cx: &Context, name: &str) -> PackageId {
     }
 }
 
+fn make_unsafe_location(
+    file: &str,
+    used: bool,
+    kind: UnsafeKind,
+    item_path: &str,
+    (start_line, start_column): (usize, usize),
+    (end_line, end_column): (usize, usize),
+) -> UnsafeLocation {
+    UnsafeLocation {
+        file: PathBuf::from(file),
+        used,
+        finding: UnsafeFinding {
+            kind,
+            item_path: item_path.into(),
+            start: LineColumn {
+                line: start_line,
+                column: start_column,
+            },
+            end: LineColumn {
+                line: end_line,
+                column: end_column,
+            },
+        },
+    }
+}
+
+/// Only the test crates list their unsafe locations in the expected reports.
+/// The locations of the dependencies are checked against their counters and
+/// then left out.
+fn strip_dependency_unsafe_locations(report: &mut SafetyReport) {
+    let unsafe_count = |counters: &CounterBlock| {
+        counters.functions.unsafe_
+            + counters.exprs.unsafe_
+            + counters.item_impls.unsafe_
+            + counters.item_traits.unsafe_
+            + counters.methods.unsafe_
+            + counters.macros.unsafe_
+    };
+    for entry in report.packages.values_mut() {
+        if entry.package.id.name.starts_with("test") {
+            continue;
+        }
+        let unsafety = &mut entry.unsafety;
+        let used_locations =
+            unsafety.unsafe_locations.iter().filter(|l| l.used).count();
+        assert_eq!(used_locations as u64, unsafe_count(&unsafety.used));
+        assert_eq!(
+            (unsafety.unsafe_locations.len() - used_locations) as u64,
+            unsafe_count(&unsafety.unused)
+        );
+        unsafety.unsafe_locations.clear();
+    }
+}
+
 fn report_entry_list_to_map<I>(entries: I) -> HashMap<PackageId, ReportEntry>
 where
     I: IntoIterator<Item = ReportEntry>,
@@ -521,6 +751,8 @@ fn to_quick_report(report: SafetyReport) -> QuickSafetyReport {
         })
         .collect();
     QuickSafetyReport {
+        schema_version: report.schema_version,
+        metadata: report.metadata,
         packages: entries,
         packages_without_metrics: report.packages_without_metrics,
     }
@@ -538,8 +770,8 @@ mod external {
         merge_test_reports, single_entry_safety_report, to_set, Context, Test,
     };
     use cargo_geiger_serde::{
-        Count, CounterBlock, PackageId, PackageInfo, ReportEntry, SafetyReport,
-        Source, UnsafeInfo,
+        Count, CounterBlock, GitReference, PackageId, PackageInfo, ReportEntry,
+        SafetyReport, Source, UnsafeInfo,
     };
     use semver::Version;
     use url::Url;
@@ -577,6 +809,7 @@ mod external {
                 },
                 ..Default::default()
             },
+            files: None,
         };
         single_entry_safety_report(entry)
     }
@@ -610,10 +843,15 @@ mod external {
                         safe: 50,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 39,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 ..Default::default()
             },
+            files: None,
         };
         single_entry_safety_report(entry)
     }
@@ -639,10 +877,15 @@ mod external {
                         safe: 37,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 4,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 ..Default::default()
             },
+            files: None,
         };
         single_entry_safety_report(entry)
     }
@@ -656,7 +899,10 @@ mod external {
                     "https://github.com/rust-itertools/itertools.git",
                 )
                 .unwrap(),
-                rev: "8761fbefb3b209".into(),
+                reference: GitReference::Rev("8761fbefb3b209".into()),
+                precise: Some(
+                    "8761fbefb3b209cf41829f8dba38044b69c1d8dd".into(),
+                ),
             },
         }
     }
@@ -689,6 +935,10 @@ mod external {
                         safe: 180,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 87,
+                        unsafe_: 0,
+                    },
                 },
                 unused: CounterBlock {
                     functions: Count {
@@ -711,9 +961,14 @@ mod external {
                         safe: 29,
                         unsafe_: 3,
                     },
+                    macros: Count {
+                        safe: 116,
+                        unsafe_: 0,
+                    },
                 },
                 ..Default::default()
             },
+            files: None,
         };
         let mut report = single_entry_safety_report(entry);
         merge_test_reports(&mut report, either_safety_report());
@@ -731,7 +986,24 @@ mod external {
     pub(super) fn cfg_if_safety_report() -> SafetyReport {
         let entry = ReportEntry {
             package: PackageInfo::new(cfg_if_package_id()),
-            unsafety: Default::default(),
+            unsafety: UnsafeInfo {
+                used: CounterBlock {
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
+                    ..Default::default()
+                },
+                unused: CounterBlock {
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            files: None,
         };
         single_entry_safety_report(entry)
     }
@@ -764,6 +1036,10 @@ mod external {
                         safe: 39,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 9,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 unused: CounterBlock {
@@ -783,11 +1059,16 @@ mod external {
                         safe: 8,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 12,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 forbids_unsafe: true,
-                unsafe_functions: vec![],
+                ..Default::default()
             },
+            files: None,
         };
         let mut report = single_entry_safety_report(entry);
         merge_test_reports(&mut report, cfg_if_safety_report());
@@ -822,6 +1103,10 @@ mod external {
                         safe: 13596,
                         unsafe_: 1,
                     },
+                    macros: Count {
+                        safe: 7,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 unused: CounterBlock {
@@ -833,10 +1118,15 @@ mod external {
                         safe: 185,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 17,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 ..Default::default()
             },
+            files: None,
         };
         let mut report = single_entry_safety_report(entry);
         merge_test_reports(&mut report, matches_safety_report());
@@ -856,7 +1146,17 @@ mod external {
     pub(super) fn matches_safety_report() -> SafetyReport {
         let entry = ReportEntry {
             package: PackageInfo::new(matches_package_id()),
-            unsafety: Default::default(),
+            unsafety: UnsafeInfo {
+                used: CounterBlock {
+                    macros: Count {
+                        safe: 3,
+                        unsafe_: 0,
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            files: None,
         };
         single_entry_safety_report(entry)
     }
@@ -894,6 +1194,10 @@ mod external {
                         safe: 92,
                         unsafe_: 13,
                     },
+                    macros: Count {
+                        safe: 25,
+                        unsafe_: 1,
+                    },
                 },
                 unused: CounterBlock {
                     functions: Count {
@@ -916,9 +1220,14 @@ mod external {
                         safe: 14,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 7,
+                        unsafe_: 0,
+                    },
                 },
                 ..Default::default()
             },
+            files: None,
         };
         single_entry_safety_report(entry)
     }
@@ -955,11 +1264,16 @@ mod external {
                         safe: 31,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 19,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 forbids_unsafe: true,
                 ..Default::default()
             },
+            files: None,
         };
         let mut report = single_entry_safety_report(entry);
         merge_test_reports(&mut report, matches_safety_report());
@@ -1002,6 +1316,7 @@ mod external {
                         safe: 21,
                         unsafe_: 0,
                     },
+                    ..Default::default()
                 },
                 unused: CounterBlock {
                     functions: Count {
@@ -1016,6 +1331,7 @@ mod external {
                 },
                 ..Default::default()
             },
+            files: None,
         };
         let mut report = single_entry_safety_report(entry);
         merge_test_reports(&mut report, smallvec_safety_report());
@@ -1049,6 +1365,7 @@ mod external {
                 },
                 ..Default::default()
             },
+            files: None,
         };
         let mut report = single_entry_safety_report(entry);
         merge_test_reports(&mut report, super::Test1.expected_report(cx));
@@ -0,0 +1,318 @@
+//! Evaluation of `#[cfg(...)]` and `#[cfg_attr(...)]` attributes.
+
+use crate::IncludeTests;
+
+use cargo_platform::{Cfg, CfgExpr};
+use std::collections::HashSet;
+use syn::{Attribute, Lit, Meta, NestedMeta};
+
+/// Names and keys of the cfgs set by rustc itself. They can be absent from
+/// `rustc --print=cfg`, e.g. `windows` on Linux, and are false then. Every
+/// `target_*` key is decided as well.
+const RUSTC_CFG_NAMES: &[&str] = &[
+    "debug_assertions",
+    "fmt_debug",
+    "overflow_checks",
+    "panic",
+    "proc_macro",
+    "relocation_model",
+    "target_thread_local",
+    "ub_checks",
+    "unix",
+    "windows",
+];
+
+/// The conditional compilation options that scanned code is evaluated
+/// against.
+///
+/// Options that are unknown, e.g. the platform cfgs when `rustc --print=cfg`
+/// could not be run, are treated as undecided and code gated behind them is
+/// scanned as usual. The same goes for cfgs that rustc does not set, like the
+/// ones from build scripts, `--cfg` in `RUSTFLAGS`, `docsrs` or `miri`. Only
+/// code whose `#[cfg]` predicate is known to be false is skipped.
+#[derive(Clone, Debug, Default)]
+pub struct ActiveCfgs {
+    /// Target cfgs as printed by `rustc --print=cfg`
+    platform: Option<HashSet<Cfg>>,
+
+    /// Enabled features of the package being scanned
+    features: Option<HashSet<String>>,
+}
+
+impl ActiveCfgs {
+    pub fn new(
+        platform: Option<Vec<Cfg>>,
+        features: Option<Vec<String>>,
+    ) -> Self {
+        ActiveCfgs {
+            platform: platform.map(|cfgs| cfgs.into_iter().collect()),
+            features: features.map(|features| features.into_iter().collect()),
+        }
+    }
+
+    /// Returns false if any `#[cfg]` in `attrs`, including the ones enabled
+    /// through `#[cfg_attr]`, is known to evaluate to false.
+    pub fn is_enabled(
+        &self,
+        attrs: &[Attribute],
+        include_tests: IncludeTests,
+    ) -> bool {
+        self.effective_attrs(attrs, include_tests)
+            .iter()
+            .all(|meta| self.is_cfg_enabled(meta, include_tests))
+    }
+
+    /// Parses `attrs` and expands every `#[cfg_attr]` whose predicate is known
+    /// to be true into the attributes it applies. Attributes behind a false or
+    /// undecided predicate are left out.
+    pub fn effective_attrs(
+        &self,
+        attrs: &[Attribute],
+        include_tests: IncludeTests,
+    ) -> Vec<Meta> {
+        let mut metas = Vec::new();
+        for meta in attrs.iter().filter_map(|attr| attr.parse_meta().ok()) {
+            self.expand_cfg_attr(meta, include_tests, &mut metas);
+        }
+        metas
+    }
+
+    /// Evaluates a cfg predicate, `None` if the result depends on options
+    /// that are not known.
+    ///
+    /// `cfg(test)` is false when tests are excluded and undecided when they
+    /// are included, since both the regular and the test build are of
+    /// interest in that case.
+    pub fn eval(
+        &self,
+        expr: &CfgExpr,
+        include_tests: IncludeTests,
+    ) -> Option<bool> {
+        match expr {
+            CfgExpr::Not(expr) => self.eval(expr, include_tests).map(|b| !b),
+            CfgExpr::All(exprs) => {
+                let mut result = Some(true);
+                for expr in exprs {
+                    match self.eval(expr, include_tests) {
+                        Some(false) => return Some(false),
+                        None => result = None,
+                        Some(true) => {}
+                    }
+                }
+                result
+            }
+            CfgExpr::Any(exprs) => {
+                let mut result = Some(false);
+                for expr in exprs {
+                    match self.eval(expr, include_tests) {
+                        Some(true) => return Some(true),
+                        None => result = None,
+                        Some(false) => {}
+                    }
+                }
+                result
+            }
+            CfgExpr::Value(cfg) => self.eval_value(cfg, include_tests),
+        }
+    }
+
+    fn eval_value(
+        &self,
+        cfg: &Cfg,
+        include_tests: IncludeTests,
+    ) -> Option<bool> {
+        match cfg {
+            Cfg::Name(name) if name == "test" => match include_tests {
+                IncludeTests::Yes => None,
+                IncludeTests::No => Some(false),
+            },
+            Cfg::KeyPair(key, value) if key == "feature" => self
+                .features
+                .as_ref()
+                .map(|features| features.contains(value)),
+            _ => match &self.platform {
+                Some(platform) if is_rustc_cfg(cfg, platform) => {
+                    Some(platform.contains(cfg))
+                }
+                _ => None,
+            },
+        }
+    }
+
+    fn expand_cfg_attr(
+        &self,
+        meta: Meta,
+        include_tests: IncludeTests,
+        metas: &mut Vec<Meta>,
+    ) {
+        let list = match meta {
+            Meta::List(list) if list.path.is_ident("cfg_attr") => list,
+            other => {
+                metas.push(other);
+                return;
+            }
+        };
+        let mut nested = list.nested.into_iter();
+        let predicate = nested
+            .next()
+            .and_then(|n| cfg_expr_from_nested_meta(&n))
+            .and_then(|expr| self.eval(&expr, include_tests));
+        if predicate != Some(true) {
+            return;
+        }
+        for n in nested {
+            if let NestedMeta::Meta(meta) = n {
+                self.expand_cfg_attr(meta, include_tests, metas);
+            }
+        }
+    }
+
+    fn is_cfg_enabled(&self, meta: &Meta, include_tests: IncludeTests) -> bool {
+        match meta {
+            Meta::List(list)
+                if list.path.is_ident("cfg") && list.nested.len() == 1 =>
+            {
+                cfg_expr_from_nested_meta(&list.nested[0])
+                    .and_then(|expr| self.eval(&expr, include_tests))
+                    .unwrap_or(true)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Whether `cfg` is set by rustc, so that its absence from the platform cfgs
+/// means it is false.
+fn is_rustc_cfg(cfg: &Cfg, platform: &HashSet<Cfg>) -> bool {
+    let name = match cfg {
+        Cfg::Name(name) | Cfg::KeyPair(name, _) => name,
+    };
+    name.starts_with("target_")
+        || RUSTC_CFG_NAMES.contains(&name.as_str())
+        || platform.iter().any(|platform_cfg| match platform_cfg {
+            Cfg::Name(platform_name) | Cfg::KeyPair(platform_name, _) => {
+                platform_name == name
+            }
+        })
+}
+
+/// Converts the syn representation of a cfg predicate, e.g. the
+/// `all(unix, feature = "std")` in `#[cfg(all(unix, feature = "std"))]`, to a
+/// `CfgExpr`. Returns `None` for malformed predicates.
+fn cfg_expr_from_nested_meta(nested: &NestedMeta) -> Option<CfgExpr> {
+    match nested {
+        NestedMeta::Meta(Meta::Path(path)) => {
+            let name = path.get_ident()?.to_string();
+            Some(CfgExpr::Value(Cfg::Name(name)))
+        }
+        NestedMeta::Meta(Meta::NameValue(name_value)) => {
+            let key = name_value.path.get_ident()?.to_string();
+            match &name_value.lit {
+                Lit::Str(value) => {
+                    Some(CfgExpr::Value(Cfg::KeyPair(key, value.value())))
+                }
+                _ => None,
+            }
+        }
+        NestedMeta::Meta(Meta::List(list)) => {
+            let mut exprs = list
+                .nested
+                .iter()
+                .map(cfg_expr_from_nested_meta)
+                .collect::<Option<Vec<_>>>()?;
+            if list.path.is_ident("all") {
+                Some(CfgExpr::All(exprs))
+            } else if list.path.is_ident("any") {
+                Some(CfgExpr::Any(exprs))
+            } else if list.path.is_ident("not") && exprs.len() == 1 {
+                Some(CfgExpr::Not(Box::new(exprs.pop()?)))
+            } else {
+                None
+            }
+        }
+        NestedMeta::Lit(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod cfg_tests {
+    use super::*;
+
+    use rstest::*;
+
+    #[rstest(
+        input_attribute,
+        expected_enabled,
+        case("#[cfg(unix)]", true),
+        case("#[cfg(windows)]", false),
+        case("#[cfg(target_os = \"linux\")]", true),
+        case("#[cfg(target_os = \"macos\")]", false),
+        case("#[cfg(target_has_atomic = \"64\")]", false),
+        case("#[cfg(feature = \"std\")]", true),
+        case("#[cfg(feature = \"alloc\")]", false),
+        case("#[cfg(test)]", false),
+        case("#[cfg(all(unix, feature = \"std\"))]", true),
+        case("#[cfg(all(unix, windows))]", false),
+        case("#[cfg(any(windows, feature = \"std\"))]", true),
+        case("#[cfg(any(windows, feature = \"alloc\"))]", false),
+        case("#[cfg(not(windows))]", true),
+        case("#[cfg(not(unix))]", false),
+        // Unknown to rustc, e.g. set by a build script or `RUSTFLAGS`.
+        case("#[cfg(docsrs)]", true),
+        case("#[cfg(not(loom))]", true),
+        case("#[cfg(tokio_unstable = \"yes\")]", true),
+        case("#[cfg(all(miri, windows))]", false),
+        case("#[cfg(any(miri, unix))]", true),
+        case("#[cfg_attr(unix, cfg(windows))]", false),
+        case("#[cfg_attr(windows, cfg(windows))]", true),
+        case("#[cfg_attr(docsrs, cfg(windows))]", true),
+        case("#[cfg_attr(all(), cfg_attr(unix, cfg(windows)))]", false)
+    )]
+    fn is_enabled_test(input_attribute: &str, expected_enabled: bool) {
+        let item: syn::ItemFn =
+            syn::parse_str(&format!("{} fn f() {{}}", input_attribute))
+                .unwrap();
+
+        assert_eq!(
+            create_active_cfgs().is_enabled(&item.attrs, IncludeTests::No),
+            expected_enabled
+        );
+    }
+
+    #[rstest(
+        input_include_tests,
+        expected_result,
+        case(IncludeTests::No, Some(false)),
+        case(IncludeTests::Yes, None)
+    )]
+    fn eval_test_cfg_test(
+        input_include_tests: IncludeTests,
+        expected_result: Option<bool>,
+    ) {
+        let expr = CfgExpr::Value(Cfg::Name(String::from("test")));
+
+        assert_eq!(
+            create_active_cfgs().eval(&expr, input_include_tests),
+            expected_result
+        );
+    }
+
+    #[rstest]
+    fn eval_test_unknown_platform() {
+        let active_cfgs = ActiveCfgs::default();
+        let expr = CfgExpr::Value(Cfg::Name(String::from("windows")));
+
+        assert_eq!(active_cfgs.eval(&expr, IncludeTests::No), None);
+    }
+
+    fn create_active_cfgs() -> ActiveCfgs {
+        ActiveCfgs::new(
+            Some(vec![
+                Cfg::Name(String::from("unix")),
+                Cfg::Name(String::from("debug_assertions")),
+                Cfg::KeyPair(String::from("target_os"), String::from("linux")),
+            ]),
+            Some(vec![String::from("std")]),
+        )
+    }
+}
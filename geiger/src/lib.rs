@@ -7,7 +7,24 @@
 #![forbid(unsafe_code)]
 #![forbid(warnings)]
 
-use cargo_geiger_serde::CounterBlock;
+mod cfg;
+mod module_tree;
+
+pub use cfg::ActiveCfgs;
+pub use module_tree::find_module_files;
+
+/// The version of this crate, which does the scanning.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Identifies how source code is turned into `RsFileMetrics`, bump it with
+/// every change to the scan or to `RsFileMetrics` that can change the result
+/// for the same source code. Stored scan results of an older format must not
+/// be reused.
+pub const ANALYSIS_FORMAT_VERSION: u32 = 1;
+
+use cargo_geiger_serde::{CounterBlock, LineColumn, UnsafeFinding, UnsafeKind};
+use proc_macro2::{Span, TokenStream, TokenTree};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
@@ -16,7 +33,11 @@ use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
 use std::string::FromUtf8Error;
-use syn::{visit, Expr, ImplItemMethod, ItemFn, ItemImpl, ItemMod, ItemTrait};
+use syn::spanned::Spanned;
+use syn::{
+    visit, Arm, Attribute, Expr, ImplItem, ImplItemMethod, Item, ItemFn,
+    ItemImpl, ItemMod, ItemTrait, Local, Macro, TraitItem, Type,
+};
 
 #[derive(Debug)]
 pub enum ScanFileError {
@@ -36,7 +57,7 @@ impl fmt::Display for ScanFileError {
 }
 
 /// Scan result for a single `.rs` file.
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct RsFileMetrics {
     /// Metrics storage.
     pub counters: CounterBlock,
@@ -49,6 +70,9 @@ pub struct RsFileMetrics {
 
     /// Names of safe functions that contains unsafe blocks
     pub contains_unsafe_functions: Vec<String>,
+
+    /// Location of every item counted as unsafe, in visiting order
+    pub unsafe_findings: Vec<UnsafeFinding>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -63,10 +87,13 @@ struct FunctionStat {
     contains_unsafe_block: bool,
 }
 
-struct GeigerSynVisitor {
+struct GeigerSynVisitor<'a> {
     /// Count unsafe usage inside tests
     include_tests: IncludeTests,
 
+    /// Code whose `#[cfg]` evaluates to false for these is skipped
+    cfgs: &'a ActiveCfgs,
+
     /// The resulting data from a single file scan.
     metrics: RsFileMetrics,
 
@@ -81,15 +108,21 @@ struct GeigerSynVisitor {
 
     /// Currently visiting functions
     function_stack: Vec<FunctionStat>,
+
+    /// Names of the modules, impls, traits and functions enclosing the
+    /// currently visited syntax node
+    item_path: Vec<String>,
 }
 
-impl GeigerSynVisitor {
-    fn new(include_tests: IncludeTests) -> Self {
+impl<'a> GeigerSynVisitor<'a> {
+    fn new(include_tests: IncludeTests, cfgs: &'a ActiveCfgs) -> Self {
         GeigerSynVisitor {
             include_tests,
+            cfgs,
             metrics: Default::default(),
             unsafe_scopes: 0,
             function_stack: Vec::new(),
+            item_path: Vec::new(),
         }
     }
 
@@ -100,51 +133,51 @@ impl GeigerSynVisitor {
     fn exit_unsafe_scope(&mut self) {
         self.unsafe_scopes -= 1;
     }
+
+    fn is_cfg_enabled(&self, attrs: &[Attribute]) -> bool {
+        self.cfgs.is_enabled(attrs, self.include_tests)
+    }
+
+    /// Records an unsafe finding ranging from the start of `start` to the end
+    /// of `end`.
+    fn add_finding(&mut self, kind: UnsafeKind, start: Span, end: Span) {
+        self.metrics.unsafe_findings.push(UnsafeFinding {
+            kind,
+            item_path: self.item_path.join("::"),
+            start: to_line_column(start.start()),
+            end: to_line_column(end.end()),
+        });
+    }
 }
 
-/// Will return true for #[cfg(test)] decodated modules.
-///
-/// This function is a somewhat of a hack and will probably misinterpret more
-/// advanced cfg expressions. A better way to do this would be to let rustc emit
-/// every single source file path and span within each source file and use that
-/// as a general filter for included code.
-/// TODO: Investigate if the needed information can be emitted by rustc today.
-fn is_test_mod(i: &ItemMod) -> bool {
-    use syn::Attribute;
-    use syn::Meta;
-    i.attrs
-        .iter()
-        .flat_map(Attribute::parse_meta)
-        .any(|m| match m {
-            Meta::List(ml) => meta_list_is_cfg_test(&ml),
-            _ => false,
-        })
+/// proc-macro2 columns are 0-based, report them 1-based like the lines.
+fn to_line_column(lc: proc_macro2::LineColumn) -> LineColumn {
+    LineColumn {
+        line: lc.line,
+        column: lc.column + 1,
+    }
 }
 
-// MetaList {
-//     ident: Ident(
-//         cfg
-//     ),
-//     paren_token: Paren,
-//     nested: [
-//         Meta(
-//             Word(
-//                 Ident(
-//                     test
-//                 )
-//             )
-//         )
-//     ]
-// }
-fn meta_list_is_cfg_test(ml: &syn::MetaList) -> bool {
-    use syn::NestedMeta;
-    if !ml.path.is_ident("cfg") {
-        return false;
+/// Name used for an impl block in `UnsafeFinding::item_path`, `Type` for
+/// inherent impls and `<Type as Trait>` for trait impls.
+fn impl_name(i: &ItemImpl) -> String {
+    let self_ty = match &*i.self_ty {
+        Type::Path(type_path) => last_segment_name(&type_path.path),
+        _ => String::from("_"),
+    };
+    match &i.trait_ {
+        Some((_, path, _)) => {
+            format!("<{} as {}>", self_ty, last_segment_name(path))
+        }
+        None => self_ty,
     }
-    ml.nested.iter().any(|n| match n {
-        NestedMeta::Meta(meta) => meta_is_word_test(meta),
-        _ => false,
-    })
+}
+
+fn last_segment_name(path: &syn::Path) -> String {
+    path.segments
+        .last()
+        .map(|segment| segment.ident.to_string())
+        .unwrap_or_default()
 }
 
 fn meta_is_word_test(m: &syn::Meta) -> bool {
@@ -194,12 +227,130 @@ fn file_forbids_unsafe(f: &syn::File) -> bool {
         > 0
 }
 
-impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
+pub(crate) fn item_attrs(i: &Item) -> &[Attribute] {
+    match i {
+        Item::Const(i) => &i.attrs,
+        Item::Enum(i) => &i.attrs,
+        Item::ExternCrate(i) => &i.attrs,
+        Item::Fn(i) => &i.attrs,
+        Item::ForeignMod(i) => &i.attrs,
+        Item::Impl(i) => &i.attrs,
+        Item::Macro(i) => &i.attrs,
+        Item::Macro2(i) => &i.attrs,
+        Item::Mod(i) => &i.attrs,
+        Item::Static(i) => &i.attrs,
+        Item::Struct(i) => &i.attrs,
+        Item::Trait(i) => &i.attrs,
+        Item::TraitAlias(i) => &i.attrs,
+        Item::Type(i) => &i.attrs,
+        Item::Union(i) => &i.attrs,
+        Item::Use(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+fn impl_item_attrs(i: &ImplItem) -> &[Attribute] {
+    match i {
+        ImplItem::Const(i) => &i.attrs,
+        ImplItem::Method(i) => &i.attrs,
+        ImplItem::Type(i) => &i.attrs,
+        ImplItem::Macro(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+fn trait_item_attrs(i: &TraitItem) -> &[Attribute] {
+    match i {
+        TraitItem::Const(i) => &i.attrs,
+        TraitItem::Method(i) => &i.attrs,
+        TraitItem::Type(i) => &i.attrs,
+        TraitItem::Macro(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+fn expr_attrs(i: &Expr) -> &[Attribute] {
+    match i {
+        Expr::Array(i) => &i.attrs,
+        Expr::Assign(i) => &i.attrs,
+        Expr::AssignOp(i) => &i.attrs,
+        Expr::Async(i) => &i.attrs,
+        Expr::Await(i) => &i.attrs,
+        Expr::Binary(i) => &i.attrs,
+        Expr::Block(i) => &i.attrs,
+        Expr::Box(i) => &i.attrs,
+        Expr::Break(i) => &i.attrs,
+        Expr::Call(i) => &i.attrs,
+        Expr::Cast(i) => &i.attrs,
+        Expr::Closure(i) => &i.attrs,
+        Expr::Continue(i) => &i.attrs,
+        Expr::Field(i) => &i.attrs,
+        Expr::ForLoop(i) => &i.attrs,
+        Expr::Group(i) => &i.attrs,
+        Expr::If(i) => &i.attrs,
+        Expr::Index(i) => &i.attrs,
+        Expr::Let(i) => &i.attrs,
+        Expr::Lit(i) => &i.attrs,
+        Expr::Loop(i) => &i.attrs,
+        Expr::Macro(i) => &i.attrs,
+        Expr::Match(i) => &i.attrs,
+        Expr::MethodCall(i) => &i.attrs,
+        Expr::Paren(i) => &i.attrs,
+        Expr::Path(i) => &i.attrs,
+        Expr::Range(i) => &i.attrs,
+        Expr::Reference(i) => &i.attrs,
+        Expr::Repeat(i) => &i.attrs,
+        Expr::Return(i) => &i.attrs,
+        Expr::Struct(i) => &i.attrs,
+        Expr::Try(i) => &i.attrs,
+        Expr::TryBlock(i) => &i.attrs,
+        Expr::Tuple(i) => &i.attrs,
+        Expr::Type(i) => &i.attrs,
+        Expr::Unary(i) => &i.attrs,
+        Expr::Unsafe(i) => &i.attrs,
+        Expr::While(i) => &i.attrs,
+        Expr::Yield(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+impl<'ast, 'a> visit::Visit<'ast> for GeigerSynVisitor<'a> {
     fn visit_file(&mut self, i: &'ast syn::File) {
         self.metrics.forbids_unsafe = file_forbids_unsafe(i);
         syn::visit::visit_file(self, i);
     }
 
+    /// Skips items, including modules and impls, that are configured away.
+    fn visit_item(&mut self, i: &Item) {
+        if self.is_cfg_enabled(item_attrs(i)) {
+            visit::visit_item(self, i);
+        }
+    }
+
+    fn visit_impl_item(&mut self, i: &ImplItem) {
+        if self.is_cfg_enabled(impl_item_attrs(i)) {
+            visit::visit_impl_item(self, i);
+        }
+    }
+
+    fn visit_trait_item(&mut self, i: &TraitItem) {
+        if self.is_cfg_enabled(trait_item_attrs(i)) {
+            visit::visit_trait_item(self, i);
+        }
+    }
+
+    fn visit_local(&mut self, i: &Local) {
+        if self.is_cfg_enabled(&i.attrs) {
+            visit::visit_local(self, i);
+        }
+    }
+
+    fn visit_arm(&mut self, i: &Arm) {
+        if self.is_cfg_enabled(&i.attrs) {
+            visit::visit_arm(self, i);
+        }
+    }
+
     /// Free-standing functions
     fn visit_item_fn(&mut self, i: &ItemFn) {
         if IncludeTests::No == self.include_tests && is_test_fn(i) {
@@ -213,8 +364,11 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
             contains_unsafe_block: false,
         });
 
+        self.item_path.push(i.sig.ident.to_string());
         if i.sig.unsafety.is_some() {
             self.enter_unsafe_scope();
+            let span = i.sig.span();
+            self.add_finding(UnsafeKind::Function, span, span);
         }
         self.metrics
             .counters
@@ -224,6 +378,7 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
         if i.sig.unsafety.is_some() {
             self.exit_unsafe_scope();
         }
+        self.item_path.pop();
 
         // Pop the stat and add it to the metric
         let stat = self.function_stack.pop().unwrap();
@@ -235,6 +390,10 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
     }
 
     fn visit_expr(&mut self, i: &Expr) {
+        // Expression statements can be configured away as well
+        if !self.is_cfg_enabled(expr_attrs(i)) {
+            return;
+        }
         // Total number of expressions of any type
         match i {
             Expr::Unsafe(i) => {
@@ -257,36 +416,60 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
                 //     println!("{:#?}", other);
                 // }
                 self.metrics.counters.exprs.count(self.unsafe_scopes > 0);
+                if self.unsafe_scopes > 0 {
+                    let span = other.span();
+                    self.add_finding(UnsafeKind::Expression, span, span);
+                }
                 visit::visit_expr(self, other);
             }
         }
     }
 
     fn visit_item_mod(&mut self, i: &ItemMod) {
-        if IncludeTests::No == self.include_tests && is_test_mod(i) {
-            return;
-        }
+        self.item_path.push(i.ident.to_string());
         visit::visit_item_mod(self, i);
+        self.item_path.pop();
     }
 
     fn visit_item_impl(&mut self, i: &ItemImpl) {
+        self.item_path.push(impl_name(i));
         // unsafe trait impl's
+        if let Some(unsafety) = i.unsafety {
+            self.add_finding(
+                UnsafeKind::ItemImpl,
+                unsafety.span,
+                i.self_ty.span(),
+            );
+        }
         self.metrics.counters.item_impls.count(i.unsafety.is_some());
         visit::visit_item_impl(self, i);
+        self.item_path.pop();
     }
 
     fn visit_item_trait(&mut self, i: &ItemTrait) {
+        self.item_path.push(i.ident.to_string());
         // Unsafe traits
+        if let Some(unsafety) = i.unsafety {
+            self.add_finding(
+                UnsafeKind::ItemTrait,
+                unsafety.span,
+                i.ident.span(),
+            );
+        }
         self.metrics
             .counters
             .item_traits
             .count(i.unsafety.is_some());
         visit::visit_item_trait(self, i);
+        self.item_path.pop();
     }
 
     fn visit_impl_item_method(&mut self, i: &ImplItemMethod) {
+        self.item_path.push(i.sig.ident.to_string());
         if i.sig.unsafety.is_some() {
-            self.enter_unsafe_scope()
+            self.enter_unsafe_scope();
+            let span = i.sig.span();
+            self.add_finding(UnsafeKind::Method, span, span);
         }
         self.metrics
             .counters
@@ -296,29 +479,108 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
         if i.sig.unsafety.is_some() {
             self.exit_unsafe_scope()
         }
+        self.item_path.pop();
+    }
+
+    /// Macro invocations and `macro_rules!` definitions. The tokens are not
+    /// visited as regular syntax, any `unsafe` found inside them is reported
+    /// through the separate `macros` counter instead. This does not mark the
+    /// surrounding function as containing unsafe code.
+    fn visit_macro(&mut self, i: &Macro) {
+        let contains_unsafe = macro_contains_unsafe(&i.tokens);
+        if contains_unsafe {
+            let span = i.span();
+            self.add_finding(UnsafeKind::Macro, span, span);
+        }
+        self.metrics.counters.macros.count(contains_unsafe);
     }
 
-    // TODO: Visit macros.
-    //
     // TODO: Figure out if there are other visit methods that should be
     // implemented here.
 }
 
+/// Looks for `unsafe` inside the tokens passed to a macro.
+///
+/// The tokens are first parsed as a comma separated list of expressions, which
+/// covers most function-like macros, then as a list of items. If neither
+/// succeeds, e.g. for `macro_rules!` bodies or custom DSLs, this falls back to
+/// looking for the `unsafe` keyword anywhere in the token tree.
+fn macro_contains_unsafe(tokens: &TokenStream) -> bool {
+    use syn::parse::Parser;
+    use syn::punctuated::Punctuated;
+    use syn::visit::Visit;
+
+    let mut finder = UnsafeFinder::default();
+    let expr_parser = Punctuated::<Expr, syn::Token![,]>::parse_terminated;
+    if let Ok(exprs) = expr_parser.parse2(tokens.clone()) {
+        exprs.iter().for_each(|e| finder.visit_expr(e));
+        return finder.found;
+    }
+    if let Ok(file) = syn::parse2::<syn::File>(tokens.clone()) {
+        finder.visit_file(&file);
+        return finder.found;
+    }
+    tokens_contain_unsafe(tokens)
+}
+
+fn tokens_contain_unsafe(tokens: &TokenStream) -> bool {
+    tokens.clone().into_iter().any(|tt| match tt {
+        TokenTree::Ident(ident) => ident == "unsafe",
+        TokenTree::Group(group) => tokens_contain_unsafe(&group.stream()),
+        _ => false,
+    })
+}
+
+/// Checks parsed macro input for any kind of `unsafe` usage, including nested
+/// macro invocations.
+#[derive(Default)]
+struct UnsafeFinder {
+    found: bool,
+}
+
+impl<'ast> visit::Visit<'ast> for UnsafeFinder {
+    fn visit_expr_unsafe(&mut self, _: &syn::ExprUnsafe) {
+        self.found = true;
+    }
+
+    fn visit_signature(&mut self, i: &syn::Signature) {
+        self.found |= i.unsafety.is_some();
+        visit::visit_signature(self, i);
+    }
+
+    fn visit_item_impl(&mut self, i: &ItemImpl) {
+        self.found |= i.unsafety.is_some();
+        visit::visit_item_impl(self, i);
+    }
+
+    fn visit_item_trait(&mut self, i: &ItemTrait) {
+        self.found |= i.unsafety.is_some();
+        visit::visit_item_trait(self, i);
+    }
+
+    fn visit_macro(&mut self, i: &Macro) {
+        self.found |= macro_contains_unsafe(&i.tokens);
+    }
+}
+
 pub fn find_unsafe_in_string(
     src: &str,
     include_tests: IncludeTests,
+    cfgs: &ActiveCfgs,
 ) -> Result<RsFileMetrics, syn::Error> {
     use syn::visit::Visit;
     let syntax = syn::parse_file(&src)?;
-    let mut vis = GeigerSynVisitor::new(include_tests);
+    let mut vis = GeigerSynVisitor::new(include_tests, cfgs);
     vis.visit_file(&syntax);
     Ok(vis.metrics)
 }
 
-/// Scan a single file for `unsafe` usage.
+/// Scan a single file for `unsafe` usage, skipping code that is configured
+/// away by `#[cfg]` attributes evaluated against `cfgs`.
 pub fn find_unsafe_in_file(
     p: &Path,
     include_tests: IncludeTests,
+    cfgs: &ActiveCfgs,
 ) -> Result<RsFileMetrics, ScanFileError> {
     let mut file =
         File::open(p).map_err(|e| ScanFileError::Io(e, p.to_path_buf()))?;
@@ -327,6 +589,77 @@ pub fn find_unsafe_in_file(
         .map_err(|e| ScanFileError::Io(e, p.to_path_buf()))?;
     let src = String::from_utf8(src)
         .map_err(|e| ScanFileError::Utf8(e, p.to_path_buf()))?;
-    find_unsafe_in_string(&src, include_tests)
+    find_unsafe_in_string(&src, include_tests, cfgs)
         .map_err(|e| ScanFileError::Syn(e, p.to_path_buf()))
 }
+
+#[cfg(test)]
+mod lib_tests {
+    use super::*;
+
+    use rstest::*;
+    use std::str::FromStr;
+
+    #[rstest(
+        input_tokens,
+        expected_contains_unsafe,
+        // Parsed as expressions
+        case("unsafe { f() }, 1", true),
+        case("x, y + 1", false),
+        case("g(inner!(unsafe { f() }))", true),
+        // Parsed as items
+        case("unsafe impl Send for X {}", true),
+        case("unsafe trait T {}", true),
+        case("struct X; unsafe fn f() {}", true),
+        case("struct X; fn f() {}", false),
+        // Neither, the tokens are searched for the keyword
+        case("unsafe => f()", true),
+        case("{ x } => y", false),
+        // A `macro_rules!` body
+        case("() => { unsafe { f() } }", true),
+        case("($x:expr) => { $x + 1 }", false)
+    )]
+    fn macro_contains_unsafe_test(
+        input_tokens: &str,
+        expected_contains_unsafe: bool,
+    ) {
+        let tokens = TokenStream::from_str(input_tokens).unwrap();
+        assert_eq!(macro_contains_unsafe(&tokens), expected_contains_unsafe);
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_macros_test() {
+        let src = r#"
+            macro_rules! read {
+                ($p:expr) => { unsafe { *$p } };
+            }
+
+            fn f(p: *const u8) -> Vec<u8> {
+                vec![unsafe { *p }, 1]
+            }
+
+            fn g() -> Vec<u8> {
+                vec![1, 2]
+            }
+        "#;
+        let metrics = find_unsafe_in_string(
+            src,
+            IncludeTests::No,
+            &ActiveCfgs::new(None, None),
+        )
+        .unwrap();
+
+        assert_eq!(metrics.counters.macros.safe, 1);
+        assert_eq!(metrics.counters.macros.unsafe_, 2);
+        assert!(metrics.counters.has_unsafe());
+        assert!(metrics.contains_unsafe_functions.is_empty());
+        assert_eq!(
+            metrics
+                .unsafe_findings
+                .iter()
+                .filter(|finding| finding.kind == UnsafeKind::Macro)
+                .count(),
+            2
+        );
+    }
+}
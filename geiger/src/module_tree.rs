@@ -0,0 +1,346 @@
+//! Discovery of the source files that make up a crate, without building it.
+
+use crate::{item_attrs, ActiveCfgs, IncludeTests, ScanFileError};
+
+use proc_macro2::{TokenStream, TokenTree};
+use std::collections::HashSet;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use syn::ext::IdentExt;
+use syn::{visit, Item, ItemMod, Lit, Macro, Meta};
+
+/// Finds the `.rs` files reachable from a crate entry point, e.g. `src/lib.rs`,
+/// by following `mod foo;` declarations, `#[path = "..."]` attributes and
+/// `include!` invocations with a string literal path.
+///
+/// Module declarations behind a `#[cfg]` that evaluates to false for `cfgs`
+/// are not followed. Module declarations found inside other macro invocations,
+/// like `cfg_if!`, are followed without evaluating any cfg. Modules generated
+/// by build scripts or procedural macros cannot be found this way. Files
+/// that do not parse as items are only searched for `mod foo;` tokens.
+///
+/// The returned paths are canonicalized and include the entry point itself.
+/// Declared modules whose file does not exist are skipped.
+pub fn find_module_files(
+    entry_point: &Path,
+    include_tests: IncludeTests,
+    cfgs: &ActiveCfgs,
+) -> Result<HashSet<PathBuf>, ScanFileError> {
+    let mut visitor = ModuleTreeVisitor {
+        cfgs,
+        include_tests,
+        file_dir: PathBuf::new(),
+        mod_dir: PathBuf::new(),
+        inline_depth: 0,
+        files: HashSet::new(),
+        pending: Vec::new(),
+    };
+    // The entry point owns its directory, just like a `mod.rs` file.
+    if let Some(parent) = entry_point.parent() {
+        visitor.add_file(entry_point, parent.to_path_buf());
+    }
+    while let Some((path, mod_dir)) = visitor.pending.pop() {
+        visitor.visit_path(&path, mod_dir)?;
+    }
+    Ok(visitor.files)
+}
+
+struct ModuleTreeVisitor<'a> {
+    cfgs: &'a ActiveCfgs,
+
+    include_tests: IncludeTests,
+
+    /// Directory of the file being visited, `#[path]` attributes outside of
+    /// inline modules and `include!` paths are relative to it.
+    file_dir: PathBuf,
+
+    /// Directory where child modules of the module being visited are
+    /// looked up.
+    mod_dir: PathBuf,
+
+    /// The number of inline `mod foo { ... }` blocks the visitor is in.
+    inline_depth: u32,
+
+    /// Canonicalized paths of every file found so far.
+    files: HashSet<PathBuf>,
+
+    /// Files that are found but not visited yet, together with the directory
+    /// of their child modules.
+    pending: Vec<(PathBuf, PathBuf)>,
+}
+
+impl<'a> ModuleTreeVisitor<'a> {
+    fn add_file(&mut self, path: &Path, mod_dir: PathBuf) {
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        if self.files.insert(path.clone()) {
+            self.pending.push((path, mod_dir));
+        }
+    }
+
+    fn visit_path(
+        &mut self,
+        path: &Path,
+        mod_dir: PathBuf,
+    ) -> Result<(), ScanFileError> {
+        use syn::visit::Visit;
+
+        let src = fs::read_to_string(path)
+            .map_err(|e| ScanFileError::Io(e, path.to_path_buf()))?;
+        self.file_dir =
+            path.parent().map(Path::to_path_buf).unwrap_or_default();
+        self.mod_dir = mod_dir;
+        self.inline_depth = 0;
+        match syn::parse_file(&src) {
+            Ok(file) => self.visit_file(&file),
+            // Files that syn cannot parse as items, e.g. older edition code
+            // or files included as an expression, are searched for
+            // `mod foo;` tokens instead.
+            Err(e) => match src.parse::<TokenStream>() {
+                Ok(tokens) => self.add_modules_in_tokens(tokens),
+                Err(_) => {
+                    return Err(ScanFileError::Syn(e, path.to_path_buf()))
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn path_attr(&self, i: &ItemMod) -> Option<String> {
+        self.cfgs
+            .effective_attrs(&i.attrs, self.include_tests)
+            .into_iter()
+            .find_map(|meta| match meta {
+                Meta::NameValue(name_value)
+                    if name_value.path.is_ident("path") =>
+                {
+                    match name_value.lit {
+                        Lit::Str(lit_str) => Some(lit_str.value()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+    }
+
+    /// Adds the file of `mod name;` declared without a `#[path]` attribute.
+    fn add_module_named(&mut self, name: &str) {
+        let child_dir = self.mod_dir.join(name);
+        let non_mod_rs = self.mod_dir.join(format!("{}.rs", name));
+        if non_mod_rs.exists() {
+            self.add_file(&non_mod_rs, child_dir);
+        } else {
+            self.add_file(&child_dir.join("mod.rs"), child_dir.clone());
+        }
+    }
+
+    /// Follows `mod foo;` token sequences in macro input that could not be
+    /// visited as regular syntax.
+    fn add_modules_in_tokens(&mut self, tokens: TokenStream) {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        for name in tokens.windows(3).filter_map(module_declaration_name) {
+            self.add_module_named(&name);
+        }
+        for token in tokens {
+            if let TokenTree::Group(group) = token {
+                self.add_modules_in_tokens(group.stream());
+            }
+        }
+    }
+}
+
+/// Returns `foo` for the tokens `mod foo;`.
+fn module_declaration_name(tokens: &[TokenTree]) -> Option<String> {
+    match tokens {
+        [TokenTree::Ident(kw), TokenTree::Ident(name), TokenTree::Punct(p)]
+            if kw == "mod" && p.as_char() == ';' =>
+        {
+            Some(name.unraw().to_string())
+        }
+        _ => None,
+    }
+}
+
+impl<'ast, 'a> visit::Visit<'ast> for ModuleTreeVisitor<'a> {
+    fn visit_item(&mut self, i: &Item) {
+        if self.cfgs.is_enabled(item_attrs(i), self.include_tests) {
+            visit::visit_item(self, i);
+        }
+    }
+
+    fn visit_item_mod(&mut self, i: &ItemMod) {
+        let name = i.ident.unraw().to_string();
+        let path_attr = self.path_attr(i);
+        match &i.content {
+            Some((_, items)) => {
+                let child_dir = match path_attr {
+                    Some(path) => self.mod_dir.join(path),
+                    None => self.mod_dir.join(&name),
+                };
+                let parent_dir = mem::replace(&mut self.mod_dir, child_dir);
+                self.inline_depth += 1;
+                for item in items {
+                    self.visit_item(item);
+                }
+                self.inline_depth -= 1;
+                self.mod_dir = parent_dir;
+            }
+            None => match path_attr {
+                Some(path) => {
+                    let path = if self.inline_depth > 0 {
+                        self.mod_dir.join(path)
+                    } else {
+                        self.file_dir.join(path)
+                    };
+                    // A module loaded through `#[path]` owns its directory.
+                    let child_dir = path
+                        .parent()
+                        .map(Path::to_path_buf)
+                        .unwrap_or_default();
+                    self.add_file(&path, child_dir);
+                }
+                None => self.add_module_named(&name),
+            },
+        }
+    }
+
+    fn visit_macro(&mut self, i: &Macro) {
+        let is_include = matches!(
+            i.path.segments.last(),
+            Some(segment) if segment.ident == "include"
+        );
+        if is_include {
+            if let Ok(lit_str) = syn::parse2::<syn::LitStr>(i.tokens.clone()) {
+                let path = self.file_dir.join(lit_str.value());
+                // Items in the included file belong to the current module.
+                let mod_dir = self.mod_dir.clone();
+                self.add_file(&path, mod_dir);
+            }
+            return;
+        }
+        self.add_modules_in_tokens(i.tokens.clone());
+    }
+}
+
+#[cfg(test)]
+mod module_tree_tests {
+    use super::*;
+
+    use cargo_platform::Cfg;
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    #[rstest]
+    fn find_module_files_test_path_attribute() {
+        let temp_dir = create_crate(&[
+            ("src/lib.rs", "#[path = \"other/renamed.rs\"] mod foo;"),
+            ("src/other/renamed.rs", "mod bar;"),
+            ("src/other/bar.rs", ""),
+            ("src/foo.rs", ""),
+        ]);
+
+        let module_files = find_module_files(
+            &temp_dir.path().join("src/lib.rs"),
+            IncludeTests::No,
+            &ActiveCfgs::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            module_files,
+            canonical_paths(
+                &temp_dir,
+                &["src/lib.rs", "src/other/renamed.rs", "src/other/bar.rs"]
+            )
+        );
+    }
+
+    #[rstest]
+    fn find_module_files_test_include() {
+        let temp_dir = create_crate(&[
+            ("src/lib.rs", "include!(\"generated.rs\");"),
+            ("src/generated.rs", "mod child;"),
+            ("src/child.rs", ""),
+        ]);
+
+        let module_files = find_module_files(
+            &temp_dir.path().join("src/lib.rs"),
+            IncludeTests::No,
+            &ActiveCfgs::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            module_files,
+            canonical_paths(
+                &temp_dir,
+                &["src/lib.rs", "src/generated.rs", "src/child.rs"]
+            )
+        );
+    }
+
+    #[rstest(
+        input_include_tests,
+        expected_file_names,
+        case(IncludeTests::No, vec!["src/lib.rs", "src/unix.rs"]),
+        case(
+            IncludeTests::Yes,
+            vec!["src/lib.rs", "src/unix.rs", "src/tests.rs"]
+        )
+    )]
+    fn find_module_files_test_cfg(
+        input_include_tests: IncludeTests,
+        expected_file_names: Vec<&str>,
+    ) {
+        let temp_dir = create_crate(&[
+            (
+                "src/lib.rs",
+                "#[cfg(windows)] mod windows; \
+                 #[cfg(unix)] mod unix; \
+                 #[cfg(test)] mod tests;",
+            ),
+            ("src/windows.rs", ""),
+            ("src/unix.rs", ""),
+            ("src/tests.rs", ""),
+        ]);
+        let active_cfgs =
+            ActiveCfgs::new(Some(vec![Cfg::Name(String::from("unix"))]), None);
+
+        let module_files = find_module_files(
+            &temp_dir.path().join("src/lib.rs"),
+            input_include_tests,
+            &active_cfgs,
+        )
+        .unwrap();
+
+        assert_eq!(
+            module_files,
+            canonical_paths(&temp_dir, &expected_file_names)
+        );
+    }
+
+    fn create_crate(files: &[(&str, &str)]) -> TempDir {
+        let temp_dir = tempdir().unwrap();
+        for (file_name, content) in files {
+            let path = temp_dir.path().join(file_name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        temp_dir
+    }
+
+    fn canonical_paths(
+        temp_dir: &TempDir,
+        file_names: &[&str],
+    ) -> HashSet<PathBuf> {
+        file_names
+            .iter()
+            .map(|file_name| {
+                temp_dir.path().join(file_name).canonicalize().unwrap()
+            })
+            .collect()
+    }
+}